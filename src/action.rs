@@ -5,10 +5,11 @@
 #![deny(clippy::all)]
 #![deny(clippy::missing_docs_in_private_items)]
 
-use crate::{flip, ActionParams, Brain};
+use crate::{flip, rng, ActError, ActionParams, Brain, ExecutionContext, Register};
 use rand::seq::SliceRandom;
+use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 /// Structure to represent the action.
 pub struct Action {
     /// The id of the action
@@ -42,6 +43,11 @@ impl Action {
         self.id
     }
 
+    /// Function to set the id of the action (e.g. when the action is imported in a brain).
+    pub(crate) fn set_id(&mut self, id: i32) {
+        self.id = id;
+    }
+
     /// Function to determine if the action is atomic.
     /// The action is atomic if they are not associate to a team.
     pub fn is_atomic(&self) -> bool {
@@ -53,23 +59,40 @@ impl Action {
         self.action_team
     }
 
+    /// Function to set the team associate to the action (None to make the action atomic)
+    pub fn set_action_team(&mut self, action_team: Option<usize>) {
+        self.action_team = action_team;
+    }
+
+    /// Function to get the action code
+    pub fn get_action_code(&self) -> i32 {
+        self.action_code
+    }
+
     /// Function to get the action
     /// If the action is atomic
     ///     |-> Then return the action code
     /// If the action is not atomic
     ///     |-> Execute the team associate to the team
-    pub fn get_action(&self, brain: &Brain, state: &Vec<i32>, visited: &mut Vec<i32>) -> i32 {
+    pub fn get_action<T: Register>(
+        &self,
+        brain: &Brain<T>,
+        state: &[T],
+        context: &mut ExecutionContext<T>,
+    ) -> Result<i32, ActError> {
         if let Some(idx_team) = self.action_team {
-            brain.teams[idx_team].act(brain, state, visited)
+            let team = brain.teams.get(idx_team).ok_or(ActError::UnknownTeam(idx_team))?;
+            context.team = idx_team;
+            team.act(brain, state, context)
         } else {
-            self.action_code
+            Ok(self.action_code)
         }
     }
 
     /// Function to mutate the action
-    pub fn mutate(
+    pub fn mutate<T: Register>(
         &mut self,
-        brain: &Brain,
+        brain: &Brain<T>,
         parent_team: usize,
         teams: &Vec<usize>,
         p_act_atom: f64,
@@ -100,7 +123,7 @@ impl Action {
                 team_mutate.remove_learner(learner_id.try_into().unwrap());
             }
 
-            let new_action_code = options.choose(&mut rand::thread_rng()).unwrap();
+            let new_action_code = options.choose(&mut rng()).unwrap();
             self.action_code = *new_action_code;
             self.action_team = None;
         }
@@ -120,7 +143,7 @@ impl Action {
                     old_team_id = Some(brain.teams[self.action_team.unwrap()].get_id());
                 }
 
-                let new_action_team = selection_pool.choose(&mut rand::thread_rng()).unwrap();
+                let new_action_team = selection_pool.choose(&mut rng()).unwrap();
                 self.action_team = Some(*new_action_team);
 
                 let new_team_id: i32 = brain.teams[self.action_team.unwrap()].get_id();
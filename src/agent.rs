@@ -5,20 +5,28 @@
 #![deny(clippy::all)]
 #![deny(clippy::missing_docs_in_private_items)]
 
-use crate::brain::Brain;
+use crate::{ActError, Brain, DecisionTrace, ExecutionContext, Register};
 
 /// Structure to represent the agent
-pub struct Agent {
+/// The agent keep its own execution context (the memory and the persistent registers),
+/// so the agents acting on the same brain don't share their state.
+pub struct Agent<T: Register> {
     /// The team associate to the agent
     team: usize,
+    /// The execution context of the agent.
+    context: ExecutionContext<T>,
 }
 
-impl Agent {
+impl<T: Register> Agent<T> {
     /// Constructor of the agent
-    pub fn new(team: usize) -> Self {
-        Agent {
-            team,
-        }
+    /// (with a new execution context, the memory has the size of the memory of the brain)
+    pub fn new(team: usize, brain: &Brain<T>) -> Self {
+        Agent::with_context(team, brain.new_context())
+    }
+
+    /// Constructor of the agent with the execution context (e.g. a context saved before).
+    pub fn with_context(team: usize, context: ExecutionContext<T>) -> Self {
+        Agent { team, context }
     }
 
     /// Function to get the index of team
@@ -26,30 +34,133 @@ impl Agent {
         self.team
     }
 
+    /// Function to get the execution context of the agent.
+    pub fn get_context(&self) -> &ExecutionContext<T> {
+        &self.context
+    }
+
+    /// Function to get the mutable execution context of the agent
+    /// (e.g. to remap the registers after a garbage collection).
+    pub fn get_context_mut(&mut self) -> &mut ExecutionContext<T> {
+        &mut self.context
+    }
+
     /// Function to act the agent
-    pub fn act(&mut self, brain: &mut Brain, state: &Vec<i32>) -> i32 {
-        let mut visited: Vec<i32> = Vec::new();
-        let mut list_teams = brain.teams.to_vec();
-        let team = list_teams.get_mut(self.team).unwrap();
-        team.act(brain, state, &mut visited)
+    /// The agent use its own execution context, the brain is only read.
+    /// The programs can read and write the memory of the execution context.
+    /// The learners with persistent registers keep the registers until `zero_registers`.
+    /// Return an error if a team of the path has no learner (see `Team::act`).
+    pub fn act(&mut self, brain: &Brain<T>, state: &[T]) -> Result<i32, ActError> {
+        let team = self.team;
+        act_team(team, brain, state, &mut self.context)
+    }
+
+    /// Function to act the agent with an other execution context.
+    /// The brain is only read, so only the teams and the learners visited are used.
+    pub fn act_with_context(
+        &self,
+        brain: &Brain<T>,
+        state: &[T],
+        context: &mut ExecutionContext<T>,
+    ) -> Result<i32, ActError> {
+        act_team(self.team, brain, state, context)
+    }
+
+    /// Function to act the agent and get the path of the decision
+    /// (the teams visited, the bids of the learners and the action).
+    /// The agent use its own execution context (as `act`).
+    pub fn act_with_trace(
+        &mut self,
+        brain: &Brain<T>,
+        state: &[T],
+    ) -> Result<DecisionTrace<T>, ActError> {
+        let team = self.team;
+        trace_team(team, brain, state, &mut self.context)
+    }
+
+    /// Function to act the agent with an other execution context and get the path of the decision.
+    pub fn act_with_context_and_trace(
+        &self,
+        brain: &Brain<T>,
+        state: &[T],
+        context: &mut ExecutionContext<T>,
+    ) -> Result<DecisionTrace<T>, ActError> {
+        trace_team(self.team, brain, state, context)
     }
 
     /// Function to set the reward into the team
-    pub fn reward(&mut self, brain: &mut Brain, score: i32, task: String) {
+    pub fn reward(&mut self, brain: &mut Brain<T>, score: i32, task: String) {
         let team = brain.teams.get_mut(self.team).unwrap();
         team.set_outcomes(task, score);
     }
 
     /// Function to check if the task is done
-    pub fn task_done(&self, brain: &Brain, task: String) -> bool {
+    pub fn task_done(&self, brain: &Brain<T>, task: String) -> bool {
         let team = brain.teams.get(self.team).unwrap();
         team.task_done(task)
     }
 
     /// Function to reset the register to zero
-    pub fn zero_registers(&mut self, brain: &Brain) {
-        let mut list_teams = brain.teams.to_vec();
-        let team = list_teams.get_mut(self.team).unwrap();
-        team.zero_registers(brain);
+    /// (the registers kept by the execution context of the agent)
+    pub fn zero_registers(&mut self) {
+        self.context.zero_registers();
     }
+
+    /// Function to get the registers of the learners reachable from the team of the agent.
+    /// Return the index of the learner and the registers
+    /// (kept by the execution context of the agent, else the registers of the learner).
+    pub fn get_registers<'a>(&'a self, brain: &'a Brain<T>) -> Vec<(usize, &'a [T])> {
+        let mut registers: Vec<(usize, &'a [T])> = Vec::new();
+        for team_idx in brain.get_reachable_teams(self.team) {
+            for learner_idx in brain.teams[team_idx].get_learners() {
+                if registers.iter().all(|(idx, _)| *idx != learner_idx) {
+                    let learner_registers = self
+                        .context
+                        .get_registers(learner_idx)
+                        .unwrap_or(brain.learners[learner_idx].get_registers());
+                    registers.push((learner_idx, learner_registers));
+                }
+            }
+        }
+        registers
+    }
+
+    /// Function to reset the memory to zero (e.g. at the start of an episode)
+    pub fn reset_memory(&mut self) {
+        self.context.reset_memory();
+    }
+
+    /// Function to get the values of the memory
+    pub fn get_memory(&self) -> &[T] {
+        self.context.get_memory().get_values()
+    }
+}
+
+/// Function to act from the team with the execution context.
+fn act_team<T: Register>(
+    team: usize,
+    brain: &Brain<T>,
+    state: &[T],
+    context: &mut ExecutionContext<T>,
+) -> Result<i32, ActError> {
+    context.visited.clear();
+    let root = brain.teams.get(team).ok_or(ActError::UnknownTeam(team))?;
+    context.team = team;
+    root.act(brain, state, context)
+}
+
+/// Function to act from the team with the execution context and get the path of the decision.
+fn trace_team<T: Register>(
+    team: usize,
+    brain: &Brain<T>,
+    state: &[T],
+    context: &mut ExecutionContext<T>,
+) -> Result<DecisionTrace<T>, ActError> {
+    context.trace = Some(Vec::new());
+    let action = act_team(team, brain, state, context);
+    let teams = context.trace.take().unwrap_or_default();
+    Ok(DecisionTrace {
+        teams,
+        action: action?,
+    })
 }
@@ -0,0 +1,435 @@
+//! # Assembly
+//! Crate that permit to read and write the programs of the Tangled Program Graph (TPG) as text.
+//! Each line of the text is one instruction:
+//!     r2 = r2 - in[3]        |-> binary operation with a symbol (add, sub, mul, div)
+//!     r0 = max(r0, r1)       |-> binary operation without symbol
+//!     r1 = double(r1)        |-> unary operation (the source is not read)
+//!     r1 = double(r1, in[2]) |-> unary operation with the source kept (not read, but the
+//!                                instruction is skipped with an empty input or memory)
+//!     r0 = r0 * mem[2]       |-> read the memory
+//!     mem[1] = mem[1] + r0   |-> write the memory (the source is a register)
+//! The register are written `r<index>`, the input `in[<index>]` and the memory `mem[<index>]`.
+//! The text after `#` is a comment and the empty lines are ignored.
+
+#![deny(missing_docs)]
+#![deny(clippy::all)]
+#![deny(clippy::missing_docs_in_private_items)]
+
+use crate::{Instruction, OperationSet, Program, ProgramParams, Register};
+use std::fmt;
+
+/// Structure to represent an error while reading a program from text.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AssemblyError {
+    /// The number of the line with the error (start at 1).
+    line: usize,
+    /// The description of the error.
+    message: String,
+}
+
+impl AssemblyError {
+    /// Constructor of the error.
+    fn new(line: usize, message: String) -> Self {
+        AssemblyError { line, message }
+    }
+
+    /// Function to get the number of the line with the error.
+    pub fn get_line(&self) -> usize {
+        self.line
+    }
+
+    /// Function to get the description of the error.
+    pub fn get_message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl fmt::Display for AssemblyError {
+    /// Function to display the error with the line number.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for AssemblyError {}
+
+/// Function to get the symbol of the operation written between the operands.
+fn get_symbol(name: &str) -> Option<&'static str> {
+    match name {
+        "add" => Some("+"),
+        "sub" => Some("-"),
+        "mul" => Some("*"),
+        "div" => Some("/"),
+        _ => None,
+    }
+}
+
+/// Function to get the name of the operation from the symbol.
+fn get_name_from_symbol(symbol: &str) -> Option<&'static str> {
+    match symbol {
+        "+" => Some("add"),
+        "-" => Some("sub"),
+        "*" => Some("mul"),
+        "/" => Some("div"),
+        _ => None,
+    }
+}
+
+/// Function to write the source of the instruction.
+fn source_to_assembly(instruction: &Instruction) -> String {
+    match instruction.get_mode() {
+        0 | 3 => format!("r{}", instruction.get_src()),
+        2 => format!("mem[{}]", instruction.get_src()),
+        _ => format!("in[{}]", instruction.get_src()),
+    }
+}
+
+/// Function to write one instruction.
+fn instruction_to_assembly<T: Register>(
+    instruction: &Instruction,
+    operations: &OperationSet<T>,
+) -> String {
+    let dst = if instruction.get_mode() == 3 {
+        format!("mem[{}]", instruction.get_dst())
+    } else {
+        format!("r{}", instruction.get_dst())
+    };
+    let src = source_to_assembly(instruction);
+
+    match operations.get(instruction.get_op()) {
+        // The source of the unary operation is only written if it's not the destination
+        // (the mode and the index of the source are kept by the text)
+        Some(operation) if operation.arity() < 2 => {
+            let mode = instruction.get_mode();
+            if (mode == 0 || mode == 3) && instruction.get_src() == instruction.get_dst() {
+                format!("{} = {}({})", dst, operation.name(), dst)
+            } else {
+                format!("{} = {}({}, {})", dst, operation.name(), dst, src)
+            }
+        }
+        Some(operation) => match get_symbol(operation.name()) {
+            Some(symbol) => format!("{} = {} {} {}", dst, dst, symbol, src),
+            None => format!("{} = {}({}, {})", dst, operation.name(), dst, src),
+        },
+        // The unknown operation is written with the code
+        None => format!("{} = op{}({}, {})", dst, instruction.get_op(), dst, src),
+    }
+}
+
+/// Function to read a register (`r<index>`).
+fn parse_register(token: &str, line: usize) -> Result<i32, AssemblyError> {
+    token
+        .strip_prefix('r')
+        .and_then(|index| index.parse::<i32>().ok())
+        .ok_or_else(|| AssemblyError::new(line, format!("invalid register '{}'", token)))
+}
+
+/// Function to read an index between brackets (`<prefix>[<index>]`).
+/// Return None if the token doesn't start with the prefix.
+fn parse_indexed(
+    token: &str,
+    prefix: &str,
+    kind: &str,
+    line: usize,
+) -> Option<Result<i32, AssemblyError>> {
+    let index = token.strip_prefix(prefix)?.strip_prefix('[')?;
+    Some(
+        index
+            .strip_suffix(']')
+            .and_then(|index| index.parse::<i32>().ok())
+            .ok_or_else(|| AssemblyError::new(line, format!("invalid {} '{}'", kind, token))),
+    )
+}
+
+/// Function to read a destination (`r<index>` or `mem[<index>]`).
+/// Return true if the destination is the memory and the index of the destination.
+fn parse_destination(token: &str, line: usize) -> Result<(bool, i32), AssemblyError> {
+    match parse_indexed(token, "mem", "memory", line) {
+        Some(index) => Ok((true, index?)),
+        None => Ok((false, parse_register(token, line)?)),
+    }
+}
+
+/// Function to read a source (`r<index>`, `in[<index>]` or `mem[<index>]`).
+/// Return the mode and the index of the source.
+fn parse_source(token: &str, line: usize) -> Result<(i32, i32), AssemblyError> {
+    if let Some(index) = parse_indexed(token, "in", "input", line) {
+        Ok((1, index?))
+    } else if let Some(index) = parse_indexed(token, "mem", "memory", line) {
+        Ok((2, index?))
+    } else if token.starts_with('r') {
+        Ok((0, parse_register(token, line)?))
+    } else {
+        Err(AssemblyError::new(line, format!("invalid source '{}'", token)))
+    }
+}
+
+/// Function to get the code of the operation from the name.
+/// The name `op<code>` is accepted for the operations that are not in the operation set.
+fn parse_operation<T: Register>(
+    name: &str,
+    operations: &OperationSet<T>,
+    line: usize,
+) -> Result<i32, AssemblyError> {
+    if let Some(code) = operations.get_code(name) {
+        return Ok(code);
+    }
+    name.strip_prefix("op")
+        .and_then(|code| code.parse::<i32>().ok())
+        .ok_or_else(|| AssemblyError::new(line, format!("unknown operation '{}'", name)))
+}
+
+/// Function to read one instruction.
+fn parse_instruction<T: Register>(
+    text: &str,
+    operations: &OperationSet<T>,
+    line: usize,
+) -> Result<Instruction, AssemblyError> {
+    let (lhs, rhs) = text
+        .split_once('=')
+        .ok_or_else(|| AssemblyError::new(line, "missing '='".to_string()))?;
+    let (to_memory, dst) = parse_destination(lhs.trim(), line)?;
+    let rhs = rhs.trim();
+
+    // List of operands and the code of the operation
+    let (op, operands): (i32, Vec<&str>) = if let Some((name, args)) = rhs.split_once('(') {
+        let args = args
+            .strip_suffix(')')
+            .ok_or_else(|| AssemblyError::new(line, "missing ')'".to_string()))?;
+        let op = parse_operation(name.trim(), operations, line)?;
+        (op, args.split(',').map(|arg| arg.trim()).collect())
+    } else {
+        let tokens: Vec<&str> = rhs.split_whitespace().collect();
+        if tokens.len() != 3 {
+            return Err(AssemblyError::new(
+                line,
+                format!("expected 'x <op> y' but found '{}'", rhs),
+            ));
+        }
+        let name = get_name_from_symbol(tokens[1])
+            .ok_or_else(|| AssemblyError::new(line, format!("unknown symbol '{}'", tokens[1])))?;
+        let op = parse_operation(name, operations, line)?;
+        (op, vec![tokens[0], tokens[2]])
+    };
+
+    // The first operand is always the destination
+    if parse_destination(operands[0], line)? != (to_memory, dst) {
+        return Err(AssemblyError::new(
+            line,
+            format!("the first operand '{}' is not the destination", operands[0]),
+        ));
+    }
+
+    // The source of the unary operation is optional
+    let arity = operations.get(op).map_or(2, |operation| operation.arity());
+    let expected = if arity < 2 { "1 or 2" } else { "2" };
+    if operands.len() > 2 || (arity >= 2 && operands.len() != 2) {
+        return Err(AssemblyError::new(
+            line,
+            format!("expected {} operand(s) but found {}", expected, operands.len()),
+        ));
+    }
+
+    // The source of the unary operation is not read, use the destination if not written
+    let (mode, src) = if operands.len() == 1 {
+        (if to_memory { 3 } else { 0 }, dst)
+    } else {
+        parse_source(operands[1], line)?
+    };
+
+    // The write of the memory only take the source from the register
+    let mode = match (to_memory, mode) {
+        (false, mode) => mode,
+        (true, 0) | (true, 3) => 3,
+        _ => {
+            return Err(AssemblyError::new(
+                line,
+                format!("the source '{}' of the memory is not a register", operands[1]),
+            ))
+        }
+    };
+
+    Ok(Instruction::new(mode, op, src, dst))
+}
+
+impl Program {
+    /// Function to write the program as text (one instruction per line).
+    pub fn to_assembly<T: Register>(&self, operations: &OperationSet<T>) -> String {
+        let mut text = String::new();
+        for instruction in self.get_instructions() {
+            text.push_str(&instruction_to_assembly(instruction, operations));
+            text.push('\n');
+        }
+        text
+    }
+
+    /// Function to read a program from text.
+    /// The program get a new id and the arithmetic from the params.
+    pub fn from_assembly<T: Register>(
+        text: &str,
+        operations: &OperationSet<T>,
+        init_params: &mut ProgramParams,
+    ) -> Result<Program, AssemblyError> {
+        let mut instructions: Vec<Instruction> = Vec::new();
+        for (i, line) in text.lines().enumerate() {
+            let code = line.split('#').next().unwrap_or("").trim();
+            if !code.is_empty() {
+                instructions.push(parse_instruction(code, operations, i + 1)?);
+            }
+        }
+        Ok(Program::new(Some(instructions), init_params, operations))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ArithmeticPolicy, CrossoverType, Maximum, Memory};
+
+    /// Function to get the params used by the tests.
+    fn get_params() -> ProgramParams {
+        ProgramParams {
+            id_counter_program: 0,
+            max_program_length: 10,
+            input_size: 5,
+            nb_destinations: 5,
+            p_inst_del: 0.0,
+            p_inst_mut: 0.0,
+            p_inst_swap: 0.0,
+            p_inst_add: 0.0,
+            arithmetic: ArithmeticPolicy::Wrapping,
+            crossover: CrossoverType::TwoPoint,
+            memory_size: 0,
+            p_memory_write: 0.0,
+        }
+    }
+
+    #[test]
+    fn test_assembly_program() {
+        let mut params = get_params();
+        let mut operations: OperationSet<i32> = OperationSet::default();
+        operations.add_operation(Box::new(Maximum));
+
+        let text = "\
+# Hand-crafted learner
+r2 = r2 - in[3]
+r1 = double(r1)
+
+r0 = max(r0, r2)  # bid
+r0 = neg_if_less(r0, in[0])
+";
+        let program = Program::from_assembly(text, &operations, &mut params).unwrap();
+
+        // Check the instructions read from the text
+        assert_eq!(
+            program.get_instructions(),
+            &[
+                Instruction::new(1, 1, 3, 2),
+                Instruction::new(0, 2, 1, 1),
+                Instruction::new(0, 7, 2, 0),
+                Instruction::new(1, 4, 0, 0),
+            ],
+            "The instructions read from the text are incorrect"
+        );
+
+        // Check the text written from the program
+        assert_eq!(
+            program.to_assembly(&operations),
+            "r2 = r2 - in[3]\nr1 = double(r1)\nr0 = max(r0, r2)\nr0 = neg_if_less(r0, in[0])\n",
+            "The text written from the program is incorrect"
+        );
+    }
+
+    #[test]
+    fn test_assembly_memory() {
+        let mut params = get_params();
+        let operations: OperationSet<i32> = OperationSet::default();
+
+        let text = "r0 = r0 * mem[2]\nmem[1] = mem[1] + r0\nmem[3] = halve(mem[3])\n\
+            r1 = double(r1, in[2])\nr2 = halve(r2, mem[0])\nr3 = double(r3, r1)\n";
+        let program = Program::from_assembly(text, &operations, &mut params).unwrap();
+
+        // Check the modes of the memory instructions
+        assert_eq!(
+            program.get_instructions(),
+            &[
+                Instruction::new(2, 5, 2, 0),
+                Instruction::new(3, 0, 0, 1),
+                Instruction::new(3, 3, 3, 3),
+                Instruction::new(1, 2, 2, 1),
+                Instruction::new(2, 3, 0, 2),
+                Instruction::new(0, 2, 1, 3),
+            ],
+            "The memory instructions read from the text are incorrect"
+        );
+        assert_eq!(program.to_assembly(&operations), text, "The memory instructions are incorrect");
+    }
+
+    #[test]
+    fn test_assembly_round_trip() {
+        let mut params = get_params();
+        params.memory_size = 4;
+        let operations: OperationSet<i32> = OperationSet::default();
+
+        // Check the random program is the same after writing and reading it
+        for _ in 0..20 {
+            let program = Program::new(None, &mut params, &operations);
+            let text = program.to_assembly(&operations);
+            let program_read = Program::from_assembly(&text, &operations, &mut params).unwrap();
+            assert_eq!(
+                program_read.get_instructions(),
+                program.get_instructions(),
+                "The instructions are different after reading them"
+            );
+
+            // Check the result with an empty input and memory (the instructions are skipped
+            // with the mode of the source, also for the unary operations)
+            let mut regs1: Vec<i32> = vec![1, 2, 3, 4, 5];
+            let mut regs2: Vec<i32> = vec![1, 2, 3, 4, 5];
+            program.execute(&operations, &[], &mut regs1);
+            program_read.execute(&operations, &[], &mut regs2);
+            assert_eq!(regs1, regs2, "The program read give a different result without input");
+
+            let input: Vec<i32> = vec![1, -2, 3, -4, 5];
+            let mut regs1: Vec<i32> = vec![1, 2, 3, 4, 5];
+            let mut regs2: Vec<i32> = vec![1, 2, 3, 4, 5];
+            let mut memory1: Memory<i32> = Memory::new(4, 1.0);
+            let mut memory2: Memory<i32> = Memory::new(4, 1.0);
+            program.execute_with_memory(&operations, &input, &mut regs1, &mut memory1);
+            program_read.execute_with_memory(&operations, &input, &mut regs2, &mut memory2);
+
+            assert_eq!(regs1, regs2, "The program read give a different result");
+            assert_eq!(memory1, memory2, "The program read give a different memory");
+            assert_eq!(
+                program_read.to_assembly(&operations),
+                text,
+                "The text is different after reading it"
+            );
+        }
+    }
+
+    #[test]
+    fn test_assembly_error() {
+        let mut params = get_params();
+        let operations: OperationSet<i32> = OperationSet::default();
+
+        let errors = [
+            ("r0 = r0 + in[1]\nr1 = r1 % r0", 2, "unknown symbol '%'"),
+            ("r0 = r0 + in[1]\n\nx1 = r1 + r0", 3, "invalid register 'x1'"),
+            ("r0 = r1 + in[1]", 1, "the first operand 'r1' is not the destination"),
+            ("r0 = foo(r0, r1)", 1, "unknown operation 'foo'"),
+            ("r0 = double(r0, r1, r2)", 1, "expected 1 or 2 operand(s) but found 3"),
+            ("r0 = add(r0)", 1, "expected 2 operand(s) but found 1"),
+            ("r0 + in[a]", 1, "missing '='"),
+            ("r0 = r0 + in[a]", 1, "invalid input 'in[a]'"),
+            ("mem[1] = mem[1] + in[0]", 1, "the source 'in[0]' of the memory is not a register"),
+            ("mem[1] = mem[2] + r0", 1, "the first operand 'mem[2]' is not the destination"),
+        ];
+
+        for (text, line, message) in errors {
+            let error = Program::from_assembly(text, &operations, &mut params).unwrap_err();
+            assert_eq!(error.get_line(), line, "The line of the error is incorrect");
+            assert_eq!(error.get_message(), message, "The message of the error is incorrect");
+        }
+    }
+}
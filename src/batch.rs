@@ -0,0 +1,391 @@
+//! # Batch
+//! Crate that permit to execute the Tangled Program Graph (TPG) on a batch of observations.
+//! The observations are stored column-major: the input `i` of the row `j` is at `i * nb_rows + j`.
+//! Each instruction is applied on a whole column at once (cache-friendly and vectorizable),
+//! and the result of each row is the same as the execution of the row alone.
+//! The rows are independent, so the batch is executed without memory (the memory instructions are
+//! skipped like with an empty memory) and each row start with the registers of the learner.
+//! So a team only act in batch if its learners have no persistent registers and don't use the
+//! memory of the brain (otherwise an error is returned, see `ActError`).
+
+#![deny(missing_docs)]
+#![deny(clippy::all)]
+#![deny(clippy::missing_docs_in_private_items)]
+
+use crate::program::wrap_index;
+use crate::team::is_valid_learner;
+use crate::{ActError, Action, Agent, Brain, Learner, OperationSet, Program, Register, Team};
+
+/// Function to get the column of the destination and the column of the source in the registers.
+/// Return None if the source is the destination.
+fn get_columns<T>(
+    regs: &mut [T],
+    dest: usize,
+    src: usize,
+    nb_rows: usize,
+) -> Option<(&mut [T], &[T])> {
+    if dest < src {
+        let (left, right) = regs.split_at_mut(src);
+        Some((&mut left[dest..dest + nb_rows], &right[..nb_rows]))
+    } else if src < dest {
+        let (left, right) = regs.split_at_mut(dest);
+        Some((&mut right[..nb_rows], &left[src..src + nb_rows]))
+    } else {
+        None
+    }
+}
+
+/// Function to get the rows of the observations (column-major) in a new block.
+fn select_rows<T: Register>(observations: &[T], nb_rows: usize, rows: &[usize]) -> Vec<T> {
+    let mut block: Vec<T> = Vec::with_capacity(observations.len() / nb_rows * rows.len());
+    for column in observations.chunks(nb_rows) {
+        block.extend(rows.iter().map(|row| column[*row]));
+    }
+    block
+}
+
+/// Function to check the size of the block is a multiple of the number of rows.
+fn check_block(len: usize, nb_rows: usize) -> Result<(), ActError> {
+    if nb_rows > 0 && len.is_multiple_of(nb_rows) {
+        Ok(())
+    } else {
+        Err(ActError::MalformedBlock { len, nb_rows })
+    }
+}
+
+/// Function to check the learner give the same bid in batch as in `act`:
+/// the registers are not persistent and the program don't use the memory of the brain.
+fn check_learner<T: Register>(brain: &Brain<T>, learner_idx: usize) -> Result<(), ActError> {
+    let learner = &brain.learners[learner_idx];
+    if learner.has_persistent_registers() {
+        return Err(ActError::UnsupportedPersistentRegisters { learner: learner.get_id() });
+    }
+    if !brain.memory.is_empty() {
+        let program = learner.get_bid_program(&brain.operations).get();
+        let mut modes = program.get_instructions().iter().map(|instruction| instruction.get_mode());
+        if modes.any(|mode| mode == 2 || mode == 3) {
+            return Err(ActError::UnsupportedMemory { learner: learner.get_id() });
+        }
+    }
+    Ok(())
+}
+
+impl Program {
+    /// Function to execute the program on a batch of input.
+    /// The input and the registers are column-major (`nb_rows` values per input or register).
+    /// The memory instructions are skipped (like the execution with an empty memory).
+    /// Return an error if the size of the input or the registers is not a multiple of `nb_rows`.
+    pub fn execute_batch<T: Register>(
+        &self,
+        operations: &OperationSet<T>,
+        inputs: &[T],
+        regs: &mut [T],
+        nb_rows: usize,
+    ) -> Result<(), ActError> {
+        if nb_rows == 0 || regs.is_empty() {
+            return Ok(());
+        }
+        check_block(inputs.len(), nb_rows)?;
+        check_block(regs.len(), nb_rows)?;
+
+        let num_registers = regs.len() / nb_rows;
+        let num_inputs = inputs.len() / nb_rows;
+
+        // Copy of the source when the source is the destination
+        let mut column: Vec<T> = Vec::new();
+
+        for instruction in self.get_instructions() {
+            let Some(operation) = operations.get(instruction.get_op()) else {
+                continue;
+            };
+
+            // The rows are independent so the memory is not used
+            if instruction.get_mode() == 2 || instruction.get_mode() == 3 {
+                continue;
+            }
+
+            let dest = wrap_index(instruction.get_dst(), num_registers) * nb_rows;
+            if instruction.get_mode() == 0 {
+                let src = wrap_index(instruction.get_src(), num_registers) * nb_rows;
+                match get_columns(regs, dest, src, nb_rows) {
+                    Some((x, y)) => operation.apply_batch(x, y, self.get_arithmetic()),
+                    None => {
+                        column.clear();
+                        column.extend_from_slice(&regs[src..src + nb_rows]);
+                        let x = &mut regs[dest..dest + nb_rows];
+                        operation.apply_batch(x, &column, self.get_arithmetic());
+                    }
+                }
+            } else if num_inputs > 0 {
+                let src = wrap_index(instruction.get_src(), num_inputs) * nb_rows;
+                let x = &mut regs[dest..dest + nb_rows];
+                operation.apply_batch(x, &inputs[src..src + nb_rows], self.get_arithmetic());
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<T: Register> Learner<T> {
+    /// Function to get the bid of the learner for each row of the observations (column-major).
+    /// Each row start with the registers of the learner (the registers kept by an execution
+    /// context are not used).
+    /// Return an error if the size of the observations is not a multiple of `nb_rows`.
+    pub fn bid_batch(
+        &self,
+        operations: &OperationSet<T>,
+        observations: &[T],
+        nb_rows: usize,
+    ) -> Result<Vec<T>, ActError> {
+        let mut regs: Vec<T> = Vec::with_capacity(self.get_len_register() * nb_rows);
+        for value in self.get_registers() {
+            regs.extend(std::iter::repeat_n(*value, nb_rows));
+        }
+
+        self.get_bid_program(operations)
+            .get()
+            .execute_batch(operations, observations, &mut regs, nb_rows)?;
+        Ok(regs[..nb_rows].to_vec())
+    }
+}
+
+impl Action {
+    /// Function to get the action for each row of the observations (column-major).
+    /// If the action is atomic
+    ///     |-> Then return the action code for all the rows
+    /// If the action is not atomic
+    ///     |-> Execute the team associate to the action on the batch
+    pub fn get_action_batch<T: Register>(
+        &self,
+        brain: &Brain<T>,
+        observations: &[T],
+        nb_rows: usize,
+        visited: &mut Vec<i32>,
+    ) -> Result<Vec<i32>, ActError> {
+        if let Some(idx_team) = self.get_action_team() {
+            let team = brain.teams.get(idx_team).ok_or(ActError::UnknownTeam(idx_team))?;
+            team.act_batch(brain, observations, nb_rows, visited)
+        } else {
+            Ok(vec![self.get_action_code(); nb_rows])
+        }
+    }
+}
+
+impl Team {
+    /// Function to act on each row of the observations (column-major).
+    /// The winner learner of each row is computed from the bids of the batch, then the rows are
+    /// grouped by winner to follow the action of the winner.
+    /// Give the same actions as `act` on each row alone (the same valid learners and fallback)
+    /// with a new execution context.
+    /// Return an error if a learner of the team has persistent registers or use the memory of
+    /// the brain (the batch can't give the same actions as `act`), or if the size of the
+    /// observations is not a multiple of `nb_rows`.
+    pub fn act_batch<T: Register>(
+        &self,
+        brain: &Brain<T>,
+        observations: &[T],
+        nb_rows: usize,
+        visited: &mut Vec<i32>,
+    ) -> Result<Vec<i32>, ActError> {
+        if visited.contains(&self.get_id()) {
+            return Err(ActError::AlreadyVisited(self.get_id()));
+        }
+        let learners = self.get_learners();
+        if learners.is_empty() {
+            return Err(ActError::EmptyTeam(self.get_id()));
+        }
+        visited.push(self.get_id());
+
+        if nb_rows == 0 {
+            return Ok(Vec::new());
+        }
+        check_block(observations.len(), nb_rows)?;
+        for lrnr in learners.iter() {
+            check_learner(brain, *lrnr)?;
+        }
+
+        // Compute the top valid learner of each row (first learner with the highest bid)
+        let mut valid_winners: Vec<Option<usize>> = vec![None; nb_rows];
+        let mut max_valid_bids: Vec<T> = vec![T::default(); nb_rows];
+        for (i, lrnr) in learners.iter().enumerate() {
+            let bids = brain.learners[*lrnr].bid_batch(&brain.operations, observations, nb_rows)?;
+            let valid = is_valid_learner(brain, *lrnr, visited);
+            for row in 0..nb_rows {
+                if valid && valid_winners[row].is_none_or(|_| bids[row] > max_valid_bids[row]) {
+                    max_valid_bids[row] = bids[row];
+                    valid_winners[row] = Some(i);
+                }
+            }
+        }
+
+        // Follow the action of the top valid learner for each group of rows
+        // (the default action of the brain if no learner is valid)
+        let mut actions: Vec<i32> = vec![brain.default_action; nb_rows];
+        for (i, lrnr) in learners.iter().enumerate() {
+            let rows: Vec<usize> =
+                (0..nb_rows).filter(|row| valid_winners[*row] == Some(i)).collect();
+            if rows.is_empty() {
+                continue;
+            }
+
+            let action = &brain.actions[brain.learners[*lrnr].get_idx_action()];
+            let block = select_rows(observations, nb_rows, &rows);
+            let mut visited_rows = visited.clone();
+            let actions_rows =
+                action.get_action_batch(brain, &block, rows.len(), &mut visited_rows)?;
+            for (row, action_code) in rows.into_iter().zip(actions_rows) {
+                actions[row] = action_code;
+            }
+        }
+        Ok(actions)
+    }
+}
+
+impl<T: Register> Agent<T> {
+    /// Function to act the agent on each row of the observations (column-major).
+    /// Unlike `act`, the execution context of the agent is not used: each row is acted alone
+    /// with a new execution context (see `Team::act_batch`).
+    /// Return an error if a learner of the path has persistent registers or use the memory of
+    /// the brain, because the actions would not be the same as `act`.
+    pub fn act_batch(
+        &self,
+        brain: &Brain<T>,
+        observations: &[T],
+        nb_rows: usize,
+    ) -> Result<Vec<i32>, ActError> {
+        let mut visited: Vec<i32> = Vec::new();
+        let team = self.get_idx_teams();
+        let root = brain.teams.get(team).ok_or(ActError::UnknownTeam(team))?;
+        root.act_batch(brain, observations, nb_rows, &mut visited)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ActionParams, ArithmeticPolicy, LearnerParams, Memory, ProgramParams, TeamParams};
+
+    /// Function to get the params used by the tests.
+    fn get_params(arithmetic: ArithmeticPolicy) -> ProgramParams {
+        ProgramParams {
+            max_program_length: 30,
+            input_size: 4,
+            nb_destinations: 4,
+            arithmetic,
+            ..ProgramParams::default()
+        }
+    }
+
+    /// Function to get the observations used by the tests (row-major and column-major).
+    fn get_observations(nb_rows: usize) -> (Vec<Vec<f64>>, Vec<f64>) {
+        let rows: Vec<Vec<f64>> = (0..nb_rows)
+            .map(|j| (0..4).map(|i| ((i * 7 + j * 13) % 11) as f64 - 5.0).collect())
+            .collect();
+        let columns: Vec<f64> = (0..4).flat_map(|i| rows.iter().map(move |row| row[i])).collect();
+        (rows, columns)
+    }
+
+    #[test]
+    fn test_execute_batch_program() {
+        let mut params = get_params(ArithmeticPolicy::Saturating);
+        let operations: OperationSet<f64> = OperationSet::default();
+        let nb_rows = 9;
+        let (rows, columns) = get_observations(nb_rows);
+
+        for _ in 0..20 {
+            let program = Program::new(None, &mut params, &operations);
+
+            let initial: Vec<f64> = vec![1.0, -2.0, 0.5, 3.0];
+            let mut regs_batch: Vec<f64> = initial
+                .iter()
+                .flat_map(|value| std::iter::repeat_n(*value, nb_rows))
+                .collect();
+            program.execute_batch(&operations, &columns, &mut regs_batch, nb_rows).unwrap();
+
+            // Check each row give the same registers as the execution of the row alone
+            for (j, row) in rows.iter().enumerate() {
+                let mut regs = initial.clone();
+                program.execute(&operations, row, &mut regs);
+                let regs_row: Vec<f64> = (0..4).map(|i| regs_batch[i * nb_rows + j]).collect();
+                assert_eq!(regs_row, regs, "The registers of the batch are incorrect");
+            }
+        }
+    }
+
+    #[test]
+    fn test_act_batch_agent() {
+        let mut brain: Brain<f64> = Brain::default();
+        let mut team_params = TeamParams::default();
+        let mut learner_params = LearnerParams::default();
+        let mut action_params = ActionParams::default();
+        let mut params = get_params(ArithmeticPolicy::Wrapping);
+
+        // Root team with the learners 0, 1 and 2, the learner 2 call the team 1
+        for (i, code) in [3, 5, -1, 7, 11].into_iter().enumerate() {
+            brain.add_action(Action::new(code, &mut action_params));
+            let program = Program::new(None, &mut params, &brain.operations);
+            brain.add_learner(Learner::new(&mut learner_params, program, i, 4));
+        }
+        brain.actions[2].set_action_team(Some(1));
+        for learners in [vec![0, 1, 2], vec![3, 4]] {
+            let mut team = Team::new(&mut team_params);
+            for learner_idx in learners {
+                team.add_learner(learner_idx);
+            }
+            brain.add_team(team);
+        }
+
+        let mut agent = Agent::new(0, &brain);
+        let nb_rows = 25;
+        let (rows, columns) = get_observations(nb_rows);
+
+        // Check the batch give the same actions as each row alone
+        let actions = agent.act_batch(&brain, &columns, nb_rows).unwrap();
+        let expected: Vec<i32> =
+            rows.iter().map(|row| agent.act(&brain, row).unwrap()).collect();
+        assert_eq!(actions, expected, "The actions of the batch are incorrect");
+
+        // Check the bids of the learner
+        let bids = brain.learners[0].bid_batch(&brain.operations, &columns, nb_rows).unwrap();
+        for (j, row) in rows.iter().enumerate() {
+            let mut regs: Vec<f64> = brain.learners[0].get_registers().to_vec();
+            let mut memory: Memory<f64> = Memory::default();
+            let bid = brain.learners[0].bid(&brain.operations, row, &mut regs, &mut memory);
+            assert_eq!(bids[j], bid, "The bid of the batch is incorrect");
+        }
+    }
+
+    #[test]
+    fn test_act_batch_error() {
+        let mut brain: Brain<f64> = Brain::default();
+        let mut learner_params = LearnerParams::default();
+        let mut action_params = ActionParams::default();
+        brain.add_action(Action::new(3, &mut action_params));
+        let mut params = get_params(ArithmeticPolicy::Saturating);
+        let program = Program::from_assembly("r0 = r0 + mem[1]", &brain.operations, &mut params);
+        let program = program.unwrap();
+        brain.add_learner(Learner::new(&mut learner_params, program, 0, 2));
+        let mut team = Team::new(&mut TeamParams::default());
+        team.add_learner(0);
+        brain.add_team(team);
+        let agent = Agent::new(0, &brain);
+
+        // Check a malformed block give an error
+        let malformed = ActError::MalformedBlock { len: 5, nb_rows: 2 };
+        let result = agent.act_batch(&brain, &[1.0; 5], 2);
+        assert_eq!(result, Err(malformed), "The error is incorrect");
+
+        // Check the memory instructions are skipped without memory, an error otherwise
+        assert_eq!(agent.act_batch(&brain, &[1.0; 4], 2), Ok(vec![3, 3]));
+        brain.memory = Memory::new(4, 1.0);
+        let result = agent.act_batch(&brain, &[1.0; 4], 2);
+        assert_eq!(result, Err(ActError::UnsupportedMemory { learner: 0 }));
+
+        // Check the persistent registers give an error
+        brain.memory = Memory::default();
+        brain.learners[0].set_persistent_registers(true);
+        let result = agent.act_batch(&brain, &[1.0; 4], 2);
+        let persistent = ActError::UnsupportedPersistentRegisters { learner: 0 };
+        assert_eq!(result, Err(persistent), "The error is incorrect");
+    }
+}
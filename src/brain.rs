@@ -6,73 +6,315 @@
 #![deny(clippy::all)]
 #![deny(clippy::missing_docs_in_private_items)]
 
-use crate::{Action, Learner, Team};
+use crate::handle::Slots;
+use crate::{
+    Action, ActionHandle, ExecutionContext, Learner, LearnerHandle, Memory, OperationSet, Register,
+    Team, TeamHandle,
+};
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
+/// Function to get the remap of the indices when the entity at the index is removed
+/// (the last entity of the list is moved at the index).
+fn swap_remap(removed: usize, last: usize) -> impl Fn(usize) -> Option<usize> {
+    move |idx| {
+        if idx == removed {
+            None
+        } else if idx == last {
+            Some(removed)
+        } else {
+            Some(idx)
+        }
+    }
+}
+
 /// Structure to represent the brain.
-#[derive(Default)]
-pub struct Brain {
+#[derive(Default, Serialize, Deserialize)]
+#[serde(bound = "T: Register")]
+pub struct Brain<T: Register> {
     /// The list of id -> index of the team in the TPG.
+    #[serde(skip)]
     pub team_id_to_index: HashMap<i32, usize>,
     /// The list of teams inside the TPG.
     pub teams: Vec<Team>,
     /// The list of id -> index of the learner in the TPG.
+    #[serde(skip)]
     pub learner_id_to_index: HashMap<i32, usize>,
     /// The list of learner inside the TPG.
-    pub learners: Vec<Learner>,
+    pub learners: Vec<Learner<T>>,
     /// The list of id -> index of the action in the TPG.
+    #[serde(skip)]
     pub action_id_to_index: HashMap<i32, usize>,
     /// The list of actions inside the TPG.
     pub actions: Vec<Action>,
+    /// The slot of each team (referenced by the handles).
+    #[serde(skip)]
+    pub(crate) team_slots: Slots,
+    /// The slot of each learner (referenced by the handles).
+    #[serde(skip)]
+    pub(crate) learner_slots: Slots,
+    /// The slot of each action (referenced by the handles).
+    #[serde(skip)]
+    pub(crate) action_slots: Slots,
+    /// The operations available for the programs inside the TPG.
+    /// (Saved with the name of the operations)
+    #[serde(skip)]
+    pub operations: OperationSet<T>,
+    /// The memory given to the execution context of each agent
+    /// (the size and the probability of write, the values are kept by the agents).
+    #[serde(default)]
+    pub memory: Memory<T>,
+    /// The action code of a decision when no learner of a team is valid
+    /// (every learner of the team call a team already visited, see `Team::act`).
+    #[serde(default)]
+    pub default_action: i32,
 }
 
-impl Brain {
+impl<T: Register> Brain<T> {
     /// Function to add a learner in the brain.
-    pub fn add_learner(&mut self, learner: Learner) {
+    /// Return the handle of the learner.
+    pub fn add_learner(&mut self, learner: Learner<T>) -> LearnerHandle<T> {
+        self.learner_id_to_index.insert(learner.get_id(), self.learners.len());
         self.learners.push(learner);
+        self.learner_slots.insert(self.learners.len())
     }
 
     /// Function to add a team in the brain.
-    pub fn add_team(&mut self, team: Team) {
+    /// Return the handle of the team.
+    pub fn add_team(&mut self, team: Team) -> TeamHandle {
+        self.team_id_to_index.insert(team.get_id(), self.teams.len());
         self.teams.push(team);
+        self.team_slots.insert(self.teams.len())
     }
 
     /// Function to add the action in the brain.
-    pub fn add_action(&mut self, action: Action) {
+    /// Return the handle of the action.
+    pub fn add_action(&mut self, action: Action) -> ActionHandle {
+        self.action_id_to_index.insert(action.get_id(), self.actions.len());
         self.actions.push(action);
+        self.action_slots.insert(self.actions.len())
     }
 
-    /// Function to get the index of the team based on the team id
-    pub fn get_team_index_from_team_id(&self, team_id: i32) -> Option<usize> {
-        for i in 0..self.teams.len() {
-            let team = self.teams.get(i).unwrap();
-            if team.get_id() == team_id {
-                return Some(i);
+    /// Function to set the back references of the team (after the team is added in the brain):
+    /// the team is in `in_teams` of its learners and each learner is in `in_learners` of the
+    /// team called by its action (see `Brain::validate`).
+    pub fn link_team(&mut self, team_idx: usize) {
+        for learner_idx in self.teams[team_idx].get_learners() {
+            let learner = &mut self.learners[learner_idx];
+            if !learner.get_in_teams().contains(&team_idx) {
+                learner.set_in_teams(team_idx);
+            }
+            if let Some(action_team) = self.actions[learner.get_idx_action()].get_action_team() {
+                self.teams[action_team].add_in_learner(learner_idx);
             }
         }
-        None
     }
 
-    /// Function to get the index of the learner based on the learner id
-    pub fn get_learner_index_from_learner_id(&self, learner_id: i32) -> Option<usize> {
-        for i in 0..self.learners.len() {
-            let learner = self.learners.get(i).unwrap();
-            if learner.get_id() == learner_id {
-                return Some(i);
+    /// Function to remove the learners of the team (the team is removed from `in_teams` of
+    /// the learners).
+    pub(crate) fn clear_team(&mut self, team_idx: usize) {
+        for learner_idx in self.teams[team_idx].get_learners() {
+            self.learners[learner_idx].remap_in_teams(&|idx| (idx != team_idx).then_some(idx));
+        }
+        self.teams[team_idx].remove_learners();
+    }
+
+    /// Function to get the handle of the team based on the team id.
+    pub fn get_team_handle(&self, team_id: i32) -> Option<TeamHandle> {
+        let index = self.get_team_index_from_team_id(team_id)?;
+        self.team_slots.get_handle(index)
+    }
+
+    /// Function to get the handle of the learner based on the learner id.
+    pub fn get_learner_handle(&self, learner_id: i32) -> Option<LearnerHandle<T>> {
+        let index = self.get_learner_index_from_learner_id(learner_id)?;
+        self.learner_slots.get_handle(index)
+    }
+
+    /// Function to get the handle of the action based on the action id.
+    pub fn get_action_handle(&self, action_id: i32) -> Option<ActionHandle> {
+        let index = self.get_action_index_from_action_id(action_id)?;
+        self.action_slots.get_handle(index)
+    }
+
+    /// Function to get the index of the team of the handle (None if the handle is stale).
+    pub fn get_team_index(&self, handle: TeamHandle) -> Option<usize> {
+        self.team_slots.get_index(&handle).filter(|index| *index < self.teams.len())
+    }
+
+    /// Function to get the team of the handle (None if the handle is stale).
+    pub fn get_team(&self, handle: TeamHandle) -> Option<&Team> {
+        self.teams.get(self.get_team_index(handle)?)
+    }
+
+    /// Function to get the mutable team of the handle (None if the handle is stale).
+    pub fn get_team_mut(&mut self, handle: TeamHandle) -> Option<&mut Team> {
+        let index = self.get_team_index(handle)?;
+        self.teams.get_mut(index)
+    }
+
+    /// Function to get the index of the learner of the handle (None if the handle is stale).
+    pub fn get_learner_index(&self, handle: LearnerHandle<T>) -> Option<usize> {
+        self.learner_slots.get_index(&handle).filter(|index| *index < self.learners.len())
+    }
+
+    /// Function to get the learner of the handle (None if the handle is stale).
+    pub fn get_learner(&self, handle: LearnerHandle<T>) -> Option<&Learner<T>> {
+        self.learners.get(self.get_learner_index(handle)?)
+    }
+
+    /// Function to get the mutable learner of the handle (None if the handle is stale).
+    pub fn get_learner_mut(&mut self, handle: LearnerHandle<T>) -> Option<&mut Learner<T>> {
+        let index = self.get_learner_index(handle)?;
+        self.learners.get_mut(index)
+    }
+
+    /// Function to get the index of the action of the handle (None if the handle is stale).
+    pub fn get_action_index(&self, handle: ActionHandle) -> Option<usize> {
+        self.action_slots.get_index(&handle).filter(|index| *index < self.actions.len())
+    }
+
+    /// Function to get the action of the handle (None if the handle is stale).
+    pub fn get_action(&self, handle: ActionHandle) -> Option<&Action> {
+        self.actions.get(self.get_action_index(handle)?)
+    }
+
+    /// Function to get the mutable action of the handle (None if the handle is stale).
+    pub fn get_action_mut(&mut self, handle: ActionHandle) -> Option<&mut Action> {
+        let index = self.get_action_index(handle)?;
+        self.actions.get_mut(index)
+    }
+
+    /// Function to remove the team of the handle.
+    /// The last team is moved at the index of the removed team and the references are remapped,
+    /// the handle of the removed team is stale (the handle of the moved team is still valid,
+    /// but the index kept outside the brain, e.g. in the trainer, are not remapped).
+    /// Return None if the handle is stale or if the team is used by an action.
+    pub fn remove_team(&mut self, handle: TeamHandle) -> Option<Team> {
+        let index = self.get_team_index(handle)?;
+        if self.actions.iter().any(|action| action.get_action_team() == Some(index)) {
+            return None;
+        }
+
+        let remap = swap_remap(index, self.teams.len() - 1);
+        let team = self.teams.swap_remove(index);
+        for action in self.actions.iter_mut() {
+            if let Some(action_team) = action.get_action_team() {
+                action.set_action_team(remap(action_team));
             }
         }
-        None
+        for learner in self.learners.iter_mut() {
+            learner.remap_in_teams(&remap);
+        }
+
+        self.team_id_to_index.remove(&team.get_id());
+        if let Some(moved) = self.teams.get(index) {
+            self.team_id_to_index.insert(moved.get_id(), index);
+        }
+        self.team_slots.swap_remove(index);
+        Some(team)
     }
 
-    /// Function to get the index of the action based on the action id
-    pub fn get_action_index_from_action_id(&self, action_id: i32) -> Option<usize> {
-        for i in 0..self.actions.len() {
-            let action = self.actions.get(i).unwrap();
-            if action.get_id() == action_id {
-                return Some(i);
+    /// Function to remove the learner of the handle.
+    /// The last learner is moved at the index of the removed learner and the references are
+    /// remapped, the handle of the removed learner is stale (the handle of the moved learner is
+    /// still valid, but the registers kept by the execution context of the agents are not remapped).
+    /// Return None if the handle is stale or if the learner is in a team.
+    pub fn remove_learner(&mut self, handle: LearnerHandle<T>) -> Option<Learner<T>> {
+        let index = self.get_learner_index(handle)?;
+        if self.teams.iter().any(|team| team.get_learners().contains(&index)) {
+            return None;
+        }
+
+        let remap = swap_remap(index, self.learners.len() - 1);
+        let learner = self.learners.swap_remove(index);
+        for team in self.teams.iter_mut() {
+            team.remap_learners(&remap);
+        }
+
+        self.learner_id_to_index.remove(&learner.get_id());
+        if let Some(moved) = self.learners.get(index) {
+            self.learner_id_to_index.insert(moved.get_id(), index);
+        }
+        self.learner_slots.swap_remove(index);
+        Some(learner)
+    }
+
+    /// Function to remove the action of the handle.
+    /// The last action is moved at the index of the removed action and the references are remapped,
+    /// the handle of the removed action is stale (the handle of the moved action is still valid).
+    /// Return None if the handle is stale or if the action is used by a learner.
+    pub fn remove_action(&mut self, handle: ActionHandle) -> Option<Action> {
+        let index = self.get_action_index(handle)?;
+        if self.learners.iter().any(|learner| learner.get_idx_action() == index) {
+            return None;
+        }
+
+        let last = self.actions.len() - 1;
+        let action = self.actions.swap_remove(index);
+        for learner in self.learners.iter_mut() {
+            if learner.get_idx_action() == last {
+                learner.set_idx_action(index);
+            }
+        }
+
+        self.action_id_to_index.remove(&action.get_id());
+        if let Some(moved) = self.actions.get(index) {
+            self.action_id_to_index.insert(moved.get_id(), index);
+        }
+        self.action_slots.swap_remove(index);
+        Some(action)
+    }
+
+    /// Function to compile the programs of all the learners for the size of the input
+    /// (else each program is compiled at the first bid).
+    pub fn compile_programs(&mut self, input_len: usize) {
+        let memory_len = self.memory.get_size();
+        for learner in self.learners.iter_mut() {
+            learner.compile(&self.operations, input_len, memory_len);
+        }
+    }
+
+    /// Function to create a new execution context for an agent
+    /// (with an empty memory of the same size as the memory of the brain).
+    pub fn new_context(&self) -> ExecutionContext<T> {
+        ExecutionContext::new(Memory::new(self.memory.get_size(), self.memory.get_p_write()))
+    }
+
+    /// Function to get the index of the teams reachable from the team (the team is the first).
+    pub fn get_reachable_teams(&self, team_idx: usize) -> Vec<usize> {
+        let mut reachable: Vec<usize> = vec![team_idx];
+        let mut i = 0;
+        while i < reachable.len() {
+            for learner_idx in self.teams[reachable[i]].get_learners() {
+                let action_idx = self.learners[learner_idx].get_idx_action();
+                if let Some(next_team) = self.actions[action_idx].get_action_team() {
+                    if !reachable.contains(&next_team) {
+                        reachable.push(next_team);
+                    }
+                }
             }
+            i += 1;
         }
-        None
+        reachable
+    }
+
+    /// Function to get the index of the team based on the team id
+    pub fn get_team_index_from_team_id(&self, team_id: i32) -> Option<usize> {
+        let index = *self.team_id_to_index.get(&team_id)?;
+        self.teams.get(index).filter(|team| team.get_id() == team_id).map(|_| index)
+    }
+
+    /// Function to get the index of the learner based on the learner id
+    pub fn get_learner_index_from_learner_id(&self, learner_id: i32) -> Option<usize> {
+        let index = *self.learner_id_to_index.get(&learner_id)?;
+        self.learners.get(index).filter(|learner| learner.get_id() == learner_id).map(|_| index)
+    }
+
+    /// Function to get the index of the action based on the action id
+    pub fn get_action_index_from_action_id(&self, action_id: i32) -> Option<usize> {
+        let index = *self.action_id_to_index.get(&action_id)?;
+        self.actions.get(index).filter(|action| action.get_id() == action_id).map(|_| index)
     }
 
     /// Function to sort a list of teams idx based on their fitness
@@ -97,3 +339,47 @@ impl Brain {
         list_teams_idx_sorted
     }
 }
+
+/// Function to build the brain used by the tests with the params (the ids of the entities).
+/// The learner i has the program i (assembly) and the action i (action code), the teams are the
+/// list of their learners and each link (action, team) make the action call the team.
+#[cfg(test)]
+pub(crate) fn brain_from_assembly_with_params<T: Register>(
+    params: &mut crate::TeamParams,
+    programs: &[(&str, i32)],
+    teams: &[&[usize]],
+    links: &[(usize, usize)],
+) -> Brain<T> {
+    let mut brain: Brain<T> = Brain::default();
+    let learner_params = &mut params.learner;
+    for (i, (text, code)) in programs.iter().enumerate() {
+        let program_params = &mut learner_params.program;
+        let program = crate::Program::from_assembly(text, &brain.operations, program_params);
+        brain.add_action(Action::new(*code, &mut learner_params.action));
+        brain.add_learner(Learner::new(learner_params, program.unwrap(), i, 2));
+    }
+    for (action_idx, team_idx) in links {
+        brain.actions[*action_idx].set_action_team(Some(*team_idx));
+    }
+    for learners in teams {
+        let mut team = Team::new(params);
+        for learner_idx in learners.iter() {
+            team.add_learner(*learner_idx);
+        }
+        brain.add_team(team);
+    }
+    for team_idx in 0..brain.teams.len() {
+        brain.link_team(team_idx);
+    }
+    brain
+}
+
+/// Function to build the brain used by the tests (see `brain_from_assembly_with_params`).
+#[cfg(test)]
+pub(crate) fn brain_from_assembly<T: Register>(
+    programs: &[(&str, i32)],
+    teams: &[&[usize]],
+    links: &[(usize, usize)],
+) -> Brain<T> {
+    brain_from_assembly_with_params(&mut crate::TeamParams::default(), programs, teams, links)
+}
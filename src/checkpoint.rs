@@ -0,0 +1,238 @@
+//! # Checkpoint
+//! Crate that permit to save and resume the training of the Tangled Program Graph (TPG).
+//! The checkpoint contains the version of the format, the state of the generator, the trainer
+//! (with the id counters of the params) and the brain (in the format of `Brain::to_json`):
+//!     { "version": 1, "rng": { ... }, "trainer": { ... }, "brain": { "version": 1, ... } }
+//! After the load, the training continue exactly as if it was never stopped.
+//! The evolution can save a checkpoint every N generations (`TrainerParams::checkpoint_interval`),
+//! only the last checkpoints are kept (`TrainerParams::checkpoint_retention`).
+//! The checkpoint is saved before the garbage collection (`Trainer::collect_garbage` is called
+//! by the user). The evolution return an error if the checkpoint can't be saved.
+
+#![deny(missing_docs)]
+#![deny(clippy::all)]
+#![deny(clippy::missing_docs_in_private_items)]
+
+use crate::storage::BrainFileVersion;
+use crate::{
+    get_rng_state, set_rng_state, Brain, OperationSet, Register, RngState, StorageError, Trainer,
+};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+
+/// The version of the format of the checkpoint.
+pub const CHECKPOINT_FORMAT_VERSION: u32 = 1;
+
+/// Structure to represent the content of the file (R is the trainer or a reference on the trainer).
+#[derive(Serialize, Deserialize)]
+struct CheckpointFile<R> {
+    /// The version of the format.
+    version: u32,
+    /// The state of the generator.
+    rng: RngState,
+    /// The trainer.
+    trainer: R,
+    /// The brain (with its own version, register and operations).
+    brain: serde_json::Value,
+}
+
+impl Trainer {
+    /// Function to write the checkpoint of the training as JSON.
+    pub fn checkpoint_to_json<T: Register>(
+        &self,
+        brain: &Brain<T>,
+    ) -> Result<String, StorageError> {
+        let file = CheckpointFile {
+            version: CHECKPOINT_FORMAT_VERSION,
+            rng: get_rng_state(),
+            trainer: self,
+            brain: brain.to_value()?,
+        };
+        Ok(serde_json::to_string(&file)?)
+    }
+
+    /// Function to read the checkpoint of the training from JSON with the operations of the brain.
+    /// The index of the trainer and of the brain are checked.
+    /// The state of the generator is restored.
+    pub fn checkpoint_from_json<T: Register>(
+        text: &str,
+        operations: OperationSet<T>,
+    ) -> Result<(Trainer, Brain<T>), StorageError> {
+        let value: serde_json::Value = serde_json::from_str(text)?;
+        let version = BrainFileVersion::deserialize(&value)?.version;
+        if version != CHECKPOINT_FORMAT_VERSION {
+            return Err(StorageError::UnsupportedVersion(version));
+        }
+
+        let file = CheckpointFile::<Trainer>::deserialize(&value)?;
+        let brain = Brain::from_value(&file.brain, operations)?;
+        file.trainer.check_indices(&brain)?;
+        set_rng_state(file.rng);
+        Ok((file.trainer, brain))
+    }
+
+    /// Function to save the checkpoint of the training in the file (JSON).
+    /// The file is written next to the path then renamed, so an interruption don't corrupt it.
+    pub fn save_checkpoint<T: Register, P: AsRef<Path>>(
+        &self,
+        brain: &Brain<T>,
+        path: P,
+    ) -> Result<(), StorageError> {
+        let path = path.as_ref();
+        let tmp_path = path.with_extension("tmp");
+        fs::write(&tmp_path, self.checkpoint_to_json(brain)?)?;
+        fs::rename(&tmp_path, path)?;
+        Ok(())
+    }
+
+    /// Function to load the checkpoint of the training from the file (JSON)
+    /// with the default operations.
+    pub fn load_checkpoint<T: Register, P: AsRef<Path>>(
+        path: P,
+    ) -> Result<(Trainer, Brain<T>), StorageError> {
+        Trainer::load_checkpoint_with_operations(path, OperationSet::default())
+    }
+
+    /// Function to load the checkpoint of the training from the file (JSON)
+    /// with the operations used by the programs.
+    pub fn load_checkpoint_with_operations<T: Register, P: AsRef<Path>>(
+        path: P,
+        operations: OperationSet<T>,
+    ) -> Result<(Trainer, Brain<T>), StorageError> {
+        let text = fs::read_to_string(path)?;
+        Trainer::checkpoint_from_json(&text, operations)
+    }
+
+    /// Function to save the checkpoint of the generation in the directory of the params
+    /// and remove the oldest checkpoints (more than the retention).
+    /// Return an error if the checkpoint can't be saved or an old checkpoint can't be removed
+    /// (the list of the checkpoints is not changed if the save fail).
+    pub(crate) fn auto_save_checkpoint<T: Register>(
+        &mut self,
+        brain: &Brain<T>,
+    ) -> Result<(), StorageError> {
+        let dir = Path::new(&self.mutate_params.checkpoint_dir).to_path_buf();
+        let path = dir.join(format!("checkpoint_{}.json", self.get_generation()));
+        let name = path.to_string_lossy().into_owned();
+
+        // The list of the checkpoints is changed before the save (the checkpoint contains it)
+        let mut checkpoints: Vec<String> =
+            self.checkpoints.iter().filter(|other| **other != name).cloned().collect();
+        checkpoints.push(name);
+        let retention = self.mutate_params.checkpoint_retention.max(0) as usize;
+        let nb_removed = match retention {
+            0 => 0,
+            _ => checkpoints.len().saturating_sub(retention),
+        };
+        let removed: Vec<String> = checkpoints.drain(..nb_removed).collect();
+        let previous = std::mem::replace(&mut self.checkpoints, checkpoints);
+
+        let result = fs::create_dir_all(&dir)
+            .map_err(StorageError::from)
+            .and_then(|_| self.save_checkpoint(brain, &path));
+        if let Err(error) = result {
+            self.checkpoints = previous;
+            return Err(error);
+        }
+        for old in removed {
+            match fs::remove_file(&old) {
+                Err(error) if error.kind() != ErrorKind::NotFound => return Err(error.into()),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{seed_rng, EvolveError, Params};
+
+    /// Function to run one generation of the training (the best action is 2).
+    fn run_generation(trainer: &mut Trainer, brain: &mut Brain<f64>) {
+        let state: Vec<f64> = vec![1.0, -2.0, 0.5, 3.0, 1.5];
+        let mut agents = trainer.get_agents(brain, Vec::new(), Vec::new());
+        for agent in agents.iter_mut() {
+            let score = if agent.act(brain, &state).unwrap() == 2 { 100 } else { -100 };
+            agent.reward(brain, score, "t1".to_string());
+        }
+        trainer.evolve(brain, vec!["t1".to_string()], &Vec::new()).unwrap();
+        trainer.collect_garbage(brain, &[]);
+    }
+
+    #[test]
+    fn test_checkpoint() {
+        let dir = std::env::temp_dir().join(format!("tpg_checkpoint_{}", std::process::id()));
+        let mut params = Params::new("params_trainer.json".to_string());
+        params.trainer.checkpoint_interval = 2;
+        params.trainer.checkpoint_retention = 2;
+        params.trainer.checkpoint_dir = dir.to_string_lossy().into_owned();
+
+        seed_rng(3);
+        let mut brain: Brain<f64> = Brain::default();
+        let mut trainer = Trainer::new(&mut params.trainer, 5);
+        trainer.set_up_actions(vec![0, 1, 2, 3, 4]);
+        trainer.initialize_populations(&mut brain);
+        for _ in 0..6 {
+            run_generation(&mut trainer, &mut brain);
+        }
+
+        // Check only the last checkpoints are kept
+        assert_eq!(trainer.get_generation(), 6, "The generation is incorrect");
+        assert!(!dir.join("checkpoint_2.json").exists(), "The oldest checkpoint is kept");
+        assert!(dir.join("checkpoint_4.json").exists(), "The checkpoint 4 is not saved");
+        assert!(dir.join("checkpoint_6.json").exists(), "The checkpoint 6 is not saved");
+        let expected_brain = brain.to_json().unwrap();
+        let expected_trainer = serde_json::to_string(&trainer).unwrap();
+        let expected_rng = get_rng_state();
+
+        // Check the training continue exactly after the load of the checkpoint
+        seed_rng(11);
+        let (mut trainer, mut brain) =
+            Trainer::load_checkpoint::<f64, _>(dir.join("checkpoint_4.json")).unwrap();
+        assert_eq!(trainer.get_generation(), 4, "The generation of the checkpoint is incorrect");
+        trainer.collect_garbage(&mut brain, &[]);
+        for _ in 0..2 {
+            run_generation(&mut trainer, &mut brain);
+        }
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(get_rng_state(), expected_rng, "The generator is not restored");
+        assert_eq!(brain.to_json().unwrap(), expected_brain, "The brain is not the same");
+        let trainer_json = serde_json::to_string(&trainer).unwrap();
+        assert_eq!(trainer_json, expected_trainer, "The trainer is not the same");
+
+        // Check the version is checked
+        let text = trainer.checkpoint_to_json(&brain).unwrap();
+        let other_version = text.replacen("\"version\":1", "\"version\":5", 1);
+        let error = Trainer::checkpoint_from_json(&other_version, OperationSet::<f64>::default());
+        assert!(
+            matches!(error.err(), Some(StorageError::UnsupportedVersion(5))),
+            "The version is not checked"
+        );
+
+        // Check the index of the trainer are checked
+        let invalid = text.replacen("\"root_teams\":[", "\"root_teams\":[99,", 1);
+        let error = Trainer::checkpoint_from_json(&invalid, OperationSet::<f64>::default());
+        assert!(
+            matches!(error.err(), Some(StorageError::InvalidIndex(_))),
+            "The index of the trainer are not checked"
+        );
+
+        // Check the error of the checkpoint is returned by the evolution
+        let file = std::env::temp_dir().join(format!("tpg_not_dir_{}", std::process::id()));
+        fs::write(&file, "").unwrap();
+        trainer.mutate_params.checkpoint_dir = file.to_string_lossy().into_owned();
+        trainer.mutate_params.checkpoint_interval = 1;
+        let checkpoints = trainer.checkpoints.clone();
+        let result = trainer.evolve(&mut brain, vec!["t1".to_string()], &Vec::new());
+        fs::remove_file(&file).unwrap();
+        assert!(
+            matches!(result, Err(EvolveError::Checkpoint(StorageError::Io(_)))),
+            "The error of the checkpoint is not returned"
+        );
+        assert_eq!(trainer.checkpoints, checkpoints, "The list of the checkpoints is changed");
+    }
+}
@@ -0,0 +1,581 @@
+//! # Codegen
+//! Crate that permit to generate the Rust source of an agent of the Tangled Program Graph (TPG).
+//! The generated module don't depend on this crate: every team, learner, program and action
+//! reachable from the root team is written as plain code (the programs are unrolled).
+//! The function `act` of the generated module give the same decision as `Agent::act`.
+
+#![deny(missing_docs)]
+#![deny(clippy::all)]
+#![deny(clippy::missing_docs_in_private_items)]
+
+use crate::register::{DIV_EPSILON_F32, DIV_EPSILON_F64};
+use crate::{Agent, ArithmeticPolicy, Brain, BuiltinOp, Register};
+use std::fmt;
+
+/// Enumeration to represent an error while generating the source of an agent.
+#[derive(Clone, Debug, PartialEq)]
+pub enum CodegenError {
+    /// The type of the register can't be written in the source (only i32, f32 and f64).
+    UnsupportedRegister(String),
+    /// The operation can't be written in the source (only the operations of the crate).
+    UnsupportedOperation(String),
+    /// The program of the learner use the memory (the write of the memory is random).
+    UnsupportedMemory {
+        /// The id of the learner.
+        learner: i32,
+    },
+    /// The learner keep the registers between the decisions.
+    UnsupportedPersistentRegisters {
+        /// The id of the learner.
+        learner: i32,
+    },
+}
+
+impl fmt::Display for CodegenError {
+    /// Function to display the error.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CodegenError::UnsupportedRegister(name) => {
+                write!(f, "the register type '{}' is not supported", name)
+            }
+            CodegenError::UnsupportedOperation(name) => {
+                write!(f, "the operation '{}' is not supported", name)
+            }
+            CodegenError::UnsupportedMemory { learner } => {
+                write!(f, "the learner {} use the memory (not supported)", learner)
+            }
+            CodegenError::UnsupportedPersistentRegisters { learner } => {
+                write!(f, "the learner {} has persistent registers (not supported)", learner)
+            }
+        }
+    }
+}
+
+impl std::error::Error for CodegenError {}
+
+/// Function to write a value of the register as a Rust literal.
+fn literal<T: Register>(value: T, type_name: &str) -> String {
+    let text = format!("{:?}", value);
+    match text.as_str() {
+        "NaN" => format!("{}::NAN", type_name),
+        "inf" => format!("{}::INFINITY", type_name),
+        "-inf" => format!("{}::NEG_INFINITY", type_name),
+        _ => text,
+    }
+}
+
+/// Structure to represent the state of the generation.
+struct Generator<'a, T: Register> {
+    /// The brain of the agent.
+    brain: &'a Brain<T>,
+    /// The name of the register type (i32, f32 or f64).
+    type_name: &'static str,
+    /// The list of arithmetic used by the programs (the index is used in the name of the helpers).
+    policies: Vec<ArithmeticPolicy>,
+    /// The name of the helpers already written.
+    helper_names: Vec<String>,
+    /// The source of the helpers.
+    helpers: String,
+    /// The index of the learners already written.
+    learners: Vec<usize>,
+    /// The index of the teams already written.
+    teams: Vec<usize>,
+    /// The source of the learners and teams.
+    functions: String,
+}
+
+impl<'a, T: Register> Generator<'a, T> {
+    /// Constructor of the generator.
+    fn new(brain: &'a Brain<T>) -> Result<Self, CodegenError> {
+        let type_name = T::NAME;
+        if !["i32", "f32", "f64"].contains(&type_name) {
+            return Err(CodegenError::UnsupportedRegister(type_name.to_string()));
+        }
+
+        Ok(Generator {
+            brain,
+            type_name,
+            policies: Vec::new(),
+            helper_names: Vec::new(),
+            helpers: String::new(),
+            learners: Vec::new(),
+            teams: Vec::new(),
+            functions: String::new(),
+        })
+    }
+
+    /// Function to check if the register is a float.
+    fn is_float(&self) -> bool {
+        self.type_name != "i32"
+    }
+
+    /// Function to write a helper (if not already written).
+    fn add_helper(&mut self, name: &str, params: &str, body: &str) {
+        if self.helper_names.iter().any(|helper| helper == name) {
+            return;
+        }
+        self.helper_names.push(name.to_string());
+        self.helpers.push_str(&format!(
+            "fn {}({}) -> Register {{\n    {}\n}}\n\n",
+            name, params, body
+        ));
+    }
+
+    /// Function to write the helper that keep a float finite with the arithmetic.
+    /// Return the name of the helper.
+    fn finite_helper(&mut self, policy: ArithmeticPolicy, p: usize) -> String {
+        let name = format!("fin_p{}", p);
+        let body = match policy {
+            ArithmeticPolicy::Checked { fallback } => format!(
+                "if !x.is_finite() {{ {} }} else {{ x.clamp(Register::MIN, Register::MAX) }}",
+                literal(T::from_f64(fallback), self.type_name)
+            ),
+            _ => "if x.is_nan() { 0.0 } else { x.clamp(Register::MIN, Register::MAX) }"
+                .to_string(),
+        };
+        self.add_helper(&name, "x: Register", &body);
+        name
+    }
+
+    /// Function to write the integer operation with the arithmetic.
+    fn integer_arithmetic(&self, policy: ArithmeticPolicy, method: &str, x: &str, y: &str) -> String {
+        match policy {
+            ArithmeticPolicy::Wrapping => format!("{}.wrapping_{}({})", x, method, y),
+            ArithmeticPolicy::Saturating => format!("{}.saturating_{}({})", x, method, y),
+            ArithmeticPolicy::Checked { fallback } => format!(
+                "{}.checked_{}({}).unwrap_or({})",
+                x,
+                method,
+                y,
+                literal(T::from_f64(fallback), self.type_name)
+            ),
+        }
+    }
+
+    /// Function to write the helper of the operation (name and kind) with the arithmetic.
+    /// Return the name of the helper, or an error if the operation is not a builtin operation.
+    fn operation_helper(
+        &mut self,
+        name: &str,
+        kind: Option<BuiltinOp>,
+        policy: ArithmeticPolicy,
+    ) -> Result<String, CodegenError> {
+        let unsupported = || CodegenError::UnsupportedOperation(name.to_string());
+        let kind = kind.ok_or_else(unsupported)?;
+        let p = match self.policies.iter().position(|known| *known == policy) {
+            Some(p) => p,
+            None => {
+                self.policies.push(policy);
+                self.policies.len() - 1
+            }
+        };
+        let helper = format!("{}_p{}", name, p);
+
+        // The parameter y is not used by the unary operations
+        let unary = matches!(
+            kind,
+            BuiltinOp::Double
+                | BuiltinOp::Halve
+                | BuiltinOp::Sin
+                | BuiltinOp::Cos
+                | BuiltinOp::Exp
+                | BuiltinOp::Log
+        );
+        let y = if unary { "_y" } else { "y" };
+
+        let body = if self.is_float() {
+            let fin = self.finite_helper(policy, p);
+            let epsilon = if self.type_name == "f32" {
+                format!("{:?}", DIV_EPSILON_F32)
+            } else {
+                format!("{:?}", DIV_EPSILON_F64)
+            };
+            match kind {
+                BuiltinOp::Add => format!("{}(x + y)", fin),
+                BuiltinOp::Sub => format!("{}(x - y)", fin),
+                BuiltinOp::Mul => format!("{}(x * y)", fin),
+                BuiltinOp::Div => {
+                    format!("if y.abs() < {} {{ x }} else {{ {}(x / y) }}", epsilon, fin)
+                }
+                BuiltinOp::Double => format!("{}(x * 2.0)", fin),
+                BuiltinOp::Halve => format!("{}(x / 2.0)", fin),
+                BuiltinOp::NegIfLess => format!("if x < y {{ {}(-x) }} else {{ x }}", fin),
+                BuiltinOp::Max => "if y > x { y } else { x }".to_string(),
+                BuiltinOp::Min => "if y < x { y } else { x }".to_string(),
+                BuiltinOp::Sin => format!("{}(x.sin())", fin),
+                BuiltinOp::Cos => format!("{}(x.cos())", fin),
+                BuiltinOp::Exp => format!("{}(x.exp())", fin),
+                BuiltinOp::Log => {
+                    format!("{}(if x == 0.0 {{ 0.0 }} else {{ x.abs().ln() }})", fin)
+                }
+            }
+        } else {
+            match kind {
+                BuiltinOp::Add => self.integer_arithmetic(policy, "add", "x", "y"),
+                BuiltinOp::Sub => self.integer_arithmetic(policy, "sub", "x", "y"),
+                BuiltinOp::Mul => self.integer_arithmetic(policy, "mul", "x", "y"),
+                BuiltinOp::Div => format!(
+                    "if y == 0 {{ x }} else {{ {} }}",
+                    self.integer_arithmetic(policy, "div", "x", "y")
+                ),
+                BuiltinOp::Double => self.integer_arithmetic(policy, "mul", "x", "2"),
+                BuiltinOp::Halve => "x / 2".to_string(),
+                BuiltinOp::NegIfLess => format!(
+                    "if x < y {{ {} }} else {{ x }}",
+                    self.integer_arithmetic(policy, "sub", "0i32", "x")
+                ),
+                BuiltinOp::Max => "if y > x { y } else { x }".to_string(),
+                BuiltinOp::Min => "if y < x { y } else { x }".to_string(),
+                BuiltinOp::Sin | BuiltinOp::Cos | BuiltinOp::Exp | BuiltinOp::Log => {
+                    return Err(unsupported())
+                }
+            }
+        };
+
+        self.add_helper(&helper, &format!("x: Register, {}: Register", y), &body);
+        Ok(helper)
+    }
+
+    /// Function to write the function of the learner (the program is unrolled).
+    fn write_learner(&mut self, learner_idx: usize) -> Result<(), CodegenError> {
+        if self.learners.contains(&learner_idx) {
+            return Ok(());
+        }
+        self.learners.push(learner_idx);
+
+        let learner = &self.brain.learners[learner_idx];
+        if learner.has_persistent_registers() {
+            return Err(CodegenError::UnsupportedPersistentRegisters {
+                learner: learner.get_id(),
+            });
+        }
+        let registers = learner.get_registers();
+        let num_registers = registers.len() as i64;
+
+        let mut source = format!("/// Learner {}\n", learner.get_id());
+        if registers.is_empty() {
+            source.push_str(&format!(
+                "fn learner_{}(_obs: &[Register]) -> Register {{\n    panic!(\"The learner {} has no register\");\n}}\n\n",
+                learner_idx,
+                learner.get_id()
+            ));
+            self.functions.push_str(&source);
+            return Ok(());
+        }
+
+        let values: Vec<String> = registers
+            .iter()
+            .map(|value| literal(*value, self.type_name))
+            .collect();
+        source.push_str(&format!(
+            "fn learner_{}(obs: &[Register]) -> Register {{\n",
+            learner_idx
+        ));
+        source.push_str(&format!(
+            "    #[allow(unused_mut)]\n    let mut r: [Register; {}] = [{}];\n",
+            registers.len(),
+            values.join(", ")
+        ));
+
+        let program = learner.get_program();
+        for instruction in program.get_instructions() {
+            // The unknown operation is skipped during the execution
+            let Some(operation) = self.brain.operations.get(instruction.get_op()) else {
+                continue;
+            };
+
+            // The memory instructions are skipped if the brain has no memory
+            if instruction.get_mode() == 2 || instruction.get_mode() == 3 {
+                if self.brain.memory.is_empty() {
+                    continue;
+                }
+                return Err(CodegenError::UnsupportedMemory {
+                    learner: learner.get_id(),
+                });
+            }
+
+            let arithmetic = program.get_arithmetic();
+            let helper = self.operation_helper(operation.name(), operation.kind(), arithmetic)?;
+
+            let dest = i64::from(instruction.get_dst()).rem_euclid(num_registers);
+            if instruction.get_mode() == 0 {
+                let src = i64::from(instruction.get_src()).rem_euclid(num_registers);
+                source.push_str(&format!(
+                    "    r[{}] = {}(r[{}], r[{}]);\n",
+                    dest, helper, dest, src
+                ));
+            } else {
+                source.push_str(&format!(
+                    "    if let Some(y) = input(obs, {}) {{\n        r[{}] = {}(r[{}], y);\n    }}\n",
+                    instruction.get_src(),
+                    dest,
+                    helper,
+                    dest
+                ));
+            }
+        }
+        source.push_str("    r[0]\n}\n\n");
+
+        self.functions.push_str(&source);
+        Ok(())
+    }
+
+    /// Function to write the function of the team and all the teams reachable from it.
+    fn write_team(&mut self, team_idx: usize) -> Result<(), CodegenError> {
+        if self.teams.contains(&team_idx) {
+            return Ok(());
+        }
+        self.teams.push(team_idx);
+
+        let team = &self.brain.teams[team_idx];
+        let team_id = team.get_id();
+        let learners = team.get_learners();
+
+        let mut source = format!("/// Team {}\n", team_id);
+        if learners.is_empty() {
+            source.push_str(&format!(
+                "fn team_{}(_obs: &[Register], _visited: &mut Vec<i32>) -> i32 {{\n    panic!(\"The team {} has no learner\");\n}}\n\n",
+                team_idx, team_id
+            ));
+            self.functions.push_str(&source);
+            return Ok(());
+        }
+
+        source.push_str(&format!(
+            "fn team_{}(obs: &[Register], visited: &mut Vec<i32>) -> i32 {{\n",
+            team_idx
+        ));
+        source.push_str(&format!(
+            "    if visited.contains(&{}) {{\n        panic!(\"Already visited team {{}}!\", {});\n    }}\n    visited.push({});\n\n",
+            team_id, team_id, team_id
+        ));
+
+        // A learner is valid if its action is atomic or its team is not already visited
+        let mut bids: Vec<String> = Vec::new();
+        let mut valid: Vec<String> = Vec::new();
+        for learner_idx in learners.iter() {
+            let action = &self.brain.actions[self.brain.learners[*learner_idx].get_idx_action()];
+            bids.push(format!("learner_{}(obs)", learner_idx));
+            valid.push(match action.get_action_team() {
+                Some(next_team) => {
+                    format!("!visited.contains(&{})", self.brain.teams[next_team].get_id())
+                }
+                None => "true".to_string(),
+            });
+        }
+        source.push_str(&format!(
+            "    let bids: [Register; {}] = [{}];\n",
+            learners.len(),
+            bids.join(", ")
+        ));
+        source.push_str(&format!(
+            "    let valid: [bool; {}] = [{}];\n",
+            learners.len(),
+            valid.join(", ")
+        ));
+        source.push_str(
+            "    let mut top_valid: Option<usize> = None;\n    for i in 0..bids.len() {\n        if valid[i] && top_valid.map_or(true, |j| bids[i] > bids[j]) {\n            top_valid = Some(i);\n        }\n    }\n\n",
+        );
+
+        // The default action of the brain if no learner is valid (see `Team::act`)
+        source.push_str("    match top_valid {\n");
+        let mut next_teams: Vec<usize> = Vec::new();
+        for (i, learner_idx) in learners.iter().enumerate() {
+            let action = &self.brain.actions[self.brain.learners[*learner_idx].get_idx_action()];
+            match action.get_action_team() {
+                Some(next_team) => {
+                    source.push_str(&format!(
+                        "        Some({}) => team_{}(obs, visited),\n",
+                        i, next_team
+                    ));
+                    next_teams.push(next_team);
+                }
+                None => {
+                    let code = action.get_action_code();
+                    source.push_str(&format!("        Some({}) => {},\n", i, code));
+                }
+            }
+        }
+        source.push_str(&format!(
+            "        Some(_) => unreachable!(),\n        None => {},\n    }}\n}}\n\n",
+            self.brain.default_action
+        ));
+        self.functions.push_str(&source);
+
+        for learner_idx in learners {
+            self.write_learner(learner_idx)?;
+        }
+        for next_team in next_teams {
+            self.write_team(next_team)?;
+        }
+        Ok(())
+    }
+}
+
+impl<T: Register> Agent<T> {
+    /// Function to generate the Rust source of a standalone module for the agent.
+    /// The module contains a function `pub fn act(obs: &[Register]) -> i32` with `Register`
+    /// the type of the register of the brain.
+    pub fn to_rust_source(&self, brain: &Brain<T>) -> Result<String, CodegenError> {
+        let mut generator = Generator::new(brain)?;
+        generator.write_team(self.get_idx_teams())?;
+
+        let root_id = brain.teams[self.get_idx_teams()].get_id();
+
+        let mut source = String::new();
+        source.push_str(&format!(
+            "//! Agent generated from the Tangled Program Graph (TPG) with the root team {}.\n",
+            root_id
+        ));
+        source.push_str("//! Call `act` with the observation to get the action code.\n\n");
+        source.push_str(&format!(
+            "/// The type of the observation and of the registers.\npub type Register = {};\n\n",
+            generator.type_name
+        ));
+        source.push_str(
+            "/// Function to get the input with the index (modulo the size of the input).\n#[allow(dead_code)]\nfn input(obs: &[Register], index: i64) -> Option<Register> {\n    if obs.is_empty() {\n        None\n    } else {\n        Some(obs[index.rem_euclid(obs.len() as i64) as usize])\n    }\n}\n\n",
+        );
+        source.push_str(&generator.helpers);
+        source.push_str(&generator.functions);
+        source.push_str(&format!(
+            "/// Function to get the action of the agent for the observation.\npub fn act(obs: &[Register]) -> i32 {{\n    let mut visited: Vec<i32> = Vec::new();\n    team_{}(obs, &mut visited)\n}}\n",
+            self.get_idx_teams()
+        ));
+        Ok(source)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::brain::brain_from_assembly_with_params;
+    use crate::{ArithmeticPolicy, TeamParams};
+    use std::process::Command;
+
+    /// Function to create a brain with two teams (the root team call the second team).
+    fn get_brain<T: Register>(arithmetic: ArithmeticPolicy) -> Brain<T> {
+        let mut params = TeamParams::default();
+        params.learner.program.arithmetic = arithmetic;
+        let programs = [
+            ("r0 = r0 + in[0]\nr1 = r1 - in[2]\nr0 = r0 / r1", 7),
+            ("r0 = r0 + in[1]\nr0 = double(r0)", -1),
+            ("r0 = r0 - in[2]\nr0 = r0 * in[0]", 9),
+            ("r0 = r0 + in[2]\nr0 = neg_if_less(r0, in[0])\nr0 = halve(r0)", 4),
+        ];
+        brain_from_assembly_with_params(&mut params, &programs, &[&[0, 1], &[2, 3]], &[(1, 1)])
+    }
+
+    /// Function to compile and run the source of the agent on the observations.
+    /// Return the action of the agent for each observation.
+    fn run_source<T: Register>(source: &str, observations: &[Vec<T>], name: &str) -> Vec<i32> {
+        let mut main = String::from(source);
+        main.push_str("\nfn main() {\n");
+        for obs in observations {
+            let values: Vec<String> = obs.iter().map(|v| literal(*v, "Register")).collect();
+            main.push_str(&format!("    println!(\"{{}}\", act(&[{}]));\n", values.join(", ")));
+        }
+        main.push_str("}\n");
+
+        let dir = std::env::temp_dir().join(format!("tpg_codegen_{}_{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let source_path = dir.join("agent.rs");
+        let binary_path = dir.join("agent");
+        std::fs::write(&source_path, main).unwrap();
+
+        let status = Command::new("rustc")
+            .arg("--edition=2021")
+            .arg("-o")
+            .arg(&binary_path)
+            .arg(&source_path)
+            .status()
+            .unwrap();
+        assert!(status.success(), "The generated source doesn't compile");
+
+        let output = Command::new(&binary_path).output().unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        String::from_utf8(output.stdout)
+            .unwrap()
+            .lines()
+            .map(|line| line.parse::<i32>().unwrap())
+            .collect()
+    }
+
+    /// Function to check the generated source give the same actions as the agent.
+    /// Return the actions of the agent.
+    fn check_source<T: Register>(brain: &Brain<T>, name: &str) -> Vec<i32> {
+        let mut agent = Agent::new(0, brain);
+        let source = agent.to_rust_source(brain).unwrap();
+
+        let values = [0.0, 1.0, -3.0, 2.5, 1e6, -7.0, 2147483647.0];
+        let mut observations: Vec<Vec<T>> = Vec::new();
+        for a in values {
+            for b in values {
+                observations.push(vec![T::from_f64(a), T::from_f64(b), T::from_f64(b - a)]);
+            }
+        }
+
+        let expected: Vec<i32> = observations
+            .iter()
+            .map(|obs| agent.act(brain, obs).unwrap())
+            .collect();
+        assert_eq!(
+            run_source(&source, &observations, name),
+            expected,
+            "The generated source give different actions"
+        );
+        expected
+    }
+
+    #[test]
+    fn test_codegen_agent() {
+        check_source::<i32>(&get_brain(ArithmeticPolicy::Wrapping), "i32");
+        let checked = ArithmeticPolicy::Checked { fallback: 5.0 };
+        check_source::<i32>(&get_brain(checked), "i32_checked");
+        check_source::<f64>(&get_brain(ArithmeticPolicy::Saturating), "f64");
+    }
+
+    #[test]
+    fn test_codegen_fallback() {
+        // Every learner of the team 1 call back the root team 0 (no valid learner in the team 1)
+        let mut brain: Brain<f64> = get_brain(ArithmeticPolicy::Saturating);
+        brain.actions[2].set_action_team(Some(0));
+        brain.actions[3].set_action_team(Some(0));
+        brain.default_action = 3;
+
+        // Check the generated source end with the default action like the agent
+        let actions = check_source(&brain, "fallback");
+        assert!(actions.contains(&3), "The default action is never taken");
+    }
+
+    #[test]
+    fn test_codegen_error() {
+        let mut brain: Brain<f32> = get_brain(ArithmeticPolicy::Wrapping);
+        brain.operations = crate::OperationSet::new();
+        brain.operations.add_operation(Box::new(Unknown));
+        let agent = Agent::new(0, &brain);
+
+        // Check the operation unknown by the generator
+        assert_eq!(
+            agent.to_rust_source(&brain),
+            Err(CodegenError::UnsupportedOperation("unknown".to_string())),
+            "The unknown operation is generated"
+        );
+    }
+
+    /// Operation unknown by the generator.
+    struct Unknown;
+
+    impl crate::Operation<f32> for Unknown {
+        fn name(&self) -> &str {
+            "unknown"
+        }
+
+        fn arity(&self) -> usize {
+            2
+        }
+
+        fn apply(&self, x: f32, _y: f32, _policy: ArithmeticPolicy) -> f32 {
+            x
+        }
+    }
+}
@@ -0,0 +1,408 @@
+//! # Compiled
+//! Crate that permit to compile the programs of the Tangled Program Graph (TPG) for the inference.
+//! The compiled program is resolved once for a size of input, a number of registers and a size of memory:
+//!     - the index of the source and the destination are already wrapped,
+//!     - the mode is replaced by the kind of the source and the destination,
+//!     - the instructions with an unknown operation (or using an empty input or memory) are removed.
+//! So the execution only read the operands and apply the operation.
+//! The compiled program is only executed with the operation set used to compile it.
+
+#![deny(missing_docs)]
+#![deny(clippy::all)]
+#![deny(clippy::missing_docs_in_private_items)]
+
+use crate::program::wrap_index;
+use crate::{ArithmeticPolicy, Memory, OperationSet, Program, Register};
+use std::fmt;
+use std::mem::size_of;
+use std::sync::{Arc, PoisonError, RwLock};
+
+/// Enumeration to represent an error during the execution of a compiled program.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CompiledError {
+    /// The operation set is not the operation set used to compile the program.
+    OperationMismatch,
+    /// The program is compiled for other sizes (the sizes of the execution).
+    SizeMismatch {
+        /// The size of the input.
+        input_len: usize,
+        /// The number of registers.
+        num_registers: usize,
+        /// The size of the memory.
+        memory_len: usize,
+    },
+}
+
+impl fmt::Display for CompiledError {
+    /// Function to display the error.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CompiledError::OperationMismatch => {
+                write!(f, "the program is compiled for an other operation set")
+            }
+            CompiledError::SizeMismatch {
+                input_len,
+                num_registers,
+                memory_len,
+            } => write!(
+                f,
+                "the program is not compiled for {} inputs, {} registers and {} memory cells",
+                input_len, num_registers, memory_len
+            ),
+        }
+    }
+}
+
+impl std::error::Error for CompiledError {}
+
+/// Enumeration to represent the source of a compiled instruction.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Source {
+    /// The register with the index.
+    Register(usize),
+    /// The input with the index.
+    Input(usize),
+    /// The memory with the index.
+    Memory(usize),
+}
+
+/// Enumeration to represent the destination of a compiled instruction.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Destination {
+    /// The register with the index.
+    Register(usize),
+    /// The memory with the index (written with the probability of write).
+    Memory(usize),
+}
+
+/// Structure to represent an instruction with the operands resolved.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct CompiledInstruction {
+    /// The index of the operation in the OperationSet.
+    op: usize,
+    /// The source of the instruction.
+    src: Source,
+    /// The destination of the instruction.
+    dst: Destination,
+}
+
+/// Structure to represent a program compiled for a size of input and a number of registers.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CompiledProgram {
+    /// The list of resolved instructions.
+    instructions: Vec<CompiledInstruction>,
+    /// The arithmetic used by the operations of the program.
+    arithmetic: ArithmeticPolicy,
+    /// The size of the input used to compile the program.
+    input_len: usize,
+    /// The number of registers used to compile the program.
+    num_registers: usize,
+    /// The size of the memory used to compile the program.
+    memory_len: usize,
+    /// The version of the operation set used to compile the program.
+    operations_version: u64,
+}
+
+impl CompiledProgram {
+    /// Function to get the number of instruction inside the compiled program.
+    pub fn get_len_program(&self) -> usize {
+        self.instructions.len()
+    }
+
+    /// Function to get the number of bytes used by the instructions of the compiled program.
+    pub(crate) fn get_bytes(&self) -> usize {
+        self.instructions.len() * size_of::<CompiledInstruction>()
+    }
+
+    /// Function to check if the program is compiled for the operations, the size of input,
+    /// the number of registers and the size of memory.
+    pub fn is_compiled_for<T: Register>(
+        &self,
+        operations: &OperationSet<T>,
+        input_len: usize,
+        num_registers: usize,
+        memory_len: usize,
+    ) -> bool {
+        self.input_len == input_len
+            && self.num_registers == num_registers
+            && self.memory_len == memory_len
+            && self.operations_version == operations.get_version()
+    }
+
+    /// Function to execute the compiled program.
+    /// Give the same result as `Program::execute_with_memory` on the program.
+    /// Return an error if the program is not compiled for the operations, the input,
+    /// the registers and the memory.
+    pub fn execute<T: Register>(
+        &self,
+        operations: &OperationSet<T>,
+        input: &[T],
+        regs: &mut [T],
+        memory: &mut Memory<T>,
+    ) -> Result<(), CompiledError> {
+        if self.operations_version != operations.get_version() {
+            return Err(CompiledError::OperationMismatch);
+        }
+        if !self.is_compiled_for(operations, input.len(), regs.len(), memory.get_size()) {
+            return Err(CompiledError::SizeMismatch {
+                input_len: input.len(),
+                num_registers: regs.len(),
+                memory_len: memory.get_size(),
+            });
+        }
+        self.execute_resolved(operations, input, regs, memory);
+        Ok(())
+    }
+
+    /// Function to execute the compiled program already checked with `is_compiled_for`
+    /// (e.g. by `Learner::bid`).
+    pub(crate) fn execute_resolved<T: Register>(
+        &self,
+        operations: &OperationSet<T>,
+        input: &[T],
+        regs: &mut [T],
+        memory: &mut Memory<T>,
+    ) {
+        for instruction in self.instructions.iter() {
+            let y = match instruction.src {
+                Source::Register(src) => regs[src],
+                Source::Input(src) => input[src],
+                Source::Memory(src) => memory.get_values()[src],
+            };
+            let operation = operations.get_resolved(instruction.op);
+            match instruction.dst {
+                Destination::Register(dst) => {
+                    regs[dst] = operation.apply(regs[dst], y, self.arithmetic);
+                }
+                Destination::Memory(dst) => {
+                    let x = memory.get_values()[dst];
+                    memory.write(dst as i32, operation.apply(x, y, self.arithmetic));
+                }
+            }
+        }
+    }
+}
+
+/// Structure to keep the compiled program of a learner.
+/// The program is compiled again when the size of the input, the number of registers, the size
+/// of the memory or the operations change (e.g. the same brain on two environments).
+#[derive(Debug, Default)]
+pub(crate) struct CompiledCache {
+    /// The last compiled program (None until the first compilation or after a reset).
+    compiled: RwLock<Option<Arc<CompiledProgram>>>,
+}
+
+impl Clone for CompiledCache {
+    /// Function to clone the cache (the compiled program is shared).
+    fn clone(&self) -> Self {
+        CompiledCache {
+            compiled: RwLock::new(self.get()),
+        }
+    }
+}
+
+impl CompiledCache {
+    /// Function to get the last compiled program.
+    pub(crate) fn get(&self) -> Option<Arc<CompiledProgram>> {
+        self.compiled.read().unwrap_or_else(PoisonError::into_inner).clone()
+    }
+
+    /// Function to get the program compiled for the sizes, the program is compiled with the
+    /// function if the last compiled program is for other sizes.
+    pub(crate) fn get_or_compile<T: Register>(
+        &self,
+        operations: &OperationSet<T>,
+        sizes: (usize, usize, usize),
+        compile: impl FnOnce() -> CompiledProgram,
+    ) -> Arc<CompiledProgram> {
+        let (input_len, num_registers, memory_len) = sizes;
+        if let Some(compiled) = self.get() {
+            if compiled.is_compiled_for(operations, input_len, num_registers, memory_len) {
+                return compiled;
+            }
+        }
+        let compiled = Arc::new(compile());
+        *self.compiled.write().unwrap_or_else(PoisonError::into_inner) = Some(compiled.clone());
+        compiled
+    }
+
+    /// Function to remove the compiled program (e.g. when the program mutate).
+    pub(crate) fn reset(&mut self) {
+        *self.compiled.get_mut().unwrap_or_else(PoisonError::into_inner) = None;
+    }
+}
+
+impl Program {
+    /// Function to compile the program for the size of input, the number of registers
+    /// and the size of memory.
+    pub fn compile<T: Register>(
+        &self,
+        operations: &OperationSet<T>,
+        input_len: usize,
+        num_registers: usize,
+        memory_len: usize,
+    ) -> CompiledProgram {
+        let mut instructions: Vec<CompiledInstruction> = Vec::new();
+        if num_registers > 0 {
+            for instruction in self.get_instructions() {
+                // The unknown operation is skipped during the execution
+                if operations.get(instruction.get_op()).is_none() {
+                    continue;
+                }
+
+                let (src, dst) = match instruction.get_mode() {
+                    0 => (
+                        Source::Register(wrap_index(instruction.get_src(), num_registers)),
+                        Destination::Register(wrap_index(instruction.get_dst(), num_registers)),
+                    ),
+                    2 | 3 if memory_len == 0 => continue,
+                    2 => (
+                        Source::Memory(wrap_index(instruction.get_src(), memory_len)),
+                        Destination::Register(wrap_index(instruction.get_dst(), num_registers)),
+                    ),
+                    3 => (
+                        Source::Register(wrap_index(instruction.get_src(), num_registers)),
+                        Destination::Memory(wrap_index(instruction.get_dst(), memory_len)),
+                    ),
+                    _ if input_len == 0 => continue,
+                    _ => (
+                        Source::Input(wrap_index(instruction.get_src(), input_len)),
+                        Destination::Register(wrap_index(instruction.get_dst(), num_registers)),
+                    ),
+                };
+
+                instructions.push(CompiledInstruction {
+                    op: instruction.get_op() as usize,
+                    src,
+                    dst,
+                });
+            }
+        }
+
+        CompiledProgram {
+            instructions,
+            arithmetic: self.get_arithmetic(),
+            input_len,
+            num_registers,
+            memory_len,
+            operations_version: operations.get_version(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Instruction, Learner, LearnerParams, ProgramParams};
+
+    /// Function to get the params used by the tests.
+    fn get_params() -> ProgramParams {
+        ProgramParams {
+            max_program_length: 30,
+            input_size: 12,
+            nb_destinations: 12,
+            ..ProgramParams::default()
+        }
+    }
+
+    #[test]
+    fn test_compiled_program() {
+        let mut params = get_params();
+        params.memory_size = 6;
+        params.p_memory_write = 1.0;
+        let operations: OperationSet<i32> = OperationSet::default();
+
+        for (input_len, memory_len) in [(0, 0), (1, 6), (5, 0), (5, 6)] {
+            for _ in 0..20 {
+                let program = Program::new(None, &mut params, &operations);
+                let compiled = program.compile(&operations, input_len, 4, memory_len);
+
+                // Check the compiled program give the same result as the program
+                let input: Vec<i32> = (0..input_len as i32).map(|i| 3 * i - 4).collect();
+                let mut regs1: Vec<i32> = vec![1, -2, 3, 7];
+                let mut regs2: Vec<i32> = vec![1, -2, 3, 7];
+                let mut memory1: Memory<i32> = Memory::new(memory_len, 1.0);
+                let mut memory2: Memory<i32> = Memory::new(memory_len, 1.0);
+                program.execute_with_memory(&operations, &input, &mut regs1, &mut memory1);
+                compiled.execute(&operations, &input, &mut regs2, &mut memory2).unwrap();
+                assert_eq!(regs1, regs2, "The compiled program give a different result");
+                assert_eq!(memory1, memory2, "The compiled program give a different memory");
+            }
+        }
+
+        // Check the unknown operation, the empty input and the empty memory are removed
+        let mut program = Program::new(Some(Vec::new()), &mut params, &operations);
+        program.set_instructions(Instruction::new(0, 8, 1, 0));
+        program.set_instructions(Instruction::new(1, 0, 1, 0));
+        program.set_instructions(Instruction::new(3, 0, 1, 0));
+        program.set_instructions(Instruction::new(0, 0, 13, -1));
+        assert_eq!(
+            program.compile(&operations, 0, 4, 0).get_len_program(),
+            1,
+            "The number of compiled instruction is incorrect"
+        );
+
+        // Check the execution with other sizes or an other operation set return an error
+        let compiled = program.compile(&operations, 0, 4, 0);
+        let mut regs: Vec<i32> = vec![1, 2, 3, 4];
+        let result = compiled.execute(&operations, &[5], &mut regs, &mut Memory::default());
+        let error = CompiledError::SizeMismatch {
+            input_len: 1,
+            num_registers: 4,
+            memory_len: 0,
+        };
+        assert_eq!(result, Err(error), "The program is executed with other sizes");
+        let other_operations: OperationSet<i32> = OperationSet::default();
+        let result = compiled.execute(&other_operations, &[], &mut regs, &mut Memory::default());
+        let error = CompiledError::OperationMismatch;
+        assert_eq!(result, Err(error), "The program is executed with other operations");
+        assert_eq!(regs, vec![1, 2, 3, 4], "The registers are changed by the error");
+    }
+
+    #[test]
+    fn test_compiled_learner() {
+        let mut params = LearnerParams {
+            program: get_params(),
+            ..LearnerParams::default()
+        };
+        let operations: OperationSet<f64> = OperationSet::default();
+        let program = Program::new(None, &mut params.program, &operations);
+        let learner: Learner<f64> = Learner::new(&mut params, program.clone(), 0, 4);
+
+        // Check the program is compiled again when the size of the input change
+        for input in [vec![1.5, -2.0, 3.0], vec![0.5; 7], vec![-1.0, 4.0]] {
+            let mut regs: Vec<f64> = learner.get_registers().to_vec();
+            program.execute(&operations, &input, &mut regs);
+            let mut regs_bid: Vec<f64> = learner.get_registers().to_vec();
+            let bid = learner.bid(&operations, &input, &mut regs_bid, &mut Memory::default());
+            assert_eq!(bid, regs[0], "The bid of the compiled program is incorrect");
+            let is_compiled = learner.is_compiled_for(&operations, input.len(), 0);
+            assert!(is_compiled, "The bid don't execute the compiled program");
+        }
+        assert!(!learner.is_compiled_for(&operations, 7, 0), "The program is not compiled again");
+
+        // Check the program is compiled again with an other operation set of the same size
+        let mut operations: OperationSet<f64> = OperationSet::new();
+        operations.add_operation(Box::new(crate::Subtraction));
+        operations.add_operation(Box::new(crate::Addition));
+        operations.add_operation(Box::new(crate::Multiplication));
+        operations.add_operation(Box::new(crate::Maximum));
+        operations.add_operation(Box::new(crate::Minimum));
+        operations.add_operation(Box::new(crate::Double));
+        operations.add_operation(Box::new(crate::Halve));
+        let mut regs: Vec<f64> = learner.get_registers().to_vec();
+        let bid = learner.bid(&operations, &[-1.0, 4.0], &mut regs, &mut Memory::default());
+        let mut regs: Vec<f64> = learner.get_registers().to_vec();
+        program.execute(&operations, &[-1.0, 4.0], &mut regs);
+        assert_eq!(bid, regs[0], "The bid use the operations of the previous compilation");
+        assert!(learner.is_compiled_for(&operations, 2, 0), "The program is not compiled");
+        let operations: OperationSet<f64> = OperationSet::default();
+
+        // Check the compilation before the bid and the reset of the compiled program
+        let mut learner: Learner<f64> = Learner::new(&mut params, program, 0, 4);
+        learner.compile(&operations, 3, 0);
+        assert!(learner.is_compiled_for(&operations, 3, 0), "The program is not compiled");
+        learner.set_strip_introns(true);
+        assert!(!learner.is_compiled_for(&operations, 3, 0), "The compiled program is not reset");
+    }
+}
@@ -0,0 +1,157 @@
+//! # Context
+//! Crate that permit to represent the execution context of an agent of the Tangled Program Graph (TPG).
+//! The brain is only read during the inference, the mutable state of the agent is kept in the context:
+//!     - the memory read and written by the programs,
+//!     - the registers of the learners with persistent registers (by index of learner),
+//!     - the teams already visited during the decision.
+//! So a decision only cost the teams and the learners visited, and several agents can act on the
+//! same brain with their own context.
+
+#![deny(missing_docs)]
+#![deny(clippy::all)]
+#![deny(clippy::missing_docs_in_private_items)]
+
+use crate::{Brain, GarbageReport, Memory, Register, TeamTrace};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Structure to represent the mutable state of an agent during the inference.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(bound = "T: Register")]
+pub struct ExecutionContext<T: Register> {
+    /// The memory read and written by the programs across the decisions.
+    memory: Memory<T>,
+    /// The registers of the learners with persistent registers (index of learner -> registers).
+    registers: HashMap<usize, Vec<T>>,
+    /// The registers used by the bid of the learners without persistent registers.
+    #[serde(skip)]
+    scratch: Vec<T>,
+    /// The id of the teams visited during the current decision.
+    #[serde(skip)]
+    pub(crate) visited: Vec<i32>,
+    /// The visit of the teams during the current decision (None if the decision is not traced).
+    #[serde(skip)]
+    pub(crate) trace: Option<Vec<TeamTrace<T>>>,
+    /// The index of the team called by the agent or the action (recorded by the trace).
+    #[serde(skip)]
+    pub(crate) team: usize,
+}
+
+impl<T: Register> ExecutionContext<T> {
+    /// Constructor of the execution context with the memory.
+    pub fn new(memory: Memory<T>) -> Self {
+        ExecutionContext {
+            memory,
+            ..ExecutionContext::default()
+        }
+    }
+
+    /// Function to get the memory of the context.
+    pub fn get_memory(&self) -> &Memory<T> {
+        &self.memory
+    }
+
+    /// Function to reset the memory to zero (e.g. at the start of an episode).
+    pub fn reset_memory(&mut self) {
+        self.memory.reset();
+    }
+
+    /// Function to get the registers kept for the learner.
+    /// Return None if the learner has not bid with persistent registers since the last reset.
+    pub fn get_registers(&self, learner_idx: usize) -> Option<&[T]> {
+        self.registers.get(&learner_idx).map(|registers| registers.as_slice())
+    }
+
+    /// Function to reset the registers of all the learners
+    /// (the next bid start again from the registers of the learner in the brain).
+    pub fn zero_registers(&mut self) {
+        self.registers.clear();
+    }
+
+    /// Function to change the index of the learners of the kept registers with the report of a
+    /// garbage collection of the brain (the registers of the removed learners are removed).
+    pub fn remap(&mut self, report: &GarbageReport) {
+        self.remap_registers(&|idx: usize| report.get_new_learner_index(idx));
+    }
+
+    /// Function to change the index of the learners of the kept registers
+    /// (the registers of the learners remapped to None are removed).
+    pub(crate) fn remap_registers(&mut self, remap: &impl Fn(usize) -> Option<usize>) {
+        self.registers = self
+            .registers
+            .drain()
+            .filter_map(|(idx, registers)| remap(idx).map(|idx| (idx, registers)))
+            .collect();
+    }
+
+    /// Function to get the bid of the learner (index in the brain) with the state.
+    /// If the registers of the learner are persistent
+    ///     |-> The program is executed on the registers kept in the context
+    /// Else
+    ///     |-> The program is executed on a copy of the registers of the learner
+    pub fn bid(&mut self, brain: &Brain<T>, learner_idx: usize, state: &[T]) -> T {
+        let learner = &brain.learners[learner_idx];
+        let registers = if learner.has_persistent_registers() {
+            self.registers
+                .entry(learner_idx)
+                .or_insert_with(|| learner.get_registers().to_vec())
+        } else {
+            self.scratch.clear();
+            self.scratch.extend_from_slice(learner.get_registers());
+            &mut self.scratch
+        };
+        learner.bid(&brain.operations, state, registers, &mut self.memory)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::brain::brain_from_assembly;
+    use crate::Agent;
+
+    #[test]
+    fn test_execution_context() {
+        // The learner 0 count the decisions, the learner 1 bid the input
+        let programs = [("r1 = r1 + in[1]\nr0 = r0 + r1", 1), ("r0 = r0 + in[0]", 2)];
+        let mut brain: Brain<i32> = brain_from_assembly(&programs, &[&[0, 1]], &[]);
+        brain.learners[0].set_persistent_registers(true);
+
+        // Check each context keep its own registers (the brain is not changed)
+        let agent = Agent::new(0, &brain);
+        let mut context1: ExecutionContext<i32> = ExecutionContext::default();
+        let mut context2: ExecutionContext<i32> = ExecutionContext::default();
+        let actions: Vec<i32> = (0..3)
+            .map(|_| agent.act_with_context(&brain, &[4, 1], &mut context1).unwrap())
+            .collect();
+        assert_eq!(actions, vec![2, 2, 1], "The actions of the first context are incorrect");
+        assert_eq!(
+            agent.act_with_context(&brain, &[4, 1], &mut context2).unwrap(),
+            2,
+            "The action of the second context is incorrect"
+        );
+        assert_eq!(context1.get_registers(0), Some(&[6, 3][..]), "The registers are incorrect");
+        assert_eq!(context1.get_registers(1), None, "The registers are kept");
+        assert_eq!(brain.learners[0].get_registers(), &[0, 0], "The brain is changed");
+
+        // Check the reset of the registers
+        context1.zero_registers();
+        assert_eq!(context1.get_registers(0), None, "The registers are not reset");
+        assert_eq!(context1.bid(&brain, 0, &[4, 1]), 1, "The bid after reset is incorrect");
+
+        // Check two agents sharing the persistent learner don't share their registers
+        let mut agent1 = Agent::new(0, &brain);
+        let mut agent2 = Agent::new(0, &brain);
+        for _ in 0..3 {
+            agent1.act(&brain, &[4, 1]).unwrap();
+        }
+        assert_eq!(agent2.act(&brain, &[4, 1]).unwrap(), 2, "The registers are shared");
+        let registers1 = agent1.get_context().get_registers(0);
+        assert_eq!(registers1, Some(&[6, 3][..]), "The registers of the agent are incorrect");
+        let registers2 = agent2.get_context().get_registers(0);
+        assert_eq!(registers2, Some(&[1, 1][..]), "The registers of the agent are incorrect");
+        agent2.zero_registers();
+        let registers1 = agent1.get_context().get_registers(0);
+        assert_eq!(registers1, Some(&[6, 3][..]), "The reset change an other agent");
+    }
+}
@@ -0,0 +1,224 @@
+//! # Dot
+//! Crate that permit to export the brain of the Tangled Program Graph (TPG) in the DOT language
+//! of Graphviz (e.g. `dot -Tsvg brain.dot -o brain.svg`).
+//! The teams are boxes, the learners are ellipses and the atomic actions are diamonds
+//! (one node by action code). The edges go from a team to its learners, and from a learner
+//! to its action code or to the team of its action.
+//! The export can be restricted to the graph reachable from an agent, and the path taken by the
+//! agent for an observation can be highlighted.
+
+#![deny(missing_docs)]
+#![deny(clippy::all)]
+#![deny(clippy::missing_docs_in_private_items)]
+
+use crate::{Agent, Brain, Register};
+use std::collections::BTreeSet;
+use std::fmt::Write;
+
+/// The attributes of the highlighted nodes and edges.
+const HIGHLIGHT: &str = "color=red, penwidth=2";
+
+/// Structure to represent the path taken by an agent (the nodes and the edges to highlight).
+#[derive(Default)]
+struct DotPath {
+    /// The index of the visited teams.
+    teams: Vec<usize>,
+    /// The index of the learners followed in the visited teams.
+    learners: Vec<usize>,
+    /// The atomic action at the end of the path
+    /// (None if the decision end with the default action of the brain, see `Team::act`).
+    action_code: Option<i32>,
+}
+
+impl<T: Register> Brain<T> {
+    /// Function to export all the teams of the brain in the DOT language.
+    pub fn to_dot(&self) -> String {
+        let teams: Vec<usize> = (0..self.teams.len()).collect();
+        self.write_dot(&teams, &DotPath::default())
+    }
+
+    /// Function to export the teams reachable from the team of the agent in the DOT language.
+    pub fn to_dot_from_agent(&self, agent: &Agent<T>) -> String {
+        let teams = self.get_reachable_teams(agent.get_idx_teams());
+        self.write_dot(&teams, &DotPath::default())
+    }
+
+    /// Function to export the teams reachable from the team of the agent in the DOT language
+    /// with the path taken by the agent for the state highlighted.
+    /// The decision use a copy of the execution context of the agent (the agent is not changed).
+    pub fn to_dot_with_path(&self, agent: &Agent<T>, state: &[T]) -> String {
+        let teams = self.get_reachable_teams(agent.get_idx_teams());
+        let path = self.get_dot_path(agent, state);
+        self.write_dot(&teams, &path)
+    }
+
+    /// Function to get the path taken by the agent for the state (with a copy of the execution
+    /// context of the agent). The path is empty if the agent can't act.
+    /// The default action of a fallback is not an action of a learner, so it's not highlighted.
+    fn get_dot_path(&self, agent: &Agent<T>, state: &[T]) -> DotPath {
+        let mut context = agent.get_context().clone();
+        match agent.act_with_context_and_trace(self, state, &mut context) {
+            Ok(trace) => DotPath {
+                teams: trace.get_visited_teams(),
+                learners: trace.get_winners(),
+                action_code: (!trace.is_fallback()).then(|| trace.get_action()),
+            },
+            Err(_) => DotPath::default(),
+        }
+    }
+
+    /// Function to write the teams, their learners and their actions in the DOT language.
+    fn write_dot(&self, teams: &[usize], path: &DotPath) -> String {
+        let mut dot = String::from("digraph tpg {\n");
+        let mut learners: BTreeSet<usize> = BTreeSet::new();
+        let mut action_codes: BTreeSet<i32> = BTreeSet::new();
+
+        for team_idx in teams {
+            let team = &self.teams[*team_idx];
+            let _ = writeln!(
+                dot,
+                "    \"t{}\" [shape=box, label=\"team {}\"{}];",
+                team_idx,
+                team.get_id(),
+                node_style(path.teams.contains(team_idx))
+            );
+            for learner_idx in team.get_learners() {
+                learners.insert(learner_idx);
+                let mut steps = path.teams.iter().zip(&path.learners);
+                let on_path = steps.any(|step| step == (team_idx, &learner_idx));
+                let _ = writeln!(
+                    dot,
+                    "    \"t{}\" -> \"l{}\"{};",
+                    team_idx,
+                    learner_idx,
+                    edge_style(on_path)
+                );
+            }
+        }
+
+        for learner_idx in learners {
+            let learner = &self.learners[learner_idx];
+            let on_path = path.learners.contains(&learner_idx);
+            let _ = writeln!(
+                dot,
+                "    \"l{}\" [shape=ellipse, label=\"learner {}\"{}];",
+                learner_idx,
+                learner.get_id(),
+                node_style(on_path)
+            );
+            let action = &self.actions[learner.get_idx_action()];
+            let target = match action.get_action_team() {
+                Some(team_idx) => format!("t{}", team_idx),
+                None => {
+                    action_codes.insert(action.get_action_code());
+                    format!("a{}", action.get_action_code())
+                }
+            };
+            let _ = writeln!(
+                dot,
+                "    \"l{}\" -> \"{}\"{};",
+                learner_idx,
+                target,
+                edge_style(on_path)
+            );
+        }
+
+        for code in action_codes {
+            let _ = writeln!(
+                dot,
+                "    \"a{}\" [shape=diamond, label=\"action {}\"{}];",
+                code,
+                code,
+                node_style(path.action_code == Some(code))
+            );
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+/// Function to get the attributes added to a node (highlighted if the node is on the path).
+fn node_style(on_path: bool) -> String {
+    if on_path {
+        format!(", {}", HIGHLIGHT)
+    } else {
+        String::new()
+    }
+}
+
+/// Function to get the attributes of an edge (highlighted if the edge is on the path).
+fn edge_style(on_path: bool) -> String {
+    if on_path {
+        format!(" [{}]", HIGHLIGHT)
+    } else {
+        String::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::brain::brain_from_assembly;
+
+    #[test]
+    fn test_to_dot() {
+        // The team 0 is not used, the root team 1 call the team 2 with the learner 2
+        let programs = [
+            ("r0 = r0 + in[0]", 10),
+            ("r0 = r0 - in[0]", 11),
+            ("r0 = r0 + in[1]", 12),
+            ("r0 = r0 + in[0]", 13),
+            ("r0 = r0 - in[0]", 14),
+        ];
+        let brain: Brain<i32> =
+            brain_from_assembly(&programs, &[&[0, 1], &[1, 2], &[3, 4]], &[(2, 2)]);
+
+        // Check the shapes and the edges of the whole brain
+        let dot = brain.to_dot();
+        assert!(dot.starts_with("digraph tpg {\n"), "The header is incorrect");
+        assert!(dot.contains("\"t0\" [shape=box, label=\"team 0\"];"), "The team is incorrect");
+        assert!(dot.contains("\"l2\" [shape=ellipse"), "The learner is incorrect");
+        assert!(dot.contains("\"a10\" [shape=diamond"), "The action is incorrect");
+        assert!(dot.contains("\"t1\" -> \"l2\";"), "The edge to the learner is incorrect");
+        assert!(dot.contains("\"l2\" -> \"t2\";"), "The edge to the team is incorrect");
+        assert!(dot.contains("\"l3\" -> \"a13\";"), "The edge to the action is incorrect");
+
+        // Check the agent only export the reachable teams
+        let agent = Agent::new(1, &brain);
+        let dot = brain.to_dot_from_agent(&agent);
+        assert!(!dot.contains("\"t0\""), "The unreachable team is exported");
+        assert!(!dot.contains("\"l0\""), "The unreachable learner is exported");
+        assert!(dot.contains("\"t2\" -> \"l4\";"), "The reachable team is not exported");
+
+        // Check the path: the learner 2 win in the team 1, the learner 3 win in the team 2
+        let dot = brain.to_dot_with_path(&agent, &[1, 5]);
+        assert!(dot.contains("\"t1\" -> \"l2\" [color=red"), "The path is not highlighted");
+        assert!(dot.contains("\"l2\" -> \"t2\" [color=red"), "The path is not highlighted");
+        assert!(dot.contains("\"t2\" -> \"l3\" [color=red"), "The path is not highlighted");
+        assert!(dot.contains("label=\"action 13\", color=red"), "The action is not highlighted");
+        assert!(dot.contains("\"t1\" -> \"l1\";"), "The edge outside the path is highlighted");
+        let mut agent = agent;
+        let action = agent.act(&brain, &[1, 5]).unwrap();
+        assert_eq!(action, 13, "The action of the path is incorrect");
+    }
+
+    #[test]
+    fn test_to_dot_fallback() {
+        // The learner 0 of the root team 0 call the team 1, the learner 1 call the team 0 back
+        let programs = [("r0 = r0 + in[0]", 10), ("r0 = r0 + in[0]", 11), ("r0 = r0 - in[0]", 13)];
+        let mut brain: Brain<i32> =
+            brain_from_assembly(&programs, &[&[0, 2], &[1]], &[(0, 1), (1, 0)]);
+        brain.default_action = 13;
+
+        // Check the fallback in the team 1 don't highlight the action of the learner 2
+        let agent = Agent::new(0, &brain);
+        let mut context = agent.get_context().clone();
+        let trace = agent.act_with_context_and_trace(&brain, &[5], &mut context);
+        assert!(trace.unwrap().is_fallback(), "The decision is not a fallback");
+        let dot = brain.to_dot_with_path(&agent, &[5]);
+        assert!(dot.contains("\"t0\" -> \"l0\" [color=red"), "The path is not highlighted");
+        let team = "\"t1\" [shape=box, label=\"team 1\", color=red";
+        assert!(dot.contains(team), "The team of the fallback is not highlighted");
+        assert!(dot.contains("label=\"action 13\"];"), "The default action is highlighted");
+    }
+}
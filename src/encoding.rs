@@ -0,0 +1,557 @@
+//! # Encoding
+//! Crate that permit to store the programs of the Tangled Program Graph (TPG) in a packed binary form.
+//! Each instruction is packed in a `u32` with bit fields sized from the params of the program:
+//!     | dst | src | op | mode |
+//! (the mode is in the lowest bits, 1 bit or 2 bits if the instructions can use the memory)
+//! The packed program can be saved with serde, one number by instruction instead of the four
+//! fields of the instruction.
+//! The learners of a brain can keep their program packed (`Brain::pack_programs`): the program
+//! use 4 bytes by instruction instead of 16, the packed learner bid directly on the packed form
+//! (no compiled program is kept, see `Learner::bid`) and the brain is saved with the packed form.
+
+#![deny(missing_docs)]
+#![deny(clippy::all)]
+#![deny(clippy::missing_docs_in_private_items)]
+
+use crate::program::execute_instruction;
+use crate::{
+    ArithmeticPolicy, Brain, Instruction, Memory, OperationSet, Program, ProgramParams, Register,
+};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::fmt;
+use std::mem::size_of;
+
+/// Enumeration to represent an error while packing a program.
+#[derive(Clone, Debug, PartialEq)]
+pub enum EncodingError {
+    /// The bit fields don't fit in a `u32`.
+    TooManyBits {
+        /// The number of bits needed by the fields.
+        bits: u32,
+    },
+    /// A field of an instruction doesn't fit in the bit field.
+    ValueOutOfRange {
+        /// The index of the instruction in the program.
+        index: usize,
+        /// The name of the field (mode, op, src or dst).
+        field: &'static str,
+        /// The value of the field.
+        value: i32,
+    },
+}
+
+impl fmt::Display for EncodingError {
+    /// Function to display the error.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EncodingError::TooManyBits { bits } => {
+                write!(f, "the instruction need {} bits (max 32)", bits)
+            }
+            EncodingError::ValueOutOfRange {
+                index,
+                field,
+                value,
+            } => write!(
+                f,
+                "the {} of the instruction {} is out of range ({})",
+                field, index, value
+            ),
+        }
+    }
+}
+
+impl std::error::Error for EncodingError {}
+
+/// Function to get the number of bits needed to store the values in 0..size.
+fn bits_for(size: i32) -> u32 {
+    if size <= 1 {
+        0
+    } else {
+        32 - (size - 1).leading_zeros()
+    }
+}
+
+/// Function to get the mask of a bit field.
+fn mask(bits: u32) -> u32 {
+    if bits == 0 {
+        0
+    } else {
+        u32::MAX >> (32 - bits)
+    }
+}
+
+/// Structure to represent the size of the bit fields of the packed instruction.
+/// The size of the bit fields is checked when the encoding is loaded.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "EncodingFields")]
+pub struct Encoding {
+    /// The number of bits of the mode.
+    mode_bits: u32,
+    /// The number of bits of the operation.
+    op_bits: u32,
+    /// The number of bits of the source.
+    src_bits: u32,
+    /// The number of bits of the destination.
+    dst_bits: u32,
+}
+
+/// Structure to represent the size of the bit fields of a loaded encoding (not checked).
+#[derive(Deserialize)]
+struct EncodingFields {
+    /// The number of bits of the mode.
+    mode_bits: u32,
+    /// The number of bits of the operation.
+    op_bits: u32,
+    /// The number of bits of the source.
+    src_bits: u32,
+    /// The number of bits of the destination.
+    dst_bits: u32,
+}
+
+impl TryFrom<EncodingFields> for Encoding {
+    type Error = EncodingError;
+
+    /// Function to check the bit fields fit in a `u32`.
+    fn try_from(fields: EncodingFields) -> Result<Self, Self::Error> {
+        let all_bits = [fields.mode_bits, fields.op_bits, fields.src_bits, fields.dst_bits];
+        let bits = all_bits.iter().fold(0u32, |sum, bits| sum.saturating_add(*bits));
+        if bits > 32 {
+            return Err(EncodingError::TooManyBits { bits });
+        }
+        Ok(Encoding {
+            mode_bits: fields.mode_bits,
+            op_bits: fields.op_bits,
+            src_bits: fields.src_bits,
+            dst_bits: fields.dst_bits,
+        })
+    }
+}
+
+impl Encoding {
+    /// Constructor of the encoding from the params of the program.
+    /// The mode use the number of modes, the operation the size of the operation set, the source
+    /// `input_size` and the destination `nb_destinations`.
+    pub fn new<T: Register>(
+        params: &ProgramParams,
+        operations: &OperationSet<T>,
+    ) -> Result<Self, EncodingError> {
+        let mode_bits = bits_for(params.get_nb_modes());
+        let op_bits = bits_for(operations.len() as i32);
+        let src_bits = bits_for(params.input_size);
+        let dst_bits = bits_for(params.nb_destinations);
+
+        let bits = mode_bits + op_bits + src_bits + dst_bits;
+        if bits > 32 {
+            return Err(EncodingError::TooManyBits { bits });
+        }
+
+        Ok(Encoding {
+            mode_bits,
+            op_bits,
+            src_bits,
+            dst_bits,
+        })
+    }
+
+    /// Function to get the number of bits used by one instruction.
+    pub fn get_nb_bits(&self) -> u32 {
+        self.mode_bits + self.op_bits + self.src_bits + self.dst_bits
+    }
+
+    /// Function to pack an instruction.
+    /// The index is the position of the instruction in the program (used for the error).
+    pub fn encode(&self, instruction: &Instruction, index: usize) -> Result<u32, EncodingError> {
+        let fields = [
+            ("mode", instruction.get_mode(), self.mode_bits),
+            ("op", instruction.get_op(), self.op_bits),
+            ("src", instruction.get_src(), self.src_bits),
+            ("dst", instruction.get_dst(), self.dst_bits),
+        ];
+
+        let mut code: u32 = 0;
+        let mut shift: u32 = 0;
+        for (field, value, bits) in fields {
+            let value_bits = u32::try_from(value)
+                .ok()
+                .filter(|v| *v <= mask(bits))
+                .ok_or(EncodingError::ValueOutOfRange {
+                    index,
+                    field,
+                    value,
+                })?;
+            code |= value_bits << shift;
+            shift += bits;
+        }
+        Ok(code)
+    }
+
+    /// Function to unpack an instruction.
+    pub fn decode(&self, code: u32) -> Instruction {
+        let mode = code & mask(self.mode_bits);
+        let mut shift = self.mode_bits;
+        let op = (code >> shift) & mask(self.op_bits);
+        shift += self.op_bits;
+        let src = (code >> shift) & mask(self.src_bits);
+        shift += self.src_bits;
+        let dst = (code >> shift) & mask(self.dst_bits);
+
+        // The fields have at most 31 bits so they fit in an i32
+        Instruction::new(mode as i32, op as i32, src as i32, dst as i32)
+    }
+}
+
+/// Structure to represent a program in the packed form.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PackedProgram {
+    /// The id of the program.
+    id: i32,
+    /// The size of the bit fields.
+    encoding: Encoding,
+    /// The list of packed instruction.
+    codes: Vec<u32>,
+    /// The arithmetic used by the operations of the program.
+    arithmetic: ArithmeticPolicy,
+}
+
+impl PackedProgram {
+    /// Function to get the id of the program.
+    pub fn get_id(&self) -> i32 {
+        self.id
+    }
+
+    /// Function to get the encoding of the program.
+    pub fn get_encoding(&self) -> Encoding {
+        self.encoding
+    }
+
+    /// Function to get the list of packed instruction.
+    pub fn get_codes(&self) -> &[u32] {
+        &self.codes
+    }
+
+    /// Function to get the number of instruction inside the program.
+    pub fn get_len_program(&self) -> usize {
+        self.codes.len()
+    }
+
+    /// Function to set the id of the program.
+    pub(crate) fn set_id(&mut self, id: i32) {
+        self.id = id;
+    }
+
+    /// Function to execute the program directly on the packed form.
+    /// Give the same result as `Program::execute` on the unpacked program.
+    pub fn execute<T: Register>(
+        &self,
+        operations: &OperationSet<T>,
+        input: &[T],
+        regs: &mut [T],
+    ) {
+        self.execute_with_memory(operations, input, regs, &mut Memory::default());
+    }
+
+    /// Function to execute the program directly on the packed form with the memory.
+    /// Give the same result as `Program::execute_with_memory` on the unpacked program.
+    pub fn execute_with_memory<T: Register>(
+        &self,
+        operations: &OperationSet<T>,
+        input: &[T],
+        regs: &mut [T],
+        memory: &mut Memory<T>,
+    ) {
+        if regs.is_empty() {
+            return;
+        }
+
+        for code in self.codes.iter() {
+            let instruction = self.encoding.decode(*code);
+            execute_instruction(&instruction, operations, self.arithmetic, input, regs, memory);
+        }
+    }
+}
+
+impl Program {
+    /// Function to pack the program with the encoding.
+    pub fn encode(&self, encoding: &Encoding) -> Result<PackedProgram, EncodingError> {
+        let codes = self
+            .get_instructions()
+            .iter()
+            .enumerate()
+            .map(|(index, instruction)| encoding.encode(instruction, index))
+            .collect::<Result<Vec<u32>, EncodingError>>()?;
+
+        Ok(PackedProgram {
+            id: self.get_id(),
+            encoding: *encoding,
+            codes,
+            arithmetic: self.get_arithmetic(),
+        })
+    }
+
+    /// Function to unpack a program (keep the id of the packed program).
+    pub fn decode(packed: &PackedProgram) -> Program {
+        let instructions = packed
+            .codes
+            .iter()
+            .map(|code| packed.encoding.decode(*code))
+            .collect();
+        Program::from_instructions(packed.id, instructions, packed.arithmetic)
+    }
+}
+
+/// Enumeration to represent the program kept by a learner (in the plain or the packed form).
+/// The brain is saved with the form of the program (the two forms are read by the load).
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+pub(crate) enum StoredProgram {
+    /// The program packed with an encoding.
+    Packed(PackedProgram),
+    /// The program with the instructions.
+    Plain(Program),
+}
+
+impl StoredProgram {
+    /// Function to get the program (unpacked if the program is packed).
+    pub(crate) fn get(&self) -> Cow<'_, Program> {
+        match self {
+            StoredProgram::Packed(packed) => Cow::Owned(Program::decode(packed)),
+            StoredProgram::Plain(program) => Cow::Borrowed(program),
+        }
+    }
+
+    /// Function to check if the program is packed.
+    pub(crate) fn is_packed(&self) -> bool {
+        matches!(self, StoredProgram::Packed(_))
+    }
+
+    /// Function to set the id of the program.
+    pub(crate) fn set_id(&mut self, id: i32) {
+        match self {
+            StoredProgram::Packed(packed) => packed.set_id(id),
+            StoredProgram::Plain(program) => program.set_id(id),
+        }
+    }
+
+    /// Function to get the number of bytes used by the instructions of the program.
+    pub(crate) fn get_bytes(&self) -> usize {
+        match self {
+            StoredProgram::Packed(packed) => packed.codes.len() * size_of::<u32>(),
+            StoredProgram::Plain(program) => {
+                program.get_len_program() * size_of::<Instruction>()
+            }
+        }
+    }
+
+    /// Function to get the program with only the effective instructions (no intron),
+    /// in the same form as the program.
+    pub(crate) fn get_effective_program<T: Register>(
+        &self,
+        operations: &OperationSet<T>,
+        num_registers: usize,
+    ) -> StoredProgram {
+        let effective_program = self.get().get_effective_program(operations, num_registers);
+        match self {
+            // The effective instructions are instructions of the program so they fit the encoding
+            StoredProgram::Packed(packed) => match effective_program.encode(&packed.encoding) {
+                Ok(effective_packed) => StoredProgram::Packed(effective_packed),
+                Err(_) => StoredProgram::Plain(effective_program),
+            },
+            StoredProgram::Plain(_) => StoredProgram::Plain(effective_program),
+        }
+    }
+}
+
+impl<T: Register> Brain<T> {
+    /// Function to pack the program of all the learners with the encoding
+    /// (e.g. `Encoding::new(&params, &brain.operations)`).
+    /// The learners bid with the same result, the new learners of the evolution are not packed.
+    /// Return an error if an instruction doesn't fit the encoding (no learner is packed).
+    pub fn pack_programs(&mut self, encoding: &Encoding) -> Result<(), EncodingError> {
+        let packed = self
+            .learners
+            .iter()
+            .map(|learner| learner.get_program().encode(encoding))
+            .collect::<Result<Vec<PackedProgram>, EncodingError>>()?;
+        for (learner, program) in self.learners.iter_mut().zip(packed) {
+            learner.set_packed_program(program);
+        }
+        Ok(())
+    }
+
+    /// Function to get the number of bytes used by the instructions of the learners
+    /// (the programs and the programs kept for the bid: effective and compiled programs).
+    pub fn get_program_bytes(&self) -> usize {
+        self.learners.iter().map(|learner| learner.get_program_bytes()).sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Function to get the params used by the tests.
+    fn get_params() -> ProgramParams {
+        ProgramParams {
+            max_program_length: 20,
+            input_size: 100,
+            nb_destinations: 8,
+            ..ProgramParams::default()
+        }
+    }
+
+    #[test]
+    fn test_encoding_program() {
+        let mut params = get_params();
+        let operations: OperationSet<i32> = OperationSet::default();
+        let encoding = Encoding::new(&params, &operations).unwrap();
+
+        // Check the size of the bit fields (1 + 3 + 7 + 3)
+        assert_eq!(encoding.get_nb_bits(), 14, "The number of bits is incorrect");
+
+        for _ in 0..20 {
+            let program = Program::new(None, &mut params, &operations);
+            let packed = program.encode(&encoding).unwrap();
+
+            // Check the program is the same after packing and unpacking it
+            assert_eq!(
+                Program::decode(&packed),
+                program,
+                "The program is different after packing it"
+            );
+            assert_eq!(packed.get_id(), program.get_id(), "The id is not kept");
+
+            // Check the packed program give the same result
+            let input: Vec<i32> = (0..100).collect();
+            let mut regs1: Vec<i32> = vec![1, 2, 3, 4, 5, 6, 7, 8];
+            let mut regs2: Vec<i32> = vec![1, 2, 3, 4, 5, 6, 7, 8];
+            program.execute(&operations, &input, &mut regs1);
+            packed.execute(&operations, &input, &mut regs2);
+            assert_eq!(regs1, regs2, "The packed program give a different result");
+        }
+    }
+
+    #[test]
+    fn test_encoding_save() {
+        let mut params = get_params();
+        let operations: OperationSet<i32> = OperationSet::default();
+        let encoding = Encoding::new(&params, &operations).unwrap();
+        let programs: Vec<Program> = (0..20).map(|_| Program::new(None, &mut params, &operations)).collect();
+        let packed: Vec<PackedProgram> =
+            programs.iter().map(|program| program.encode(&encoding).unwrap()).collect();
+
+        // Check the packed programs are the same after the save and the load
+        let text = serde_json::to_string(&packed).unwrap();
+        let loaded: Vec<PackedProgram> = serde_json::from_str(&text).unwrap();
+        assert_eq!(loaded, packed, "The packed programs are different after the load");
+
+        // Check the packed programs take less than half the size of the programs
+        let program_text = serde_json::to_string(&programs).unwrap();
+        assert!(
+            text.len() * 2 < program_text.len(),
+            "The packed programs are not smaller ({} >= {} / 2)",
+            text.len(),
+            program_text.len()
+        );
+
+        // Check the encoding with too many bits is not loaded
+        let invalid = r#"{"mode_bits":1,"op_bits":30,"src_bits":7,"dst_bits":3}"#;
+        let error = serde_json::from_str::<Encoding>(invalid).unwrap_err();
+        assert!(error.to_string().contains("41 bits"), "The encoding is not checked");
+    }
+
+    #[test]
+    fn test_encoding_brain() {
+        let mut params = crate::Params::new("params_trainer.json".to_string());
+        params.trainer.team.learner.program.max_program_length = 40;
+        let mut brain: Brain<f64> = Brain::default();
+        let mut trainer = crate::Trainer::new(&mut params.trainer, 5);
+        trainer.set_up_actions(vec![0, 1, 2, 3, 4]);
+        trainer.initialize_populations(&mut brain);
+        let states = [[1.0, -2.0, 0.5, 3.0, 1.5], [0.0, 4.0, -1.0, 2.0, -3.5]];
+        let mut agents = trainer.get_agents(&brain, Vec::new(), Vec::new());
+        let mut act = |brain: &Brain<f64>| -> Vec<i32> {
+            let actions = agents.iter_mut().flat_map(|agent| {
+                states.iter().map(|state| agent.act(brain, state).unwrap()).collect::<Vec<_>>()
+            });
+            actions.collect()
+        };
+        let actions = act(&brain);
+        let program_bytes = brain.get_program_bytes();
+
+        // Check the packed brain give the same actions with less memory
+        let program_params = &params.trainer.team.learner.program;
+        let encoding = Encoding::new(program_params, &brain.operations).unwrap();
+        brain.pack_programs(&encoding).unwrap();
+        let packed = brain.learners.iter().all(|learner| learner.is_packed());
+        assert!(packed, "A learner is not packed");
+        assert_eq!(act(&brain), actions, "The packed brain give different actions");
+        let nb_instructions: usize =
+            brain.learners.iter().map(|learner| learner.get_program().get_len_program()).sum();
+        let packed_bytes = brain.get_program_bytes();
+        assert_eq!(packed_bytes, nb_instructions * 4, "The packed learners keep other programs");
+        assert!(
+            packed_bytes * 4 < program_bytes,
+            "The packed brain is not smaller ({} >= {} / 4)",
+            packed_bytes,
+            program_bytes
+        );
+
+        // Check the packed brain is saved and loaded with the packed programs
+        let text = brain.to_json().unwrap();
+        let loaded: Brain<f64> = Brain::from_json(&text, OperationSet::default()).unwrap();
+        let packed = loaded.learners.iter().all(|learner| learner.is_packed());
+        assert!(packed, "The programs are unpacked by the load");
+        assert_eq!(act(&loaded), actions, "The loaded brain give different actions");
+    }
+
+    #[test]
+    fn test_encoding_memory() {
+        let mut params = get_params();
+        params.memory_size = 8;
+        let operations: OperationSet<i32> = OperationSet::default();
+        let encoding = Encoding::new(&params, &operations).unwrap();
+
+        // Check the mode use 2 bits (2 + 3 + 7 + 3)
+        assert_eq!(encoding.get_nb_bits(), 15, "The number of bits is incorrect");
+
+        for _ in 0..20 {
+            let program = Program::new(None, &mut params, &operations);
+            let packed = program.encode(&encoding).unwrap();
+            assert_eq!(
+                Program::decode(&packed),
+                program,
+                "The program with memory is different after packing it"
+            );
+        }
+    }
+
+    #[test]
+    fn test_encoding_error() {
+        let mut params = get_params();
+        let operations: OperationSet<i32> = OperationSet::default();
+        let encoding = Encoding::new(&params, &operations).unwrap();
+
+        // Check the instruction out of the params is not packed
+        let mut program = Program::new(None, &mut params, &operations);
+        program.set_instructions(Instruction::new(0, 0, 0, 8));
+        assert_eq!(
+            program.encode(&encoding),
+            Err(EncodingError::ValueOutOfRange {
+                index: 20,
+                field: "dst",
+                value: 8
+            }),
+            "The destination out of range is packed"
+        );
+
+        // Check the params that need more than 32 bits
+        params.input_size = i32::MAX;
+        params.nb_destinations = 1 << 16;
+        assert_eq!(
+            Encoding::new(&params, &operations),
+            Err(EncodingError::TooManyBits { bits: 51 }),
+            "The encoding with more than 32 bits is created"
+        );
+    }
+}
@@ -0,0 +1,559 @@
+//! # Expression
+//! Crate that permit to export the bid of a program of the Tangled Program Graph (TPG) as a closed-form expression.
+//! The program is executed with symbols in place of the values, so the register 0 at the end of the
+//! program give the expression of the bid (e.g. `bid = (in[3] - in[1]) * 2`).
+//! The expression is simplified during the construction (constant folding and neutral elements),
+//! assuming the input values are finite.
+//! The expression is built for a size of input and of memory, so the instructions skipped by the
+//! execution (reading an empty input or memory) are also skipped by the expression.
+//! The value read in the memory is a symbol (`mem[<index>]`) because it depends on the previous
+//! decisions, the value written by the program before the read is used in place of the symbol.
+//! The writes of the memory are assumed to be done (probability of write of 1): with a lower
+//! probability the write is random, so the expression give the bid only when every write is done.
+//! The sub-expressions are shared (`Rc`), so a register used several times don't copy its
+//! expression and the size of the expression stay linear in the size of the program
+//! (only the text of the expression can be long).
+
+#![deny(missing_docs)]
+#![deny(clippy::all)]
+#![deny(clippy::missing_docs_in_private_items)]
+
+use crate::program::wrap_index;
+use crate::{ArithmeticPolicy, BuiltinOp, Learner, Operation, OperationSet, Program, Register};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::rc::Rc;
+
+/// Enumeration to represent a node of the expression tree.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Expression<T: Register> {
+    /// A constant value.
+    Constant(T),
+    /// The value of the input with the index (taken modulo the size of the input).
+    Input(i32),
+    /// The value of the register with the index before the execution of the program.
+    Register(usize),
+    /// The value of the memory with the index (already taken modulo the size of the memory)
+    /// before the execution of the program.
+    Memory(i32),
+    /// The result of an operation of the operation set.
+    Operation {
+        /// The code of the operation.
+        op: i32,
+        /// The name of the operation.
+        name: String,
+        /// The kind of the operation (None if the operation is defined by the user).
+        kind: Option<BuiltinOp>,
+        /// The arithmetic used by the operation.
+        arithmetic: ArithmeticPolicy,
+        /// The operands of the operation (x and maybe y), shared with the other expressions.
+        operands: Vec<Rc<Expression<T>>>,
+    },
+}
+
+impl<T: Register> Expression<T> {
+    /// Function to check if the expression is the constant.
+    fn is_constant(&self, value: T) -> bool {
+        matches!(self, Expression::Constant(c) if *c == value)
+    }
+
+    /// Function to get the number of nodes in the expression (a shared node is counted once).
+    pub fn get_size(&self) -> usize {
+        let mut nodes: HashSet<*const Expression<T>> = HashSet::new();
+        let mut stack: Vec<&Expression<T>> = vec![self];
+        while let Some(node) = stack.pop() {
+            if !nodes.insert(node) {
+                continue;
+            }
+            if let Expression::Operation { operands, .. } = node {
+                stack.extend(operands.iter().map(|operand| operand.as_ref()));
+            }
+        }
+        nodes.len()
+    }
+
+    /// Function to get the value of the expression with the values of the input, of the
+    /// registers (before the execution) and of the memory (the index are taken modulo the size).
+    /// A value read in an empty input, registers or memory is 0 (the expression of a program for
+    /// an empty input or memory don't read them).
+    /// The value of a shared node is only computed once.
+    pub fn evaluate(
+        &self,
+        operations: &OperationSet<T>,
+        input: &[T],
+        registers: &[T],
+        memory: &[T],
+    ) -> T {
+        let mut values: HashMap<*const Expression<T>, T> = HashMap::new();
+        self.evaluate_shared(operations, input, registers, memory, &mut values)
+    }
+
+    /// Function to get the value of the expression with the values of the shared nodes already computed.
+    fn evaluate_shared(
+        &self,
+        operations: &OperationSet<T>,
+        input: &[T],
+        registers: &[T],
+        memory: &[T],
+        values: &mut HashMap<*const Expression<T>, T>,
+    ) -> T {
+        let read = |values: &[T], index: i64| -> T {
+            if values.is_empty() {
+                T::default()
+            } else {
+                values[index.rem_euclid(values.len() as i64) as usize]
+            }
+        };
+        match self {
+            Expression::Constant(value) => *value,
+            Expression::Input(index) => read(input, i64::from(*index)),
+            Expression::Register(index) => read(registers, *index as i64),
+            Expression::Memory(index) => read(memory, i64::from(*index)),
+            Expression::Operation { op, arithmetic, operands, .. } => {
+                let key: *const Expression<T> = self;
+                if let Some(value) = values.get(&key) {
+                    return *value;
+                }
+                let mut args = operands.iter().map(|operand| {
+                    operand.evaluate_shared(operations, input, registers, memory, values)
+                });
+                let x = args.next().unwrap_or_default();
+                let y = args.next().unwrap_or_default();
+                let value = match operations.get(*op) {
+                    Some(operation) => operation.apply(x, y, *arithmetic),
+                    None => x,
+                };
+                values.insert(key, value);
+                value
+            }
+        }
+    }
+
+    /// Function to create the expression of an operation and simplify it.
+    fn from_operation(
+        op: i32,
+        operation: &dyn Operation<T>,
+        program: &Program,
+        x: Rc<Expression<T>>,
+        y: Rc<Expression<T>>,
+    ) -> Rc<Expression<T>> {
+        let zero = T::default();
+        let one = T::from_f64(1.0);
+        let arithmetic = program.get_arithmetic();
+
+        // Constant folding
+        if let (Expression::Constant(a), Expression::Constant(b)) = (x.as_ref(), y.as_ref()) {
+            return Rc::new(Expression::Constant(operation.apply(*a, *b, arithmetic)));
+        }
+        if operation.arity() < 2 {
+            if let Expression::Constant(a) = x.as_ref() {
+                return Rc::new(Expression::Constant(operation.apply(*a, zero, arithmetic)));
+            }
+        }
+
+        // Neutral and absorbing elements
+        let kind = operation.kind();
+        match kind {
+            Some(BuiltinOp::Add) if y.is_constant(zero) => return x,
+            Some(BuiltinOp::Add) if x.is_constant(zero) => return y,
+            Some(BuiltinOp::Sub) if y.is_constant(zero) => return x,
+            Some(BuiltinOp::Sub) if Rc::ptr_eq(&x, &y) || x == y => {
+                return Rc::new(Expression::Constant(zero))
+            }
+            Some(BuiltinOp::Mul) if y.is_constant(one) => return x,
+            Some(BuiltinOp::Mul) if x.is_constant(one) => return y,
+            Some(BuiltinOp::Mul) if x.is_constant(zero) || y.is_constant(zero) => {
+                return Rc::new(Expression::Constant(zero))
+            }
+            Some(BuiltinOp::Div) if y.is_constant(one) => return x,
+            _ => {}
+        }
+
+        let operands = if operation.arity() < 2 { vec![x] } else { vec![x, y] };
+        Rc::new(Expression::Operation {
+            op,
+            name: operation.name().to_string(),
+            kind,
+            arithmetic,
+            operands,
+        })
+    }
+}
+
+/// Function to get the symbol of the operation written between the operands.
+fn get_infix(kind: Option<BuiltinOp>) -> Option<&'static str> {
+    match kind? {
+        BuiltinOp::Add => Some("+"),
+        BuiltinOp::Sub => Some("-"),
+        BuiltinOp::Mul => Some("*"),
+        BuiltinOp::Div => Some("/"),
+        _ => None,
+    }
+}
+
+/// Function to write an operand (with parenthesis if it is an infix operation).
+fn write_operand<T: Register>(f: &mut fmt::Formatter<'_>, operand: &Expression<T>) -> fmt::Result {
+    match operand {
+        Expression::Operation { kind, .. }
+            if get_infix(*kind).is_some()
+                || matches!(kind, Some(BuiltinOp::Double | BuiltinOp::Halve)) =>
+        {
+            write!(f, "({})", operand)
+        }
+        _ => write!(f, "{}", operand),
+    }
+}
+
+impl<T: Register> fmt::Display for Expression<T> {
+    /// Function to write the expression.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expression::Constant(value) => write!(f, "{}", value),
+            Expression::Input(index) => write!(f, "in[{}]", index),
+            Expression::Register(index) => write!(f, "r{}", index),
+            Expression::Memory(index) => write!(f, "mem[{}]", index),
+            Expression::Operation {
+                name,
+                kind,
+                operands,
+                ..
+            } => {
+                match (kind, operands.as_slice()) {
+                    (Some(BuiltinOp::Double), [x]) => {
+                        write_operand(f, x)?;
+                        write!(f, " * 2")
+                    }
+                    (Some(BuiltinOp::Halve), [x]) => {
+                        write_operand(f, x)?;
+                        write!(f, " / 2")
+                    }
+                    (kind, [x, y]) if get_infix(*kind).is_some() => {
+                        write_operand(f, x)?;
+                        write!(f, " {} ", get_infix(*kind).unwrap_or_default())?;
+                        write_operand(f, y)
+                    }
+                    (_, operands) => {
+                        write!(f, "{}(", name)?;
+                        for (i, operand) in operands.iter().enumerate() {
+                            if i > 0 {
+                                write!(f, ", ")?;
+                            }
+                            write!(f, "{}", operand)?;
+                        }
+                        write!(f, ")")
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Function to get the expression of a memory cell: the value written by the program or the value
+/// before the execution of the program.
+fn read_memory<T: Register>(
+    memory: &mut HashMap<usize, Rc<Expression<T>>>,
+    index: usize,
+) -> Rc<Expression<T>> {
+    let cell = memory.entry(index).or_insert_with(|| Rc::new(Expression::Memory(index as i32)));
+    Rc::clone(cell)
+}
+
+/// Function to get the expression of the register 0 (the bid) from the expression of the registers.
+fn get_bid<T: Register>(regs: Vec<Rc<Expression<T>>>) -> Expression<T> {
+    match regs.into_iter().next() {
+        Some(bid) => Rc::unwrap_or_clone(bid),
+        None => Expression::Constant(T::default()),
+    }
+}
+
+impl Program {
+    /// Function to execute the program with symbols, starting from the registers.
+    /// The instructions are skipped like the execution (with the size of the input and memory).
+    /// Return the expression of each register at the end of the program.
+    /// The expressions of the registers are shared (not copied) by the operations.
+    fn execute_symbolic<T: Register>(
+        &self,
+        operations: &OperationSet<T>,
+        mut regs: Vec<Rc<Expression<T>>>,
+        input_len: usize,
+        memory_len: usize,
+    ) -> Vec<Rc<Expression<T>>> {
+        if regs.is_empty() {
+            return regs;
+        }
+
+        // The expression of the memory cells written by the program
+        let mut memory: HashMap<usize, Rc<Expression<T>>> = HashMap::new();
+
+        let num_registers = regs.len();
+        for instruction in self.get_instructions() {
+            let Some(operation) = operations.get(instruction.get_op()) else {
+                continue;
+            };
+            let op = instruction.get_op();
+            let src = instruction.get_src();
+            let dst = instruction.get_dst();
+
+            let (dest, src) = match instruction.get_mode() {
+                0 => (
+                    wrap_index(dst, num_registers),
+                    Rc::clone(&regs[wrap_index(src, num_registers)]),
+                ),
+                2 | 3 if memory_len == 0 => continue,
+                2 => (
+                    wrap_index(dst, num_registers),
+                    read_memory(&mut memory, wrap_index(src, memory_len)),
+                ),
+                3 => {
+                    // The write of the memory don't change the registers
+                    let cell = wrap_index(dst, memory_len);
+                    let x = read_memory(&mut memory, cell);
+                    let y = Rc::clone(&regs[wrap_index(src, num_registers)]);
+                    memory.insert(cell, Expression::from_operation(op, operation, self, x, y));
+                    continue;
+                }
+                _ if input_len == 0 => continue,
+                _ => (wrap_index(dst, num_registers), Rc::new(Expression::Input(src))),
+            };
+
+            let x = Rc::clone(&regs[dest]);
+            regs[dest] = Expression::from_operation(op, operation, self, x, src);
+        }
+        regs
+    }
+
+    /// Function to get the expression of the register 0 (the bid) at the end of the program
+    /// executed with the size of the input and of the memory.
+    /// The registers have the values given before the execution (so they are folded as constants).
+    pub fn to_expression<T: Register>(
+        &self,
+        operations: &OperationSet<T>,
+        registers: &[T],
+        input_len: usize,
+        memory_len: usize,
+    ) -> Expression<T> {
+        let regs: Vec<Rc<Expression<T>>> =
+            registers.iter().map(|r| Rc::new(Expression::Constant(*r))).collect();
+        get_bid(self.execute_symbolic(operations, regs, input_len, memory_len))
+    }
+
+    /// Function to get the expression of the register 0 (the bid) at the end of the program
+    /// executed with the size of the input and of the memory.
+    /// The registers before the execution are symbols (`r<index>`).
+    pub fn to_symbolic_expression<T: Register>(
+        &self,
+        operations: &OperationSet<T>,
+        num_registers: usize,
+        input_len: usize,
+        memory_len: usize,
+    ) -> Expression<T> {
+        let regs: Vec<Rc<Expression<T>>> =
+            (0..num_registers).map(|index| Rc::new(Expression::Register(index))).collect();
+        get_bid(self.execute_symbolic(operations, regs, input_len, memory_len))
+    }
+}
+
+impl<T: Register> Learner<T> {
+    /// Function to get the expression of the bid of the learner (with the current registers)
+    /// for the size of the input and of the memory.
+    pub fn get_bid_expression(
+        &self,
+        operations: &OperationSet<T>,
+        input_len: usize,
+        memory_len: usize,
+    ) -> Expression<T> {
+        let registers = self.get_registers();
+        self.get_program().to_expression(operations, registers, input_len, memory_len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{rng, seed_rng, Instruction, Memory, ProgramParams};
+    use rand::Rng;
+
+    /// Function to read the program used by the tests.
+    fn get_program(text: &str, operations: &OperationSet<i32>) -> Program {
+        let mut params = ProgramParams {
+            input_size: 5,
+            nb_destinations: 5,
+            ..ProgramParams::default()
+        };
+        Program::from_assembly(text, operations, &mut params).unwrap()
+    }
+
+    #[test]
+    fn test_expression_program() {
+        let operations: OperationSet<i32> = OperationSet::default();
+        let program = get_program(
+            "r1 = r1 + in[3]\nr1 = r1 - in[1]\nr1 = double(r1)\nr2 = r2 + in[0]\nr0 = r0 + r1\n",
+            &operations,
+        );
+
+        // Check the expression with the registers at zero
+        let expression = program.to_expression(&operations, &[0, 0, 0], 4, 0);
+        assert_eq!(
+            format!("bid = {}", expression),
+            "bid = (in[3] - in[1]) * 2",
+            "The expression of the bid is incorrect"
+        );
+        assert_eq!(expression.get_size(), 4, "The size of the expression is incorrect");
+
+        // Check the expression with the registers as symbols
+        let expression = program.to_symbolic_expression(&operations, 3, 4, 0);
+        assert_eq!(
+            expression.to_string(),
+            "r0 + (((r1 + in[3]) - in[1]) * 2)",
+            "The symbolic expression of the bid is incorrect"
+        );
+
+        // Check the expression give the same bid as the program
+        let input: Vec<i32> = vec![5, 7, 11, 13];
+        let mut regs: Vec<i32> = vec![0, 0, 0];
+        program.execute(&operations, &input, &mut regs);
+        assert_eq!(regs[0], (13 - 7) * 2, "The bid of the program is incorrect");
+        let bid = expression.evaluate(&operations, &input, &[0, 0, 0], &[]);
+        assert_eq!(bid, regs[0], "The value of the expression is incorrect");
+    }
+
+    #[test]
+    fn test_expression_evaluate() {
+        let operations: OperationSet<i32> = OperationSet::default();
+        let mut params = ProgramParams {
+            max_program_length: 20,
+            input_size: 4,
+            nb_destinations: 3,
+            memory_size: 3,
+            ..ProgramParams::default()
+        };
+        seed_rng(7);
+        let mut rng = rng();
+
+        // Check the expression give the same value as the execution of random programs
+        // (with or without input and memory, the memory is always written)
+        for i in 0..400 {
+            let program = Program::new(None, &mut params, &operations);
+            let input_len = if i % 4 < 2 { 4 } else { 0 };
+            let memory_len = if i % 2 == 0 { 3 } else { 0 };
+            let input: Vec<i32> = (0..input_len).map(|_| rng.gen_range(-50..50)).collect();
+            let registers: Vec<i32> = (0..3).map(|_| rng.gen_range(-50..50)).collect();
+            let mut memory: Memory<i32> = Memory::new(memory_len, 1.0);
+            for index in 0..memory_len as i32 {
+                memory.write(index, rng.gen_range(-50..50));
+            }
+            let memory_values = memory.get_values().to_vec();
+            let mut regs = registers.clone();
+            program.execute_with_memory(&operations, &input, &mut regs, &mut memory);
+
+            let expression = program.to_expression(&operations, &registers, input_len, memory_len);
+            let bid = expression.evaluate(&operations, &input, &[], &memory_values);
+            assert_eq!(bid, regs[0], "The value of the expression is incorrect");
+            let expression = program.to_symbolic_expression(&operations, 3, input_len, memory_len);
+            let bid = expression.evaluate(&operations, &input, &registers, &memory_values);
+            assert_eq!(bid, regs[0], "The value of the symbolic expression is incorrect");
+        }
+    }
+
+    #[test]
+    fn test_expression_skip() {
+        let operations: OperationSet<i32> = OperationSet::default();
+
+        // Check the instruction reading an empty input is skipped like the execution
+        let program = get_program("r0 = r0 * in[0]
+r0 = r0 + r1
+", &operations);
+        let expression = program.to_symbolic_expression(&operations, 2, 0, 0);
+        assert_eq!(expression.to_string(), "r0 + r1", "The read of the empty input is kept");
+        let mut regs: Vec<i32> = vec![5, 2];
+        program.execute(&operations, &[], &mut regs);
+        let bid = expression.evaluate(&operations, &[], &[5, 2], &[]);
+        assert_eq!(bid, regs[0], "The value of the expression is incorrect");
+
+        // Check the value written in the memory is read by the next instructions
+        let text = "mem[1] = mem[1] + r1
+r0 = r0 + mem[4]
+r0 = r0 * mem[2]
+";
+        let program = get_program(text, &operations);
+        let expression = program.to_symbolic_expression(&operations, 2, 0, 3);
+        assert_eq!(
+            expression.to_string(),
+            "(r0 + (mem[1] + r1)) * mem[2]",
+            "The value written in the memory is not read"
+        );
+        let expression = program.to_symbolic_expression(&operations, 2, 0, 0);
+        assert_eq!(expression.to_string(), "r0", "The read of the empty memory is kept");
+    }
+
+    #[test]
+    fn test_expression_shared() {
+        let operations: OperationSet<f64> = OperationSet::default();
+        let text = "r0 = r0 * r0\n".repeat(30);
+        let mut params = ProgramParams::default();
+        let program = Program::from_assembly(&text, &operations, &mut params).unwrap();
+
+        // Check the expression of the register is shared (not copied) by each instruction
+        let expression = program.to_symbolic_expression(&operations, 1, 0, 0);
+        assert_eq!(expression.get_size(), 31, "The sub-expressions are not shared");
+        let bid = expression.evaluate(&operations, &[], &[1.0], &[]);
+        assert_eq!(bid, 1.0, "The value of the expression is incorrect");
+    }
+
+    #[test]
+    fn test_expression_folding() {
+        let operations: OperationSet<i32> = OperationSet::default();
+        let program = get_program(
+            "r1 = r1 * r2\nr0 = r0 + r1\nr0 = r0 * in[0]\nr2 = r2 - r2\nr0 = r0 + r2\n",
+            &operations,
+        );
+
+        // Check the constants are folded
+        let expression = program.to_expression(&operations, &[0, 3, 4], 5, 0);
+        assert_eq!(expression.to_string(), "12 * in[0]", "The constants are not folded");
+
+        // Check the subtraction of the same expression is simplified
+        let expression = program.to_symbolic_expression(&operations, 3, 5, 0);
+        assert_eq!(
+            expression.to_string(),
+            "(r0 + (r1 * r2)) * in[0]",
+            "The expression is not simplified"
+        );
+    }
+
+    /// Operation defined by the user with the name of a builtin operation.
+    struct UserAdd;
+
+    impl Operation<i32> for UserAdd {
+        fn name(&self) -> &str {
+            "add"
+        }
+
+        fn arity(&self) -> usize {
+            2
+        }
+
+        fn apply(&self, x: i32, y: i32, _policy: ArithmeticPolicy) -> i32 {
+            x - y
+        }
+    }
+
+    #[test]
+    fn test_expression_user_operation() {
+        let mut operations: OperationSet<i32> = OperationSet::new();
+        operations.add_operation(Box::new(UserAdd));
+        let instructions = vec![Instruction::new(1, 0, 0, 0), Instruction::new(1, 0, 1, 0)];
+        let mut params = ProgramParams::default();
+        let program = Program::new(Some(instructions), &mut params, &operations);
+
+        // Check the operation is not simplified or written as the builtin operation
+        let expression = program.to_expression(&operations, &[0], 2, 0);
+        assert_eq!(
+            expression.to_string(),
+            "add(add(0, in[0]), in[1])",
+            "The operation of the user is simplified as a builtin operation"
+        );
+        let value = expression.evaluate(&operations, &[3, 4], &[], &[]);
+        assert_eq!(value, -7, "The value of the expression is incorrect");
+    }
+}
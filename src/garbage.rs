@@ -0,0 +1,359 @@
+//! # Garbage
+//! Crate that permit to remove the unused teams, learners and actions of the brain
+//! of the Tangled Program Graph (TPG).
+//! The teams, the learners and the actions reachable from the roots are kept, the others are removed
+//! and the lists of the brain are compacted (the order of the kept entities is the same).
+//! All the index inside the brain are remapped, the index kept outside the brain are remapped
+//! with the report (e.g. `Trainer::collect_garbage`).
+//! The graph reachable from an agent can also be copied in a new minimal brain (e.g. to ship
+//! only one agent).
+
+#![deny(missing_docs)]
+#![deny(clippy::all)]
+#![deny(clippy::missing_docs_in_private_items)]
+
+use crate::handle::Slots;
+use crate::{Agent, Brain, OperationSet, Register};
+use std::collections::HashMap;
+
+/// Structure to represent what was freed by the garbage collection.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct GarbageReport {
+    /// The id of the removed teams.
+    removed_teams: Vec<i32>,
+    /// The id of the removed learners.
+    removed_learners: Vec<i32>,
+    /// The id of the removed actions.
+    removed_actions: Vec<i32>,
+    /// The new index of each team (None if the team is removed).
+    team_remap: Vec<Option<usize>>,
+    /// The new index of each learner (None if the learner is removed).
+    learner_remap: Vec<Option<usize>>,
+    /// The new index of each action (None if the action is removed).
+    action_remap: Vec<Option<usize>>,
+}
+
+impl GarbageReport {
+    /// Function to get the id of the removed teams.
+    pub fn get_removed_teams(&self) -> &[i32] {
+        &self.removed_teams
+    }
+
+    /// Function to get the id of the removed learners.
+    pub fn get_removed_learners(&self) -> &[i32] {
+        &self.removed_learners
+    }
+
+    /// Function to get the id of the removed actions.
+    pub fn get_removed_actions(&self) -> &[i32] {
+        &self.removed_actions
+    }
+
+    /// Function to get the number of removed teams, learners and actions.
+    pub fn get_nb_freed(&self) -> usize {
+        self.removed_teams.len() + self.removed_learners.len() + self.removed_actions.len()
+    }
+
+    /// Function to get the new index of the team (None if the team is removed).
+    pub fn get_new_team_index(&self, team_idx: usize) -> Option<usize> {
+        self.team_remap.get(team_idx).copied().flatten()
+    }
+
+    /// Function to get the new index of the learner (None if the learner is removed).
+    pub fn get_new_learner_index(&self, learner_idx: usize) -> Option<usize> {
+        self.learner_remap.get(learner_idx).copied().flatten()
+    }
+
+    /// Function to get the new index of the action (None if the action is removed).
+    pub fn get_new_action_index(&self, action_idx: usize) -> Option<usize> {
+        self.action_remap.get(action_idx).copied().flatten()
+    }
+
+    /// Function to remap a list of team index (the removed teams are removed from the list).
+    pub fn remap_teams(&self, teams: &[usize]) -> Vec<usize> {
+        teams.iter().filter_map(|idx| self.get_new_team_index(*idx)).collect()
+    }
+
+    /// Function to remap a list of learner index (the removed learners are removed from the list).
+    pub fn remap_learners(&self, learners: &[usize]) -> Vec<usize> {
+        learners.iter().filter_map(|idx| self.get_new_learner_index(*idx)).collect()
+    }
+}
+
+/// Structure to represent the teams, the learners and the actions reachable from the roots.
+pub(crate) struct Reachable {
+    /// True if the team (by index) is reachable.
+    pub(crate) teams: Vec<bool>,
+    /// True if the learner (by index) is reachable.
+    pub(crate) learners: Vec<bool>,
+    /// True if the action (by index) is reachable.
+    pub(crate) actions: Vec<bool>,
+}
+
+/// Function to get the new index of each entity (the kept entities keep their order).
+pub(crate) fn get_remap(kept: &[bool]) -> Vec<Option<usize>> {
+    let mut nb_kept: usize = 0;
+    kept.iter()
+        .map(|keep| {
+            nb_kept += usize::from(*keep);
+            keep.then(|| nb_kept - 1)
+        })
+        .collect()
+}
+
+/// Function to compact the list with the kept entities.
+/// Return the new index of each entity and the id of the removed entities.
+fn compact<E>(
+    list: &mut Vec<E>,
+    kept: &[bool],
+    get_id: impl Fn(&E) -> i32,
+) -> (Vec<Option<usize>>, Vec<i32>) {
+    let remap = get_remap(kept);
+    let removed: Vec<i32> = list
+        .iter()
+        .zip(kept)
+        .filter(|(_, keep)| !**keep)
+        .map(|(entity, _)| get_id(entity))
+        .collect();
+
+    let mut i = 0;
+    list.retain(|_| {
+        i += 1;
+        kept[i - 1]
+    });
+    (remap, removed)
+}
+
+/// Function to update the id -> index and the slots after the compaction.
+/// The slots of the removed entities are freed (so their handles are stale),
+/// the slots of the kept entities follow them (so their handles are still valid).
+fn update_slots<E>(
+    list: &[E],
+    remap: &[Option<usize>],
+    id_to_index: &mut HashMap<i32, usize>,
+    slots: &mut Slots,
+    get_id: impl Fn(&E) -> i32,
+) {
+    slots.remap(remap);
+    id_to_index.clear();
+    for (idx, entity) in list.iter().enumerate() {
+        id_to_index.insert(get_id(entity), idx);
+    }
+}
+
+impl<T: Register> Brain<T> {
+    /// Function to mark the teams, the learners and the actions reachable from the roots.
+    pub(crate) fn get_reachable(&self, roots: &[usize]) -> Reachable {
+        let mut reachable = Reachable {
+            teams: vec![false; self.teams.len()],
+            learners: vec![false; self.learners.len()],
+            actions: vec![false; self.actions.len()],
+        };
+        for root in roots.iter().filter(|root| **root < self.teams.len()) {
+            if reachable.teams[*root] {
+                continue;
+            }
+            for team_idx in self.get_reachable_teams(*root) {
+                reachable.teams[team_idx] = true;
+                for learner_idx in self.teams[team_idx].get_learners() {
+                    reachable.learners[learner_idx] = true;
+                    reachable.actions[self.learners[learner_idx].get_idx_action()] = true;
+                }
+            }
+        }
+        reachable
+    }
+
+    /// Function to change the index inside the brain with the new index of each entity.
+    fn remap_indices(
+        &mut self,
+        team_remap: &[Option<usize>],
+        learner_remap: &[Option<usize>],
+        action_remap: &[Option<usize>],
+    ) {
+        let remap_team = |idx: usize| team_remap.get(idx).copied().flatten();
+        let remap_learner = |idx: usize| learner_remap.get(idx).copied().flatten();
+        for team in self.teams.iter_mut() {
+            team.remap_learners(&remap_learner);
+        }
+        for learner in self.learners.iter_mut() {
+            learner.remap_in_teams(&remap_team);
+            if let Some(action_idx) = action_remap[learner.get_idx_action()] {
+                learner.set_idx_action(action_idx);
+            }
+        }
+        for action in self.actions.iter_mut() {
+            if let Some(team_idx) = action.get_action_team() {
+                action.set_action_team(remap_team(team_idx));
+            }
+        }
+    }
+
+    /// Function to remove the teams, the learners and the actions not reachable from the roots
+    /// (index of the teams, e.g. the teams of the trainer and the protected extra teams).
+    /// The brain is compacted, the handles of the removed entities are stale
+    /// (the handles of the kept entities are still valid).
+    /// Return the report with the removed ids and the new index of each entity.
+    pub fn collect_garbage(&mut self, roots: &[usize]) -> GarbageReport {
+        let reachable = self.get_reachable(roots);
+
+        // Compact the lists
+        let (team_remap, removed_teams) =
+            compact(&mut self.teams, &reachable.teams, |t| t.get_id());
+        let (learner_remap, removed_learners) =
+            compact(&mut self.learners, &reachable.learners, |l| l.get_id());
+        let (action_remap, removed_actions) =
+            compact(&mut self.actions, &reachable.actions, |a| a.get_id());
+
+        // Remap the index inside the brain
+        self.remap_indices(&team_remap, &learner_remap, &action_remap);
+
+        update_slots(
+            &self.teams,
+            &team_remap,
+            &mut self.team_id_to_index,
+            &mut self.team_slots,
+            |t| t.get_id(),
+        );
+        update_slots(
+            &self.learners,
+            &learner_remap,
+            &mut self.learner_id_to_index,
+            &mut self.learner_slots,
+            |l| l.get_id(),
+        );
+        update_slots(
+            &self.actions,
+            &action_remap,
+            &mut self.action_id_to_index,
+            &mut self.action_slots,
+            |a| a.get_id(),
+        );
+
+        GarbageReport {
+            removed_teams,
+            removed_learners,
+            removed_actions,
+            team_remap,
+            learner_remap,
+            action_remap,
+        }
+    }
+
+    /// Function to copy the teams, the learners (with their program) and the actions reachable
+    /// from the team of the agent in a new brain, with the memory of the brain.
+    /// The operations are code and can't be copied, the operations must be the same as the
+    /// operations of the brain (e.g. `OperationSet::default()`).
+    /// Return the new brain and the agent of the new brain (same decisions as the agent, with a
+    /// copy of the execution context of the agent).
+    pub fn extract_agent(
+        &self,
+        agent: &Agent<T>,
+        operations: OperationSet<T>,
+    ) -> (Brain<T>, Agent<T>) {
+        let reachable = self.get_reachable(&[agent.get_idx_teams()]);
+        let mut brain: Brain<T> = Brain {
+            operations,
+            memory: self.memory.clone(),
+            default_action: self.default_action,
+            ..Brain::default()
+        };
+        for (team, _) in self.teams.iter().zip(&reachable.teams).filter(|(_, keep)| **keep) {
+            brain.add_team(team.clone());
+        }
+        let learners = self.learners.iter().zip(&reachable.learners);
+        for (learner, _) in learners.filter(|(_, keep)| **keep) {
+            brain.add_learner(learner.clone());
+        }
+        for (action, _) in self.actions.iter().zip(&reachable.actions).filter(|(_, keep)| **keep) {
+            brain.add_action(action.clone());
+        }
+
+        let team_remap = get_remap(&reachable.teams);
+        let learner_remap = get_remap(&reachable.learners);
+        brain.remap_indices(&team_remap, &learner_remap, &get_remap(&reachable.actions));
+        let root = team_remap[agent.get_idx_teams()].unwrap();
+        let mut context = agent.get_context().clone();
+        context.remap_registers(&|idx: usize| learner_remap.get(idx).copied().flatten());
+        (brain, Agent::with_context(root, context))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::brain::brain_from_assembly;
+
+    /// Function to get the brain used by the tests.
+    /// The team 0 is not used, the root team 1 call the team 2, the learner 5 is orphan.
+    fn get_brain() -> Brain<i32> {
+        let programs = [
+            ("r0 = r0 + in[0]", 10),
+            ("r0 = r0 - in[0]", 11),
+            ("r0 = r0 + in[1]", 12),
+            ("r0 = r0 + in[0]", 13),
+            ("r0 = r0 - in[0]", 14),
+            ("r0 = r0 + in[1]", 15),
+        ];
+        brain_from_assembly(&programs, &[&[0, 1], &[1, 2], &[3, 4]], &[(2, 2)])
+    }
+
+    #[test]
+    fn test_collect_garbage() {
+        let mut brain = get_brain();
+        let states: Vec<Vec<i32>> = vec![vec![1, 5], vec![-3, 2], vec![4, -1]];
+        let mut agent = Agent::new(1, &brain);
+        let expected: Vec<i32> =
+            states.iter().map(|state| agent.act(&brain, state).unwrap()).collect();
+        let handle = brain.get_learner_handle(1).unwrap();
+
+        // Check the unused entities are removed
+        let report = brain.collect_garbage(&[1]);
+        assert_eq!(report.get_removed_teams(), &[0], "The removed teams are incorrect");
+        assert_eq!(report.get_removed_learners(), &[0, 5], "The removed learners are incorrect");
+        assert_eq!(report.get_removed_actions(), &[0, 5], "The removed actions are incorrect");
+        assert_eq!(report.get_nb_freed(), 5, "The number of freed entities is incorrect");
+        assert_eq!(brain.learners.len(), 4, "The learners are not compacted");
+
+        // Check the index are remapped
+        assert_eq!(report.remap_teams(&[0, 1, 2]), vec![0, 1], "The teams are not remapped");
+        assert_eq!(brain.teams[1].get_learners(), vec![2, 3], "The learners are not remapped");
+        assert_eq!(brain.actions[1].get_action_team(), Some(1), "The action is not remapped");
+        assert_eq!(brain.get_learner_index_from_learner_id(4), Some(3), "The id is not remapped");
+        let moved = brain.get_learner(handle).map(|learner| learner.get_id());
+        assert_eq!(moved, Some(1), "The handle of the moved learner is not valid");
+        assert_eq!(brain.get_learner_index(handle), Some(0), "The index of the handle is incorrect");
+
+        // Check the agent give the same actions
+        let mut agent = Agent::new(report.get_new_team_index(1).unwrap(), &brain);
+        let actions: Vec<i32> =
+            states.iter().map(|state| agent.act(&brain, state).unwrap()).collect();
+        assert_eq!(actions, expected, "The actions after the garbage collection are incorrect");
+    }
+
+    #[test]
+    fn test_extract_agent() {
+        let brain = get_brain();
+        let states: Vec<Vec<i32>> = vec![vec![1, 5], vec![-3, 2], vec![4, -1]];
+        let mut agent = Agent::new(1, &brain);
+
+        // Check only the reachable entities are copied (the brain is not changed)
+        let (extracted, mut extracted_agent) =
+            brain.extract_agent(&agent, OperationSet::default());
+        assert_eq!(extracted_agent.get_idx_teams(), 0, "The root of the agent is incorrect");
+        assert_eq!(extracted.teams.len(), 2, "The number of teams is incorrect");
+        assert_eq!(extracted.learners.len(), 4, "The number of learners is incorrect");
+        assert_eq!(extracted.actions.len(), 4, "The number of actions is incorrect");
+        assert_eq!(extracted.teams[0].get_learners(), vec![0, 1], "The learners are incorrect");
+        assert_eq!(extracted.actions[1].get_action_team(), Some(1), "The team is incorrect");
+        assert_eq!(extracted.get_team_index_from_team_id(2), Some(1), "The id is not kept");
+        assert_eq!(brain.teams.len(), 3, "The brain is changed");
+
+        // Check the extracted agent give the same actions
+        for state in states.iter() {
+            let action = agent.act(&brain, state).unwrap();
+            let extracted_action = extracted_agent.act(&extracted, state).unwrap();
+            assert_eq!(extracted_action, action, "The action of the extracted agent is incorrect");
+        }
+    }
+}
@@ -0,0 +1,237 @@
+//! # Handle
+//! Crate that permit to reference the teams, the learners and the actions of the brain
+//! of the Tangled Program Graph (TPG) with a generation.
+//! Each entity of the lists of the brain has a slot, the handle reference the slot (not the
+//! index in the list). When an entity is moved in the list (e.g. by a remove or a garbage
+//! collection) its slot follow it, so its handle is still valid. When an entity is removed the
+//! generation of its slot change, so a handle on a removed entity is detected as stale
+//! (instead of giving an other entity). The free slots are reused by the next entities.
+
+#![deny(missing_docs)]
+#![deny(clippy::all)]
+#![deny(clippy::missing_docs_in_private_items)]
+
+use crate::{Action, Learner, Team};
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+
+/// Structure to represent a handle on an entity of the brain (the slot and its generation).
+pub struct Handle<E> {
+    /// The slot of the entity.
+    slot: usize,
+    /// The generation of the slot when the handle was created.
+    generation: u32,
+    /// The kind of entity referenced by the handle.
+    marker: PhantomData<fn() -> E>,
+}
+
+/// The handle on a team of the brain.
+pub type TeamHandle = Handle<Team>;
+/// The handle on a learner of the brain.
+pub type LearnerHandle<T> = Handle<Learner<T>>;
+/// The handle on an action of the brain.
+pub type ActionHandle = Handle<Action>;
+
+impl<E> Handle<E> {
+    /// Function to get the slot of the entity
+    /// (not the index in the brain, see `Brain::get_team_index`).
+    pub fn get_slot(&self) -> usize {
+        self.slot
+    }
+
+    /// Function to get the generation of the handle.
+    pub fn get_generation(&self) -> u32 {
+        self.generation
+    }
+}
+
+impl<E> Clone for Handle<E> {
+    /// Function to copy the handle.
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<E> Copy for Handle<E> {}
+
+impl<E> PartialEq for Handle<E> {
+    /// Function to compare two handles between them.
+    fn eq(&self, other: &Self) -> bool {
+        self.slot == other.slot && self.generation == other.generation
+    }
+}
+
+impl<E> Eq for Handle<E> {}
+
+impl<E> Hash for Handle<E> {
+    /// Function to hash the handle.
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.slot.hash(state);
+        self.generation.hash(state);
+    }
+}
+
+impl<E> fmt::Debug for Handle<E> {
+    /// Function to format the handle.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Handle({}v{})", self.slot, self.generation)
+    }
+}
+
+/// Structure to represent a slot (the generation and the index of the entity).
+#[derive(Clone, Copy, Debug)]
+struct Slot {
+    /// The generation of the slot (changed when the entity is removed).
+    generation: u32,
+    /// The index of the entity in the list of the brain (None if the slot is free).
+    index: Option<usize>,
+}
+
+/// Structure to represent the slots of a list of the brain.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Slots {
+    /// The slots (referenced by the handles).
+    slots: Vec<Slot>,
+    /// The slot of each entity of the list (by index).
+    slot_of_index: Vec<usize>,
+    /// The free slots (reused by the next entities).
+    free: Vec<usize>,
+}
+
+impl Slots {
+    /// Function to give a slot to each entity of a list of the size without slot
+    /// (e.g. the entities loaded from a file or pushed in the list without the brain).
+    pub(crate) fn fill(&mut self, len: usize) {
+        while self.slot_of_index.len() < len {
+            let index = self.slot_of_index.len();
+            let slot = match self.free.pop() {
+                Some(slot) => slot,
+                None => {
+                    self.slots.push(Slot { generation: 0, index: None });
+                    self.slots.len() - 1
+                }
+            };
+            self.slots[slot].index = Some(index);
+            self.slot_of_index.push(slot);
+        }
+    }
+
+    /// Function to give a slot to the entity added at the end of a list of the size.
+    /// Return the handle of the entity.
+    pub(crate) fn insert<E>(&mut self, len: usize) -> Handle<E> {
+        self.fill(len);
+        self.get_handle(len - 1).unwrap()
+    }
+
+    /// Function to get the handle of the entity at the index (None if the entity has no slot).
+    pub(crate) fn get_handle<E>(&self, index: usize) -> Option<Handle<E>> {
+        let slot = *self.slot_of_index.get(index)?;
+        Some(Handle {
+            slot,
+            generation: self.slots[slot].generation,
+            marker: PhantomData,
+        })
+    }
+
+    /// Function to get the index of the entity of the handle (None if the handle is stale).
+    pub(crate) fn get_index<E>(&self, handle: &Handle<E>) -> Option<usize> {
+        let slot = self.slots.get(handle.slot)?;
+        if slot.generation == handle.generation {
+            slot.index
+        } else {
+            None
+        }
+    }
+
+    /// Function to free the slot of the entity removed with a swap remove
+    /// (the last entity is moved at the index, its slot follow it).
+    pub(crate) fn swap_remove(&mut self, index: usize) {
+        let slot = self.slot_of_index.swap_remove(index);
+        self.release(slot);
+        if let Some(moved) = self.slot_of_index.get(index) {
+            self.slots[*moved].index = Some(index);
+        }
+    }
+
+    /// Function to update the slots with the new index of each entity
+    /// (the slots of the entities remapped to None are freed).
+    pub(crate) fn remap(&mut self, remap: &[Option<usize>]) {
+        let mut kept: Vec<(usize, usize)> = Vec::with_capacity(self.slot_of_index.len());
+        for (index, slot) in std::mem::take(&mut self.slot_of_index).into_iter().enumerate() {
+            match remap.get(index).copied().flatten() {
+                Some(new_index) => {
+                    self.slots[slot].index = Some(new_index);
+                    kept.push((new_index, slot));
+                }
+                None => self.release(slot),
+            }
+        }
+        kept.sort_unstable();
+        self.slot_of_index = kept.into_iter().map(|(_, slot)| slot).collect();
+    }
+
+    /// Function to free the slot (the handles on the slot are stale).
+    fn release(&mut self, slot: usize) {
+        self.slots[slot].generation = self.slots[slot].generation.wrapping_add(1);
+        self.slots[slot].index = None;
+        self.free.push(slot);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::brain::brain_from_assembly_with_params;
+    use crate::{Agent, Brain, Program, TeamParams};
+
+    #[test]
+    fn test_handles() {
+        // The team use the learners 0 and 2, the learner 1 is not used
+        let mut params = TeamParams::default();
+        let programs = [("r0 = r0 + in[0]", 1), ("r0 = r0 - in[0]", 2), ("r0 = r0 + in[1]", 3)];
+        let mut brain: Brain<i32> =
+            brain_from_assembly_with_params(&mut params, &programs, &[&[0, 2]], &[]);
+        let handles: Vec<LearnerHandle<i32>> =
+            (0..3).map(|id| brain.get_learner_handle(id).unwrap()).collect();
+        let team_handle = brain.get_team_handle(0).unwrap();
+
+        // Check the lookup with the id
+        assert_eq!(brain.get_learner_handle(2), Some(handles[2]), "The handle is incorrect");
+        assert_eq!(brain.get_learner_index_from_learner_id(1), Some(1), "The index is incorrect");
+        let team_id = brain.get_team(team_handle).map(|team| team.get_id());
+        assert_eq!(team_id, Some(0), "The team is incorrect");
+        assert_eq!(brain.get_learner_handle(5), None, "The unknown id has a handle");
+
+        // Check the learner used by the team and the action used by a learner are not removed
+        let action_handle = brain.get_action_handle(0).unwrap();
+        assert!(brain.remove_learner(handles[0]).is_none(), "The learner in a team is removed");
+        assert!(brain.remove_action(action_handle).is_none(), "The action used is removed");
+
+        // Check only the handle of the removed learner is stale (not the moved learner)
+        let removed = brain.remove_learner(handles[1]).expect("The learner is not removed");
+        assert_eq!(removed.get_id(), 1, "The removed learner is incorrect");
+        assert!(brain.get_learner(handles[1]).is_none(), "The handle of the removed is valid");
+        let moved_id = brain.get_learner(handles[2]).map(|learner| learner.get_id());
+        assert_eq!(moved_id, Some(2), "The handle of the moved learner is not valid");
+        assert_eq!(brain.get_learner_handle(1), None, "The removed learner has a handle");
+        assert_eq!(brain.get_learner_handle(2), Some(handles[2]), "The handle is changed");
+
+        // Check the moved learner is still used by the team
+        assert_eq!(brain.get_learner_index(handles[2]), Some(1), "The index is incorrect");
+        assert_eq!(brain.teams[0].get_learners(), vec![0, 1], "The team is not remapped");
+
+        // Check the free slot is reused with a new generation
+        let text = "r0 = r0 + in[0]";
+        let program = Program::from_assembly(text, &brain.operations, &mut params.learner.program);
+        let learner = Learner::new(&mut params.learner, program.unwrap(), 0, 2);
+        let handle = brain.add_learner(learner);
+        assert_eq!(handle.get_slot(), handles[1].get_slot(), "The free slot is not reused");
+        assert!(brain.get_learner(handles[1]).is_none(), "The handle of the removed is valid");
+        assert_eq!(brain.get_learner_index(handle), Some(2), "The index is incorrect");
+
+        let mut agent = Agent::new(brain.get_team_index(team_handle).unwrap(), &brain);
+        let action = agent.act(&brain, &[1, 5]).unwrap();
+        assert_eq!(action, 3, "The action after the remove is incorrect");
+    }
+}
@@ -6,31 +6,50 @@
 #![deny(clippy::all)]
 #![deny(clippy::missing_docs_in_private_items)]
 
-use crate::{flip, Brain, LearnerParams, Program};
+use crate::compiled::CompiledCache;
+use crate::encoding::StoredProgram;
+use crate::{
+    flip, ActError, Brain, CompiledProgram, Encoding, EncodingError, ExecutionContext,
+    LearnerParams, Memory, OperationSet, PackedProgram, Program, Register,
+};
+use serde::{Deserialize, Serialize};
+use std::sync::OnceLock;
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(bound = "T: Register")]
 /// Structure that represetn a Learner.
-pub struct Learner {
+pub struct Learner<T: Register> {
     /// The id of the learner.
     id: i32,
-    /// The program of the learner.
-    program: Program,
-    /// The register of the learner.
-    registers: Vec<i32>,
+    /// The program of the learner (in the packed form after `pack`).
+    program: StoredProgram,
+    /// The register of the learner (the registers at the start of the bid).
+    registers: Vec<T>,
     /// The id of action observed by the learner.
     action: usize,
     /// The link with the team.
     in_teams: Vec<usize>,
+    /// If true the bid only execute the effective instructions of the program.
+    strip_introns: bool,
+    /// If true the registers are kept by the execution context after the bid (recurrent learner).
+    persistent_registers: bool,
+    /// The program without intron (computed at the first bid, reset when the program mutate).
+    #[serde(skip)]
+    effective_program: OnceLock<StoredProgram>,
+    /// The program compiled for the bid (compiled at the first bid and when the size of the input
+    /// or the memory change, reset when the program mutate).
+    #[serde(skip)]
+    compiled_program: CompiledCache,
 }
 
-impl PartialEq for Learner {
+impl<T: Register> PartialEq for Learner<T> {
     /// Function to compare two learner between them.
     fn eq(&self, other: &Self) -> bool {
-        self.program == other.program
+        self.program.get() == other.program.get()
     }
 }
 
-impl Learner {
+impl<T: Register> Learner<T> {
     /// Constructor of the learner
     pub fn new(
         init_params: &mut LearnerParams,
@@ -39,14 +58,18 @@ impl Learner {
         num_register: usize,
     ) -> Self {
         let id: i32 = init_params.get_new_id_learner();
-        let registers: Vec<i32> = vec![0; num_register];
+        let registers: Vec<T> = vec![T::default(); num_register];
         let in_teams: Vec<usize> = Vec::new();
         Learner {
             id,
-            program,
+            program: StoredProgram::Plain(program),
             registers,
             action,
             in_teams,
+            strip_introns: init_params.strip_introns,
+            persistent_registers: init_params.persistent_registers,
+            effective_program: OnceLock::new(),
+            compiled_program: CompiledCache::default(),
         }
     }
 
@@ -55,6 +78,11 @@ impl Learner {
         self.registers.len()
     }
 
+    /// Function to get the register
+    pub fn get_registers(&self) -> &[T] {
+        &self.registers
+    }
+
     /// Function to get action
     pub fn get_idx_action(&self) -> usize {
         self.action
@@ -62,21 +90,65 @@ impl Learner {
 
     /// Function to get the program
     pub fn get_program(&self) -> Program {
-        self.program.clone()
+        self.program.get().into_owned()
+    }
+
+    /// Function to pack the program with the encoding to reduce the memory of the learner.
+    /// The packed learner bid directly on the packed program (the bid is not compiled).
+    /// Return an error if an instruction doesn't fit the encoding (the learner is not changed).
+    pub fn pack(&mut self, encoding: &Encoding) -> Result<(), EncodingError> {
+        let packed = self.program.get().encode(encoding)?;
+        self.set_packed_program(packed);
+        Ok(())
+    }
+
+    /// Function to replace the program by the packed program.
+    pub(crate) fn set_packed_program(&mut self, packed: PackedProgram) {
+        self.program = StoredProgram::Packed(packed);
+        self.effective_program = OnceLock::new();
+        self.compiled_program.reset();
+    }
+
+    /// Function to check if the program of the learner is packed.
+    pub fn is_packed(&self) -> bool {
+        self.program.is_packed()
+    }
+
+    /// Function to get the number of bytes used by the instructions of the learner
+    /// (the program, the effective program and the compiled program of the bid).
+    pub fn get_program_bytes(&self) -> usize {
+        let effective = self.effective_program.get().map_or(0, StoredProgram::get_bytes);
+        let compiled = self.compiled_program.get().map_or(0, |compiled| compiled.get_bytes());
+        self.program.get_bytes() + effective + compiled
     }
 
     /// Function to reset the register
     pub fn zero_registers(&mut self) {
-        let size_registers: i32 = self.registers.len().try_into().unwrap();
-        let regs: Vec<i32> = vec![0, size_registers];
+        let size_registers: usize = self.registers.len();
+        let regs: Vec<T> = vec![T::default(); size_registers];
         self.registers = regs;
     }
 
+    /// Function to get the teams referencing the learner
+    pub fn get_in_teams(&self) -> Vec<usize> {
+        self.in_teams.to_vec()
+    }
+
     /// Function to set teams to learner
     pub fn set_in_teams(&mut self, team_idx: usize) {
         self.in_teams.push(team_idx);
     }
 
+    /// Function to change the index of the teams (the teams remapped to None are removed).
+    pub(crate) fn remap_in_teams(&mut self, remap: &impl Fn(usize) -> Option<usize>) {
+        self.in_teams = self.in_teams.iter().filter_map(|idx| remap(*idx)).collect();
+    }
+
+    /// Function to set the index of the action of the learner.
+    pub(crate) fn set_idx_action(&mut self, action: usize) {
+        self.action = action;
+    }
+
     /// Function to get the number of teams referencing
     pub fn num_teams_referencing(&self) -> i32 {
         self.in_teams.len().try_into().unwrap()
@@ -87,32 +159,139 @@ impl Learner {
         self.id
     }
 
+    /// Function to set the id of the learner and of its program
+    /// (e.g. when the learner is imported in a brain).
+    pub(crate) fn set_ids(&mut self, id: i32, program_id: i32) {
+        self.id = id;
+        self.program.set_id(program_id);
+    }
+
     /// Function to get the action or the team of the learner
-    pub fn get_action_team(&self, brain: &Brain) -> Option<usize> {
+    pub fn get_action_team(&self, brain: &Brain<T>) -> Option<usize> {
         brain.actions[self.action].get_action_team()
     }
 
     /// Function to get the action with the current state
-    pub fn get_action(&self, brain: &Brain, state: &Vec<i32>, visited: &mut Vec<i32>) -> i32 {
-        brain.actions[self.action].get_action(brain, state, visited)
+    pub fn get_action(
+        &self,
+        brain: &Brain<T>,
+        state: &[T],
+        context: &mut ExecutionContext<T>,
+    ) -> Result<i32, ActError> {
+        brain.actions[self.action].get_action(brain, state, context)
+    }
+
+    /// Function to set if the bid only execute the effective instructions of the program.
+    pub fn set_strip_introns(&mut self, strip_introns: bool) {
+        self.strip_introns = strip_introns;
+        self.compiled_program.reset();
+    }
+
+    /// Function to set if the registers are kept after the bid.
+    /// If true
+    ///     |-> The execution context keep the registers between the decisions
+    ///         (until `zero_registers`).
+    /// If false
+    ///     |-> Each bid start from the registers of the learner.
+    pub fn set_persistent_registers(&mut self, persistent_registers: bool) {
+        self.persistent_registers = persistent_registers;
+        self.compiled_program.reset();
+    }
+
+    /// Function to check if the registers are kept after the bid.
+    pub fn has_persistent_registers(&self) -> bool {
+        self.persistent_registers
+    }
+
+    /// Function to get the program executed by the bid (the effective program if the introns are stripped).
+    /// The introns are not stripped with persistent registers (the other registers are read by the next bid).
+    pub(crate) fn get_bid_program(&self, operations: &OperationSet<T>) -> &StoredProgram {
+        if self.strip_introns && !self.persistent_registers {
+            self.effective_program.get_or_init(|| {
+                self.program.get_effective_program(operations, self.registers.len())
+            })
+        } else {
+            &self.program
+        }
+    }
+
+    /// Function to compile the program of the bid for the size of the input and the memory
+    /// (e.g. before the first bid, so the bids don't compile the program).
+    /// The program is compiled again only if the size of the input, the memory or the operation
+    /// set changed. The packed learner is not compiled (the bid execute the packed program).
+    pub fn compile(&self, operations: &OperationSet<T>, input_len: usize, memory_len: usize) {
+        if self.is_packed() {
+            return;
+        }
+        let sizes = (input_len, self.registers.len(), memory_len);
+        self.compiled_program
+            .get_or_compile(operations, sizes, || self.compile_bid(operations, sizes));
+    }
+
+    /// Function to check if the program of the bid is compiled for the size of the input
+    /// and the memory (the next bid with these sizes execute the compiled program).
+    pub fn is_compiled_for(
+        &self,
+        operations: &OperationSet<T>,
+        input_len: usize,
+        memory_len: usize,
+    ) -> bool {
+        let num_registers = self.registers.len();
+        self.compiled_program.get().is_some_and(|compiled| {
+            compiled.is_compiled_for(operations, input_len, num_registers, memory_len)
+        })
+    }
+
+    /// Function to compile the program of the bid (size of the input, registers and memory).
+    fn compile_bid(
+        &self,
+        operations: &OperationSet<T>,
+        sizes: (usize, usize, usize),
+    ) -> CompiledProgram {
+        let (input_len, num_registers, memory_len) = sizes;
+        let program = self.get_bid_program(operations).get();
+        program.compile(operations, input_len, num_registers, memory_len)
     }
 
     /// Function to get the bid of the learner
-    pub fn bid(&mut self, state: &Vec<i32>) -> i32 {
-        self.program.execute(state, &mut self.registers);
-        // println!("Learner - Bid {}", self.registers[0]);
-        self.registers[0]
+    /// The program is executed on the registers (the registers are changed),
+    /// the bid is the register 0.
+    /// The bid execute the compiled program, the program is compiled at the first bid and
+    /// compiled again when the size of the input, the registers, the memory or the operation set
+    /// change. The packed learner execute directly the packed program (no compiled program is
+    /// kept, so the memory of the learner stay small).
+    /// The program can read and write the memory (if the memory is not empty).
+    /// If the introns are stripped then only the effective instructions are executed,
+    /// the register 0 is the same but the other registers can be different.
+    pub fn bid(
+        &self,
+        operations: &OperationSet<T>,
+        state: &[T],
+        registers: &mut [T],
+        memory: &mut Memory<T>,
+    ) -> T {
+        if let StoredProgram::Packed(packed) = self.get_bid_program(operations) {
+            packed.execute_with_memory(operations, state, registers, memory);
+            return registers[0];
+        }
+        let sizes = (state.len(), registers.len(), memory.get_size());
+        let compiled_program = self
+            .compiled_program
+            .get_or_compile(operations, sizes, || self.compile_bid(operations, sizes));
+        compiled_program.execute_resolved(operations, state, registers, memory);
+        registers[0]
     }
 
     /// Function to return if the action is atomic
-    pub fn is_action_atomic(&self, brain: &Brain) -> bool {
+    pub fn is_action_atomic(&self, brain: &Brain<T>) -> bool {
         brain.actions[self.action].is_atomic()
     }
 
     /// Function to mutate the learner
+    /// The mutated program is not packed.
     pub fn mutate(
         &mut self,
-        brain: &Brain,
+        brain: &Brain<T>,
         mutate_params: &LearnerParams,
         parent_team: usize,
         teams: &Vec<usize>,
@@ -129,7 +308,12 @@ impl Learner {
         while !changed {
             if flip(mutate_params.p_prog_mut) {
                 changed = true;
-                self.program.mutate(&mutate_params.program);
+                // The mutated program is not packed (see `Brain::pack_programs`)
+                let mut program = self.get_program();
+                program.mutate(&mutate_params.program, &brain.operations);
+                self.program = StoredProgram::Plain(program);
+                self.effective_program = OnceLock::new();
+                self.compiled_program.reset();
             }
 
             if flip(mutate_params.p_act_mut) {
@@ -139,3 +323,59 @@ impl Learner {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::brain::brain_from_assembly;
+    use crate::Agent;
+
+    #[test]
+    fn test_persistent_registers() {
+        let programs = [("r1 = r1 + in[0]\nr0 = r0 + r1", 4)];
+        let mut brain: Brain<i32> = brain_from_assembly(&programs, &[&[0]], &[]);
+        let learner = &brain.learners[0];
+        let mut memory: Memory<i32> = Memory::default();
+
+        // Check the bid change the registers
+        let mut registers: Vec<i32> = learner.get_registers().to_vec();
+        let operations = &brain.operations;
+        let bids: Vec<i32> =
+            (0..3).map(|_| learner.bid(operations, &[1], &mut registers, &mut memory)).collect();
+        assert_eq!(bids, vec![1, 3, 6], "The bids are incorrect");
+        assert_eq!(registers, vec![6, 3], "The registers are incorrect");
+        assert_eq!(learner.get_registers(), &[0, 0], "The registers of the learner are changed");
+
+        // Check the registers of the agent are kept until the reset
+        brain.learners[0].set_persistent_registers(true);
+
+        let mut agent = Agent::new(0, &brain);
+        let mut other_agent = Agent::new(0, &brain);
+        agent.act(&brain, &[2]).unwrap();
+        other_agent.act(&brain, &[10]).unwrap();
+        agent.act(&brain, &[3]).unwrap();
+        assert_eq!(
+            agent.get_registers(&brain),
+            vec![(0, &[7, 5][..])],
+            "The registers of the agent are incorrect"
+        );
+        assert_eq!(
+            other_agent.get_registers(&brain),
+            vec![(0, &[10, 10][..])],
+            "The registers of the other agent are incorrect"
+        );
+
+        // Check the reset of an agent don't change the other agent
+        agent.zero_registers();
+        assert_eq!(
+            agent.get_registers(&brain),
+            vec![(0, &[0, 0][..])],
+            "The registers of the agent are not reset"
+        );
+        assert_eq!(
+            other_agent.get_registers(&brain),
+            vec![(0, &[10, 10][..])],
+            "The registers of the other agent are reset"
+        );
+    }
+}
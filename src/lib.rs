@@ -1,29 +1,67 @@
 mod action;
 mod agent;
+mod assembly;
+mod batch;
 mod brain;
+mod checkpoint;
+mod codegen;
+mod compiled;
+mod context;
+mod dot;
+mod encoding;
+mod expression;
+mod garbage;
+mod handle;
 mod learner;
+mod memory;
+mod merge;
+mod operation;
 mod param;
 mod program;
+mod random;
+mod register;
+mod storage;
 mod team;
+mod trace;
 mod trainer;
+mod validate;
 
 pub use action::Action;
 pub use agent::Agent;
+pub use assembly::AssemblyError;
 pub use brain::Brain;
+pub use checkpoint::CHECKPOINT_FORMAT_VERSION;
+pub use codegen::CodegenError;
+pub use compiled::{CompiledError, CompiledProgram};
+pub use context::ExecutionContext;
+pub use encoding::{Encoding, EncodingError, PackedProgram};
+pub use expression::Expression;
+pub use garbage::GarbageReport;
+pub use handle::{ActionHandle, Handle, LearnerHandle, TeamHandle};
 pub use learner::Learner;
+pub use memory::Memory;
+pub use merge::ImportError;
+pub use operation::{
+    Addition, BuiltinOp, ConditionalNegate, Cosine, Double, Exponential, Halve, Logarithm, Maximum,
+    Minimum, Multiplication, Operation, OperationSet, ProtectedDivision, Sine, Subtraction,
+};
 pub use param::{Params,
-    ActionParams, LearnerParams, ProgramParams, TeamParams,
+    ActionParams, ArithmeticPolicy, CrossoverType, LearnerParams, ProgramParams, TeamParams,
     TrainerParams,
 };
-pub use program::Program;
-pub use team::Team;
-pub use trainer::Trainer;
+pub use program::{Instruction, Program};
+pub use random::{get_rng_state, rng, seed_rng, set_rng_state, RngState, TpgRng};
+pub use register::Register;
+pub use storage::{StorageError, BRAIN_FORMAT_VERSION};
+pub use team::{ActError, Team};
+pub use trace::{DecisionTrace, TeamTrace};
+pub use trainer::{EvolveError, Trainer};
+pub use validate::Violation;
 
 use rand::Rng;
 
 /// Function to determine if the proba is apply
 pub fn flip(proba: f64) -> bool {
-    let mut rng = rand::thread_rng();
-    let y: f64 = rng.gen();
+    let y: f64 = rng().gen();
     y < proba
 }
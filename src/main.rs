@@ -16,7 +16,7 @@ fn main() {
 
     println!("Initialization of population terminated!");
 
-    println!("List of teams: {:#?}", brain.teams);
+    println!("Initial teams (in the DOT language):\n{}", brain.to_dot());
 
     // Test in several iterations
     for i in 0..100 {
@@ -30,15 +30,18 @@ fn main() {
         
         for agent in agents.iter_mut() {
 
-            let state: Vec<i32> = vec![1, 1, 1, 1];
-
-            let act = agent.act(&mut brain, &state);
-            println!("The action take by the agent: {}", act);
+            let state: Vec<f64> = vec![1.0, 1.0, 1.0, 1.0];
 
             // define a reward for the test (just to test the code)
             let mut score = -100;
-            if act == 2 {
-                score = 100;
+            match agent.act(&brain, &state) {
+                Ok(act) => {
+                    println!("The action take by the agent: {}", act);
+                    if act == 2 {
+                        score = 100;
+                    }
+                }
+                Err(error) => println!("The agent can't act: {}", error),
             }
 
             println!("Before reward the agent");
@@ -51,8 +54,17 @@ fn main() {
         let extra_teams: Vec<usize> = Vec::new();
 
         println!("Before evolve the trainer");
-        trainer.evolve(&mut brain, tasks, &extra_teams);
+        if let Err(error) = trainer.evolve(&mut brain, tasks, &extra_teams) {
+            println!("The evolution failed: {}", error);
+            break;
+        }
+
+        // Remove the teams culled by the evolution from the brain
+        let report = trainer.collect_garbage(&mut brain, &extra_teams);
+        println!("Number of entities freed: {}", report.get_nb_freed());
     }
 
-    println!("Teams (in the brain): {:#?}", brain.teams);
+    // Draw the brain with Graphviz: dot -Tsvg brain.dot -o brain.svg
+    std::fs::write("brain.dot", brain.to_dot()).expect("error while writing the brain");
+    println!("Teams (in the brain) written in brain.dot");
 }
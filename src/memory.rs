@@ -0,0 +1,138 @@
+//! # Memory
+//! Crate that permit to represent the indexed memory of the Tangled Program Graph (TPG).
+//! The memory keep values across the decisions of an agent (the registers are reset at each bid),
+//! so the agent can solve partially observable tasks.
+//! The instructions read the memory with the mode 2 and write the memory with the mode 3:
+//!     mode 2 |-> r[dst] = op(r[dst], mem[src])
+//!     mode 3 |-> mem[dst] = op(mem[dst], r[src])   (only with the probability of write)
+//! The index of the memory is taken modulo the size of the memory.
+
+#![deny(missing_docs)]
+#![deny(clippy::all)]
+#![deny(clippy::missing_docs_in_private_items)]
+
+use crate::{flip, Register};
+use serde::{Deserialize, Serialize};
+
+/// Structure to represent the indexed memory.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(bound = "T: Register")]
+pub struct Memory<T: Register> {
+    /// The values of the memory.
+    values: Vec<T>,
+    /// The probability that a write instruction change the memory.
+    p_write: f64,
+}
+
+impl<T: Register> Memory<T> {
+    /// Constructor of the memory (all the values are 0).
+    pub fn new(size: usize, p_write: f64) -> Self {
+        Memory {
+            values: vec![T::default(); size],
+            p_write,
+        }
+    }
+
+    /// Function to reset all the values of the memory to 0.
+    pub fn reset(&mut self) {
+        self.values.fill(T::default());
+    }
+
+    /// Function to get the number of values in the memory.
+    pub fn get_size(&self) -> usize {
+        self.values.len()
+    }
+
+    /// Function to check if the memory is empty (the memory instructions are skipped).
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Function to get the values of the memory.
+    pub fn get_values(&self) -> &[T] {
+        &self.values
+    }
+
+    /// Function to get the probability of write.
+    pub fn get_p_write(&self) -> f64 {
+        self.p_write
+    }
+
+    /// Function to read the value at the index (modulo the size of the memory).
+    /// Return None if the memory is empty.
+    pub fn read(&self, index: i32) -> Option<T> {
+        if self.values.is_empty() {
+            return None;
+        }
+        let index = i64::from(index).rem_euclid(self.values.len() as i64) as usize;
+        Some(self.values[index])
+    }
+
+    /// Function to write the value at the index (modulo the size of the memory).
+    /// The value is written with the probability of write.
+    /// Return true if the memory is changed.
+    pub fn write(&mut self, index: i32, value: T) -> bool {
+        if self.values.is_empty() || !flip(self.p_write) {
+            return false;
+        }
+        let index = i64::from(index).rem_euclid(self.values.len() as i64) as usize;
+        self.values[index] = value;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::brain::brain_from_assembly;
+    use crate::{Agent, Brain};
+
+    #[test]
+    fn test_memory() {
+        let mut memory: Memory<i32> = Memory::new(4, 1.0);
+
+        // Check the index is taken modulo the size
+        assert!(memory.write(6, 5), "The value is not written");
+        assert_eq!(memory.read(2), Some(5), "The value read is incorrect");
+        assert_eq!(memory.read(-2), Some(5), "The negative index is incorrect");
+        assert_eq!(memory.get_values(), &[0, 0, 5, 0], "The values are incorrect");
+
+        // Check the reset
+        memory.reset();
+        assert_eq!(memory.get_values(), &[0, 0, 0, 0], "The memory is not reset");
+
+        // Check the probability of write
+        let mut memory: Memory<i32> = Memory::new(4, 0.0);
+        assert!(!memory.write(1, 5), "The value is written with a probability 0");
+        assert_eq!(memory.read(1), Some(0), "The memory is changed");
+
+        // Check the empty memory
+        let mut memory: Memory<i32> = Memory::default();
+        assert!(!memory.write(1, 5), "The value is written in an empty memory");
+        assert_eq!(memory.read(1), None, "The value is read from an empty memory");
+    }
+
+    #[test]
+    fn test_memory_agent() {
+        // The first learner bid the memory, the second learner bid the input 0 and write the input 1
+        let programs = [
+            ("r0 = r0 + mem[0]", 1),
+            ("r1 = r1 + in[1]\nmem[0] = mem[0] + r1\nr0 = r0 + in[0]", 2),
+        ];
+        let mut brain: Brain<i32> = brain_from_assembly(&programs, &[&[0, 1]], &[]);
+        brain.memory = Memory::new(2, 1.0);
+
+        // Check the memory is kept across the decisions
+        let mut agent = Agent::new(0, &brain);
+        assert_eq!(agent.act(&brain, &[1, 5]).unwrap(), 2, "The first action is incorrect");
+        assert_eq!(agent.get_memory(), &[5, 0], "The memory is not written");
+        assert_eq!(agent.act(&brain, &[1, 5]).unwrap(), 1, "The memory is not read");
+        assert_eq!(agent.get_memory(), &[10, 0], "The memory is not kept");
+
+        // Check the reset of the memory
+        agent.reset_memory();
+        assert_eq!(agent.get_memory(), &[0, 0], "The memory is not reset");
+        let action = agent.act(&brain, &[1, 5]).unwrap();
+        assert_eq!(action, 2, "The action after reset is incorrect");
+    }
+}
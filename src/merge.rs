@@ -0,0 +1,190 @@
+//! # Merge
+//! Crate that permit to import the teams of an other brain in the brain of the
+//! Tangled Program Graph (TPG).
+//! The root teams of the other brain and the teams, the learners (with their program) and the
+//! actions reachable from them are copied at the end of the lists of the brain.
+//! The index are remapped and the copies get new ids from the counters of the params, so the
+//! ids don't collide with the entities created by the trainer.
+//! The imported root teams can be given as extra teams to `Trainer::evolve`, so the new
+//! population can call the specialist teams trained in an other run (hierarchical transfer).
+
+#![deny(missing_docs)]
+#![deny(clippy::all)]
+#![deny(clippy::missing_docs_in_private_items)]
+
+use crate::garbage::get_remap;
+use crate::{Brain, Register, Trainer, TrainerParams};
+use std::fmt;
+
+/// Enumeration to represent an error during the import of the teams of an other brain.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ImportError {
+    /// The operations of the two brains are not the same.
+    OperationMismatch {
+        /// The name of the operations of the other brain.
+        found: Vec<String>,
+        /// The name of the operations of the brain.
+        expected: Vec<String>,
+    },
+    /// The root team is not in the other brain (the index of the team).
+    UnknownTeam(usize),
+}
+
+impl fmt::Display for ImportError {
+    /// Function to display the error.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImportError::OperationMismatch { found, expected } => write!(
+                f,
+                "the operations {:?} are not the operations {:?}",
+                found, expected
+            ),
+            ImportError::UnknownTeam(team) => write!(f, "the team {} is not in the brain", team),
+        }
+    }
+}
+
+impl std::error::Error for ImportError {}
+
+/// Function to get the new index of the kept entities, after the entities already in the brain.
+fn get_offset_remap(kept: &[bool], len: usize) -> Vec<Option<usize>> {
+    get_remap(kept).into_iter().map(|idx| idx.map(|idx| idx + len)).collect()
+}
+
+impl<T: Register> Brain<T> {
+    /// Function to copy the root teams of the other brain (index of the teams) and everything
+    /// reachable from them in the brain. The ids of the copies are given by the params
+    /// (the params of the trainer of the brain, see `Trainer::import`).
+    /// The operations of the two brains must be the same, the execution context of the other
+    /// brain is not copied.
+    /// Return the new index of the root teams (e.g. the extra teams of `Trainer::evolve`),
+    /// or an error if the operations are not the same or a root team is not in the other brain
+    /// (the brain is not changed).
+    pub fn import(
+        &mut self,
+        other: &Brain<T>,
+        root_teams: &[usize],
+        params: &mut TrainerParams,
+    ) -> Result<Vec<usize>, ImportError> {
+        if self.operations.names() != other.operations.names() {
+            return Err(ImportError::OperationMismatch {
+                found: other.operations.names().into_iter().map(String::from).collect(),
+                expected: self.operations.names().into_iter().map(String::from).collect(),
+            });
+        }
+        if let Some(root) = root_teams.iter().find(|root| **root >= other.teams.len()) {
+            return Err(ImportError::UnknownTeam(*root));
+        }
+
+        let reachable = other.get_reachable(root_teams);
+        let team_remap = get_offset_remap(&reachable.teams, self.teams.len());
+        let learner_remap = get_offset_remap(&reachable.learners, self.learners.len());
+        let action_remap = get_offset_remap(&reachable.actions, self.actions.len());
+        let remap_team = |idx: usize| team_remap.get(idx).copied().flatten();
+        let remap_learner = |idx: usize| learner_remap.get(idx).copied().flatten();
+
+        for (team, _) in other.teams.iter().zip(&reachable.teams).filter(|(_, keep)| **keep) {
+            let mut team = team.clone();
+            team.set_id(params.team.get_new_id_team());
+            team.remap_learners(&remap_learner);
+            self.add_team(team);
+        }
+        let learners = other.learners.iter().zip(&reachable.learners);
+        for (learner, _) in learners.filter(|(_, keep)| **keep) {
+            let mut learner = learner.clone();
+            let learner_params = &mut params.team.learner;
+            let id = learner_params.get_new_id_learner();
+            learner.set_ids(id, learner_params.program.get_new_id_program());
+            learner.remap_in_teams(&remap_team);
+            learner.set_idx_action(action_remap[learner.get_idx_action()].unwrap());
+            self.add_learner(learner);
+        }
+        let actions = other.actions.iter().zip(&reachable.actions);
+        for (action, _) in actions.filter(|(_, keep)| **keep) {
+            let mut action = action.clone();
+            action.set_id(params.team.learner.action.get_new_id_action());
+            if let Some(team_idx) = action.get_action_team() {
+                action.set_action_team(remap_team(team_idx));
+            }
+            self.add_action(action);
+        }
+
+        Ok(root_teams.iter().filter_map(|root| team_remap[*root]).collect())
+    }
+}
+
+impl Trainer {
+    /// Function to copy the root teams of the other brain and everything reachable from them
+    /// in the brain of the trainer (the ids are given by the params of the trainer).
+    /// Return the new index of the root teams (e.g. the extra teams of `evolve`),
+    /// or an error if the teams can't be imported (see `Brain::import`).
+    pub fn import<T: Register>(
+        &mut self,
+        brain: &mut Brain<T>,
+        other: &Brain<T>,
+        root_teams: &[usize],
+    ) -> Result<Vec<usize>, ImportError> {
+        brain.import(other, root_teams, &mut self.mutate_params)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::brain::brain_from_assembly_with_params;
+    use crate::{Agent, OperationSet};
+    use std::collections::HashSet;
+
+    /// Function to get a brain: the root team 1 call the team 0 with the learner 2.
+    fn get_brain(params: &mut TrainerParams, programs: &[&str]) -> Brain<i32> {
+        let programs: Vec<(&str, i32)> =
+            programs.iter().enumerate().map(|(i, text)| (*text, i as i32 + 1)).collect();
+        brain_from_assembly_with_params(&mut params.team, &programs, &[&[0, 1], &[2, 3]], &[(2, 0)])
+    }
+
+    #[test]
+    fn test_import() {
+        let programs = ["r0 = r0 + in[0]", "r0 = r0 - in[0]", "r0 = r0 + in[1]", "r0 = r0 - in[1]"];
+        let mut params = TrainerParams::new();
+        let mut brain = get_brain(&mut params, &programs);
+        let other = get_brain(&mut TrainerParams::new(), &programs);
+
+        // Check the root team and the team called are copied after the teams of the brain
+        let roots = brain.import(&other, &[1], &mut params).unwrap();
+        assert_eq!(roots, vec![3], "The index of the imported root is incorrect");
+        assert_eq!(brain.teams.len(), 4, "The number of teams is incorrect");
+        assert_eq!(brain.learners.len(), 8, "The number of learners is incorrect");
+        assert_eq!(brain.teams[3].get_learners(), vec![6, 7], "The learners are not remapped");
+        assert_eq!(brain.learners[6].get_idx_action(), 6, "The action is not remapped");
+        assert_eq!(brain.actions[6].get_action_team(), Some(2), "The team is not remapped");
+
+        // Check the ids don't collide and the counters are updated
+        let ids: HashSet<i32> = brain.teams.iter().map(|team| team.get_id()).collect();
+        assert_eq!(ids.len(), 4, "The id of the teams collide");
+        let ids: HashSet<i32> = brain.learners.iter().map(|learner| learner.get_id()).collect();
+        assert_eq!(ids.len(), 8, "The id of the learners collide");
+        assert_eq!(params.team.id_counter_team, 4, "The counter of the teams is not updated");
+        assert_eq!(brain.get_team_index_from_team_id(3), Some(3), "The id is not registered");
+
+        // Check the imported team give the same actions
+        let mut agent = Agent::new(1, &other);
+        let mut imported_agent = Agent::new(roots[0], &brain);
+        for state in [[1, 5], [-3, 2], [4, -1]] {
+            let action = agent.act(&other, &state).unwrap();
+            let imported_action = imported_agent.act(&brain, &state).unwrap();
+            assert_eq!(imported_action, action, "The action is incorrect");
+        }
+
+        // Check the errors don't change the brain
+        let error = brain.import(&other, &[0, 2], &mut params);
+        assert_eq!(error, Err(ImportError::UnknownTeam(2)), "The error is incorrect");
+        let mut other_operations = get_brain(&mut TrainerParams::new(), &programs);
+        other_operations.operations = OperationSet::new();
+        let error = brain.import(&other_operations, &[1], &mut params);
+        assert!(
+            matches!(error, Err(ImportError::OperationMismatch { .. })),
+            "The operations are not checked"
+        );
+        assert_eq!(brain.teams.len(), 4, "The brain is changed by the error");
+    }
+}
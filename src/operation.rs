@@ -0,0 +1,492 @@
+//! # Operation
+//! Crate that permit to define the operations executed by the instructions of the Tangled Program Graph (TPG).
+//! Each instruction compute `regs[dst] = op(x, y)` with `x = regs[dst]` and `y` the source value
+//! (taken from the register or the input).
+//! The builtin operations give their kind (`Operation::kind`), so the simplification of the
+//! expressions and the generation of code don't depend on the name of the operations.
+
+#![deny(missing_docs)]
+#![deny(clippy::all)]
+#![deny(clippy::missing_docs_in_private_items)]
+
+use crate::{ArithmeticPolicy, Register};
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// The counter of the versions of the registries (see `OperationSet::get_version`).
+static VERSION_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Function to get a new version for a registry.
+fn new_version() -> u64 {
+    VERSION_COUNTER.fetch_add(1, Ordering::Relaxed)
+}
+
+/// Enumeration to represent the kind of the builtin operations.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BuiltinOp {
+    /// Operation x + y.
+    Add,
+    /// Operation x - y.
+    Sub,
+    /// Operation x * 2.
+    Double,
+    /// Operation x / 2.
+    Halve,
+    /// Operation -x if x < y.
+    NegIfLess,
+    /// Operation x * y.
+    Mul,
+    /// Operation x / y (protected).
+    Div,
+    /// Operation max(x, y).
+    Max,
+    /// Operation min(x, y).
+    Min,
+    /// Operation sin(x).
+    Sin,
+    /// Operation cos(x).
+    Cos,
+    /// Operation exp(x).
+    Exp,
+    /// Operation ln(|x|) (protected).
+    Log,
+}
+
+/// Trait to represent an operation that can be used by the instructions.
+pub trait Operation<T: Register> {
+    /// Function to get the name of the operation.
+    fn name(&self) -> &str;
+
+    /// Function to get the kind of the operation if it is a builtin operation.
+    /// The operations defined by the user return None (they are not simplified in the
+    /// expressions and can't be generated as Rust code).
+    fn kind(&self) -> Option<BuiltinOp> {
+        None
+    }
+
+    /// Function to get the number of operands used by the operation.
+    /// If 1
+    ///     |-> The operation only use the destination register (x).
+    /// If 2
+    ///     |-> The operation use the destination register (x) and the source (y).
+    fn arity(&self) -> usize;
+
+    /// Function to apply the operation on the destination value (x) and the source value (y).
+    /// The policy define how to handle the invalid result (overflow, NaN, infinity).
+    fn apply(&self, x: T, y: T, policy: ArithmeticPolicy) -> T;
+
+    /// Function to apply the operation on a column of destination values (x) with a column of
+    /// source values (y), the result is written in x.
+    /// The default loop call `apply` without dynamic dispatch, so it can be vectorized.
+    fn apply_batch(&self, x: &mut [T], y: &[T], policy: ArithmeticPolicy) {
+        for (x, y) in x.iter_mut().zip(y) {
+            *x = self.apply(*x, *y, policy);
+        }
+    }
+}
+
+/// Structure to represent the registry of operations available for the programs.
+/// The code of the operation in the instruction is the index in the registry.
+pub struct OperationSet<T: Register> {
+    /// The list of operations.
+    operations: Vec<Box<dyn Operation<T>>>,
+    /// The version of the registry (unique for each registry, changed when an operation is added).
+    version: u64,
+}
+
+impl<T: Register> Default for OperationSet<T> {
+    /// Function to create the registry with the default operations:
+    ///     0 |-> x + y
+    ///     1 |-> x - y
+    ///     2 |-> x * 2
+    ///     3 |-> x / 2
+    ///     4 |-> -x (only if x < y)
+    ///     5 |-> x * y
+    ///     6 |-> x / y (protected)
+    fn default() -> Self {
+        let mut operations: OperationSet<T> = OperationSet::new();
+        operations.add_operation(Box::new(Addition));
+        operations.add_operation(Box::new(Subtraction));
+        operations.add_operation(Box::new(Double));
+        operations.add_operation(Box::new(Halve));
+        operations.add_operation(Box::new(ConditionalNegate));
+        operations.add_operation(Box::new(Multiplication));
+        operations.add_operation(Box::new(ProtectedDivision));
+        operations
+    }
+}
+
+impl<T: Register> fmt::Debug for OperationSet<T> {
+    /// Function to display the name of the operations in the registry.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.names()).finish()
+    }
+}
+
+impl<T: Register> OperationSet<T> {
+    /// Constructor of an empty registry.
+    pub fn new() -> Self {
+        OperationSet {
+            operations: Vec::new(),
+            version: new_version(),
+        }
+    }
+
+    /// Function to add an operation in the registry.
+    /// Return the code of the operation.
+    pub fn add_operation(&mut self, operation: Box<dyn Operation<T>>) -> i32 {
+        self.operations.push(operation);
+        self.version = new_version();
+        (self.operations.len() - 1).try_into().unwrap()
+    }
+
+    /// Function to get the operation with the code.
+    pub fn get(&self, op: i32) -> Option<&dyn Operation<T>> {
+        let idx: usize = op.try_into().ok()?;
+        self.operations.get(idx).map(|operation| operation.as_ref())
+    }
+
+    /// Function to get the version of the registry.
+    /// Two registries (or the same registry before and after an added operation) never have the
+    /// same version, so the compiled programs are never executed with other operations.
+    pub(crate) fn get_version(&self) -> u64 {
+        self.version
+    }
+
+    /// Function to get the operation with an index already checked (by the compiled program).
+    pub(crate) fn get_resolved(&self, idx: usize) -> &dyn Operation<T> {
+        self.operations[idx].as_ref()
+    }
+
+    /// Function to get the code of the operation with the name.
+    pub fn get_code(&self, name: &str) -> Option<i32> {
+        let idx = self.operations.iter().position(|op| op.name() == name)?;
+        idx.try_into().ok()
+    }
+
+    /// Function to get the number of operations in the registry.
+    pub fn len(&self) -> usize {
+        self.operations.len()
+    }
+
+    /// Function to check if the registry is empty.
+    pub fn is_empty(&self) -> bool {
+        self.operations.is_empty()
+    }
+
+    /// Function to get the name of all operations in the registry.
+    pub fn names(&self) -> Vec<&str> {
+        self.operations.iter().map(|op| op.name()).collect()
+    }
+}
+
+/// Operation x + y.
+pub struct Addition;
+
+impl<T: Register> Operation<T> for Addition {
+    fn name(&self) -> &str {
+        "add"
+    }
+
+    fn kind(&self) -> Option<BuiltinOp> {
+        Some(BuiltinOp::Add)
+    }
+
+    fn arity(&self) -> usize {
+        2
+    }
+
+    fn apply(&self, x: T, y: T, policy: ArithmeticPolicy) -> T {
+        x.plus(y, policy)
+    }
+}
+
+/// Operation x - y.
+pub struct Subtraction;
+
+impl<T: Register> Operation<T> for Subtraction {
+    fn name(&self) -> &str {
+        "sub"
+    }
+
+    fn kind(&self) -> Option<BuiltinOp> {
+        Some(BuiltinOp::Sub)
+    }
+
+    fn arity(&self) -> usize {
+        2
+    }
+
+    fn apply(&self, x: T, y: T, policy: ArithmeticPolicy) -> T {
+        x.minus(y, policy)
+    }
+}
+
+/// Operation x * 2.
+pub struct Double;
+
+impl<T: Register> Operation<T> for Double {
+    fn name(&self) -> &str {
+        "double"
+    }
+
+    fn kind(&self) -> Option<BuiltinOp> {
+        Some(BuiltinOp::Double)
+    }
+
+    fn arity(&self) -> usize {
+        1
+    }
+
+    fn apply(&self, x: T, _y: T, policy: ArithmeticPolicy) -> T {
+        x.double(policy)
+    }
+}
+
+/// Operation x / 2.
+pub struct Halve;
+
+impl<T: Register> Operation<T> for Halve {
+    fn name(&self) -> &str {
+        "halve"
+    }
+
+    fn kind(&self) -> Option<BuiltinOp> {
+        Some(BuiltinOp::Halve)
+    }
+
+    fn arity(&self) -> usize {
+        1
+    }
+
+    fn apply(&self, x: T, _y: T, policy: ArithmeticPolicy) -> T {
+        x.halve(policy)
+    }
+}
+
+/// Operation -x if x < y, else x is unchanged.
+pub struct ConditionalNegate;
+
+impl<T: Register> Operation<T> for ConditionalNegate {
+    fn name(&self) -> &str {
+        "neg_if_less"
+    }
+
+    fn kind(&self) -> Option<BuiltinOp> {
+        Some(BuiltinOp::NegIfLess)
+    }
+
+    fn arity(&self) -> usize {
+        2
+    }
+
+    fn apply(&self, x: T, y: T, policy: ArithmeticPolicy) -> T {
+        if x < y {
+            x.negate(policy)
+        } else {
+            x
+        }
+    }
+}
+
+/// Operation x * y.
+pub struct Multiplication;
+
+impl<T: Register> Operation<T> for Multiplication {
+    fn name(&self) -> &str {
+        "mul"
+    }
+
+    fn kind(&self) -> Option<BuiltinOp> {
+        Some(BuiltinOp::Mul)
+    }
+
+    fn arity(&self) -> usize {
+        2
+    }
+
+    fn apply(&self, x: T, y: T, policy: ArithmeticPolicy) -> T {
+        x.times(y, policy)
+    }
+}
+
+/// Operation x / y (protected, keep x if y is close to 0).
+pub struct ProtectedDivision;
+
+impl<T: Register> Operation<T> for ProtectedDivision {
+    fn name(&self) -> &str {
+        "div"
+    }
+
+    fn kind(&self) -> Option<BuiltinOp> {
+        Some(BuiltinOp::Div)
+    }
+
+    fn arity(&self) -> usize {
+        2
+    }
+
+    fn apply(&self, x: T, y: T, policy: ArithmeticPolicy) -> T {
+        x.divide(y, policy)
+    }
+}
+
+/// Operation max(x, y).
+pub struct Maximum;
+
+impl<T: Register> Operation<T> for Maximum {
+    fn name(&self) -> &str {
+        "max"
+    }
+
+    fn kind(&self) -> Option<BuiltinOp> {
+        Some(BuiltinOp::Max)
+    }
+
+    fn arity(&self) -> usize {
+        2
+    }
+
+    fn apply(&self, x: T, y: T, _policy: ArithmeticPolicy) -> T {
+        if y > x {
+            y
+        } else {
+            x
+        }
+    }
+}
+
+/// Operation min(x, y).
+pub struct Minimum;
+
+impl<T: Register> Operation<T> for Minimum {
+    fn name(&self) -> &str {
+        "min"
+    }
+
+    fn kind(&self) -> Option<BuiltinOp> {
+        Some(BuiltinOp::Min)
+    }
+
+    fn arity(&self) -> usize {
+        2
+    }
+
+    fn apply(&self, x: T, y: T, _policy: ArithmeticPolicy) -> T {
+        if y < x {
+            y
+        } else {
+            x
+        }
+    }
+}
+
+/// Operation sin(x).
+pub struct Sine;
+
+/// Operation cos(x).
+pub struct Cosine;
+
+/// Operation exp(x).
+pub struct Exponential;
+
+/// Operation ln(|x|) (protected, 0 if x is 0).
+pub struct Logarithm;
+
+/// Macro to implement the unary float operations.
+macro_rules! impl_operation_float {
+    ($op:ty, $name:expr, $kind:ident, $t:ty, |$x:ident| $body:expr) => {
+        impl Operation<$t> for $op {
+            fn name(&self) -> &str {
+                $name
+            }
+
+            fn kind(&self) -> Option<BuiltinOp> {
+                Some(BuiltinOp::$kind)
+            }
+
+            fn arity(&self) -> usize {
+                1
+            }
+
+            fn apply(&self, $x: $t, _y: $t, policy: ArithmeticPolicy) -> $t {
+                let value: $t = $body;
+                value.finite(policy)
+            }
+        }
+    };
+}
+
+impl_operation_float!(Sine, "sin", Sin, f32, |x| x.sin());
+impl_operation_float!(Sine, "sin", Sin, f64, |x| x.sin());
+impl_operation_float!(Cosine, "cos", Cos, f32, |x| x.cos());
+impl_operation_float!(Cosine, "cos", Cos, f64, |x| x.cos());
+impl_operation_float!(Exponential, "exp", Exp, f32, |x| x.exp());
+impl_operation_float!(Exponential, "exp", Exp, f64, |x| x.exp());
+impl_operation_float!(Logarithm, "log", Log, f32, |x| if x == 0.0 { 0.0 } else { x.abs().ln() });
+impl_operation_float!(Logarithm, "log", Log, f64, |x| if x == 0.0 { 0.0 } else { x.abs().ln() });
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Operation defined by the user to test the registry.
+    struct Square;
+
+    impl Operation<i32> for Square {
+        fn name(&self) -> &str {
+            "square"
+        }
+
+        fn arity(&self) -> usize {
+            1
+        }
+
+        fn apply(&self, x: i32, _y: i32, policy: ArithmeticPolicy) -> i32 {
+            x.times(x, policy)
+        }
+    }
+
+    /// The policy used by the tests.
+    const POLICY: ArithmeticPolicy = ArithmeticPolicy::Wrapping;
+
+    #[test]
+    fn test_operation_set() {
+        let mut operations: OperationSet<i32> = OperationSet::default();
+
+        // Check the number of default operations
+        assert_eq!(operations.len(), 7, "The number of default operations is incorrect");
+
+        // Check the code of the operation added by the user
+        let code = operations.add_operation(Box::new(Square));
+        assert_eq!(code, 7, "The code of the new operation is incorrect");
+        assert_eq!(operations.get_code("square"), Some(7), "The operation is not found");
+
+        // Check the operation is applied
+        let square = operations.get(code).unwrap();
+        assert_eq!(square.apply(3, 0, POLICY), 9, "The result of the operation is incorrect");
+        assert_eq!(square.arity(), 1, "The arity of the operation is incorrect");
+
+        // Check the unknown operation
+        assert!(operations.get(8).is_none(), "The unknown operation is found");
+        assert!(operations.get(-1).is_none(), "The negative operation is found");
+    }
+
+    #[test]
+    fn test_operation_float() {
+        // Check the protected logarithm
+        assert_eq!(Logarithm.apply(0.0_f64, 0.0, POLICY), 0.0, "The log of 0 is incorrect");
+        assert_eq!(Logarithm.apply(-1.0_f64, 0.0, POLICY), 0.0, "The log of -1 is incorrect");
+
+        // Check the overflow of the exponential
+        assert_eq!(
+            Exponential.apply(1000.0_f64, 0.0, POLICY),
+            f64::MAX,
+            "The exponential is not clamped"
+        );
+
+        // Check the min and the max
+        assert_eq!(Maximum.apply(1.0_f32, 2.0, POLICY), 2.0, "The max is incorrect");
+        assert_eq!(Minimum.apply(1.0_f32, 2.0, POLICY), 1.0, "The min is incorrect");
+    }
+}
@@ -8,6 +8,33 @@
 use std::fs::File;
 use serde::{Serialize, Deserialize};
 
+/// Enumeration to represent how the registers handle an invalid result of an operation.
+/// For the integer registers the invalid result is an overflow.
+/// For the float registers the invalid result is NaN or infinity.
+#[derive(Serialize, Deserialize, Default, Clone, Copy, Debug, PartialEq)]
+pub enum ArithmeticPolicy {
+    /// The integer wrap around the bounds, the float are clamped (NaN |-> 0).
+    #[default]
+    Wrapping,
+    /// The integer and the float are clamped to the bounds (NaN |-> 0).
+    Saturating,
+    /// The invalid result is replaced by the fallback value.
+    Checked {
+        /// The value used when the result is invalid.
+        fallback: f64,
+    },
+}
+
+/// Enumeration to represent the crossover between two programs.
+#[derive(Serialize, Deserialize, Default, Clone, Copy, Debug, PartialEq)]
+pub enum CrossoverType {
+    /// The tail of the programs are exchanged after one cut point in each parent.
+    OnePoint,
+    /// A segment of the programs (between two cut points in each parent) are exchanged.
+    #[default]
+    TwoPoint,
+}
+
 /// Structure to represent all information for the init and mutation of program
 #[derive(Serialize, Deserialize, Default, Clone, Debug)]
 pub struct ProgramParams {
@@ -15,8 +42,6 @@ pub struct ProgramParams {
     pub id_counter_program: i32,
     /// The len of the program.
     pub max_program_length: i32,
-    /// The number of operation possible in the program.
-    pub nb_operations: i32,
     /// The size of the input.
     pub input_size: i32,
     /// The number of destination possilbe in the program.
@@ -29,9 +54,32 @@ pub struct ProgramParams {
     pub p_inst_swap: f64,
     /// The probability to add the instruction.
     pub p_inst_add: f64,
+    /// The arithmetic used by the operations of the program.
+    #[serde(default)]
+    pub arithmetic: ArithmeticPolicy,
+    /// The crossover used to combine two programs.
+    #[serde(default)]
+    pub crossover: CrossoverType,
+    /// The size of the memory of the brain.
+    /// (0 = no memory, the instructions only use the register and the input)
+    #[serde(default)]
+    pub memory_size: i32,
+    /// The probability that an instruction write the memory.
+    #[serde(default)]
+    pub p_memory_write: f64,
 }
 
 impl ProgramParams {
+    /// Function to get the number of modes of the instructions.
+    /// The modes 2 and 3 (read and write the memory) are only used if there is a memory.
+    pub fn get_nb_modes(&self) -> i32 {
+        if self.memory_size > 0 {
+            4
+        } else {
+            2
+        }
+    }
+
     /// Function to return a new id for the program.
     pub fn get_new_id_program(&mut self) -> i32 {
         let id_program = self.id_counter_program;
@@ -49,6 +97,16 @@ pub struct LearnerParams {
     pub p_prog_mut: f64,
     /// The probability to mutate the action in the learner
     pub p_act_mut: f64,
+    /// If true the learner only execute the effective instructions (no intron) to compute the bid.
+    #[serde(default)]
+    pub strip_introns: bool,
+    /// The probability to create a learner with the crossover of two parent learners.
+    #[serde(default)]
+    pub p_crossover: f64,
+    /// If true the registers of the learner are kept between the decisions of the agent
+    /// (until `Agent::zero_registers`), else each bid start from the same registers.
+    #[serde(default)]
+    pub persistent_registers: bool,
     /// The list of mutation parameter for the program in the learner
     pub program: ProgramParams,
     /// The list of mutation parameter for the action in the learner
@@ -126,6 +184,19 @@ pub struct TrainerParams {
     pub generation: i32,
     /// The probability information about the team
     pub team: TeamParams,
+    /// The number of generations between two checkpoints saved by the evolution (0 = never).
+    #[serde(default)]
+    pub checkpoint_interval: i32,
+    /// The number of checkpoints kept in the directory, the oldest are removed (0 = all).
+    #[serde(default)]
+    pub checkpoint_retention: i32,
+    /// The directory of the checkpoints saved by the evolution.
+    #[serde(default)]
+    pub checkpoint_dir: String,
+    /// If true the invariants of the brain are checked after each evolution
+    /// (the evolution return an error with the violations).
+    #[serde(default)]
+    pub validate_after_evolve: bool,
 }
 
 impl TrainerParams {
@@ -137,6 +208,10 @@ impl TrainerParams {
             max_team_in_population: 5,
             generation: 0,
             team: TeamParams::default(),
+            checkpoint_interval: 0,
+            checkpoint_retention: 0,
+            checkpoint_dir: String::new(),
+            validate_after_evolve: false,
         }
     }
 }
@@ -5,10 +5,62 @@
 #![deny(clippy::all)]
 #![deny(clippy::missing_docs_in_private_items)]
 
-use crate::{flip, ProgramParams};
+use crate::{
+    flip, rng, ArithmeticPolicy, CrossoverType, Memory, OperationSet, ProgramParams, Register,
+};
 use rand::Rng;
+use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Debug)]
+/// Function to get a valid index (in 0..len) from the index of an instruction.
+pub(crate) fn wrap_index(index: i32, len: usize) -> usize {
+    i64::from(index).rem_euclid(len as i64) as usize
+}
+
+/// Function to execute one instruction on the registers (not empty).
+/// The instruction reading an empty input (or memory) or with an unknown operation is skipped.
+pub(crate) fn execute_instruction<T: Register>(
+    instruction: &Instruction,
+    operations: &OperationSet<T>,
+    arithmetic: ArithmeticPolicy,
+    input: &[T],
+    regs: &mut [T],
+    memory: &mut Memory<T>,
+) {
+    let Some(operation) = operations.get(instruction.op) else {
+        return;
+    };
+
+    // Write the result in the memory
+    if instruction.mode == 3 {
+        if let Some(x) = memory.read(instruction.dst) {
+            let y = regs[wrap_index(instruction.src, regs.len())];
+            memory.write(instruction.dst, operation.apply(x, y, arithmetic));
+        }
+        return;
+    }
+
+    let src = if instruction.mode == 0 {
+        regs[wrap_index(instruction.src, regs.len())]
+    } else if instruction.mode == 2 {
+        match memory.read(instruction.src) {
+            Some(value) => value,
+            None => return,
+        }
+    } else if input.is_empty() {
+        return;
+    } else {
+        input[wrap_index(instruction.src, input.len())]
+    };
+
+    let dest = wrap_index(instruction.dst, regs.len());
+
+    let x = regs[dest];
+    let y = src;
+
+    regs[dest] = operation.apply(x, y, arithmetic);
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 /// Structure that represent the instruction.
 pub struct Instruction {
     /// The mode used by the instruction.
@@ -16,8 +68,13 @@ pub struct Instruction {
     ///     |-> The instruction take data from the register.
     /// If 1
     ///     |-> The instrucetion take data from the input.
+    /// If 2
+    ///     |-> The instruction take data from the memory.
+    /// If 3
+    ///     |-> The instruction write the result in the memory (the destination is in the memory).
     mode: i32,
     /// The operation made by the instruction.
+    /// (The code of the operation in the OperationSet)
     op: i32,
     /// The index of the data inpût to take (in the register or the input).
     src: i32,
@@ -40,15 +97,37 @@ impl Instruction {
     pub fn new(mode: i32, op: i32, src: i32, dst: i32) -> Self {
         Instruction { mode, op, src, dst }
     }
+
+    /// Function to get the mode of the instruction.
+    pub fn get_mode(&self) -> i32 {
+        self.mode
+    }
+
+    /// Function to get the operation of the instruction.
+    pub fn get_op(&self) -> i32 {
+        self.op
+    }
+
+    /// Function to get the source of the instruction.
+    pub fn get_src(&self) -> i32 {
+        self.src
+    }
+
+    /// Function to get the destination of the instruction.
+    pub fn get_dst(&self) -> i32 {
+        self.dst
+    }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 /// Structure that represent the program.
 pub struct Program {
     /// The id of the program.
     id: i32,
     /// The list of instruction inside the program.
     instructions: Vec<Instruction>,
+    /// The arithmetic used by the operations of the program.
+    arithmetic: ArithmeticPolicy,
 }
 
 impl PartialEq for Program {
@@ -61,9 +140,11 @@ impl PartialEq for Program {
 
 impl Program {
     /// Constructor of the program.
-    pub fn new(
+    /// The random instructions only use the operations of the operation set.
+    pub fn new<T: Register>(
         instructions_heritage: Option<Vec<Instruction>>,
         init_params: &mut ProgramParams,
+        operations: &OperationSet<T>,
     ) -> Self {
         let id: i32 = init_params.get_new_id_program();
         let mut instructions: Vec<Instruction> = Vec::new();
@@ -71,11 +152,11 @@ impl Program {
         if let Some(instruction_parents) = instructions_heritage {
             instructions = instruction_parents;
         } else {
-            let mut rng = rand::thread_rng();
+            let mut rng = rng();
 
             for _ in 0..init_params.max_program_length {
-                let mode: i32 = rng.gen_range(0..2);
-                let op: i32 = rng.gen_range(0..init_params.nb_operations);
+                let mode: i32 = rng.gen_range(0..init_params.get_nb_modes());
+                let op: i32 = rng.gen_range(0..operations.len() as i32);
                 let src: i32 = rng.gen_range(0..init_params.input_size);
                 let dst: i32 = rng.gen_range(0..init_params.nb_destinations);
 
@@ -84,7 +165,24 @@ impl Program {
             }
         }
 
-        Program { id, instructions }
+        Program {
+            id,
+            instructions,
+            arithmetic: init_params.arithmetic,
+        }
+    }
+
+    /// Constructor of the program with an existing id (used to rebuild a program).
+    pub(crate) fn from_instructions(
+        id: i32,
+        instructions: Vec<Instruction>,
+        arithmetic: ArithmeticPolicy,
+    ) -> Self {
+        Program {
+            id,
+            instructions,
+            arithmetic,
+        }
     }
 
     /// Function to clear the instruction inside the program.
@@ -102,45 +200,253 @@ impl Program {
         self.id
     }
 
+    /// Function to set the id of the program (e.g. when the program is imported in a brain).
+    pub(crate) fn set_id(&mut self, id: i32) {
+        self.id = id;
+    }
+
+    /// Function to get the arithmetic used by the operations of the program.
+    pub fn get_arithmetic(&self) -> ArithmeticPolicy {
+        self.arithmetic
+    }
+
+    /// Function to get the list of instruction inside the program.
+    pub fn get_instructions(&self) -> &[Instruction] {
+        &self.instructions
+    }
+
     /// Function to get the number of instruction inside the program
     pub fn get_len_program(&self) -> usize {
         self.instructions.len()
     }
 
-    /// Function to execute a program
-    pub fn execute(&self, input: &Vec<i32>, regs: &mut Vec<i32>) {
-        let input_len: i32 = input.len().try_into().unwrap();
-        let regs_len: i32 = regs.len().try_into().unwrap();
+    /// Function to get the introns of the program (one bool per instruction).
+    /// An instruction is an intron if it has no effect on the register 0 (the bid) at the end of the program.
+    /// The introns are found with a backward pass that keep the list of registers used by the bid:
+    ///     - If the instruction write a register not used then it is an intron.
+    ///     - Else the source register become used (if the operation read the source).
+    /// The instructions writing the memory are never introns (the memory is read by the next decisions).
+    pub fn get_intron_mask<T: Register>(
+        &self,
+        operations: &OperationSet<T>,
+        num_registers: usize,
+    ) -> Vec<bool> {
+        let mut introns: Vec<bool> = vec![true; self.instructions.len()];
+        if num_registers == 0 {
+            return introns;
+        }
 
-        for instruction in self.instructions.iter() {
-            let mut src = input[(instruction.src % input_len) as usize];
-            if instruction.mode == 0 {
-                src = regs[(instruction.src % regs_len) as usize];
+        let mut used_registers: Vec<bool> = vec![false; num_registers];
+        used_registers[0] = true;
+
+        for (i, instruction) in self.instructions.iter().enumerate().rev() {
+            // The unknown operation is skipped during the execution
+            let Some(operation) = operations.get(instruction.op) else {
+                continue;
+            };
+
+            // The write in the memory is used by the next decisions so it is never an intron
+            if instruction.mode == 3 {
+                introns[i] = false;
+                if operation.arity() >= 2 {
+                    used_registers[wrap_index(instruction.src, num_registers)] = true;
+                }
+                continue;
             }
 
-            let op = instruction.op;
-
-            let x = regs[(instruction.dst) as usize];
-            let y = src;
-            let dest = (instruction.dst % regs_len) as usize;
-
-            if op == 0 {
-                regs[dest] = x + y;
-            } else if op == 1 {
-                regs[dest] = x - y;
-            } else if op == 2 {
-                regs[dest] = x * 2;
-            } else if op == 3 {
-                regs[dest] = x / 2;
-            } else if op == 4 && x < y {
-                regs[dest] = -x;
+            let dest = wrap_index(instruction.dst, num_registers);
+            if !used_registers[dest] {
+                continue;
+            }
+
+            introns[i] = false;
+
+            // The destination is also read by the operation so it stay used
+            if operation.arity() >= 2 && instruction.mode == 0 {
+                used_registers[wrap_index(instruction.src, num_registers)] = true;
             }
         }
+        introns
+    }
+
+    /// Function to get the number of effective instruction (not intron) inside the program.
+    pub fn get_effective_len_program<T: Register>(
+        &self,
+        operations: &OperationSet<T>,
+        num_registers: usize,
+    ) -> usize {
+        let introns = self.get_intron_mask(operations, num_registers);
+        introns.iter().filter(|intron| !**intron).count()
+    }
+
+    /// Function to get a copy of the program with only the effective instruction (no intron).
+    /// The copy give the same register 0 as the original program.
+    pub fn get_effective_program<T: Register>(
+        &self,
+        operations: &OperationSet<T>,
+        num_registers: usize,
+    ) -> Program {
+        let introns = self.get_intron_mask(operations, num_registers);
+        let mut effective_program = self.clone();
+        effective_program.instructions = self
+            .instructions
+            .iter()
+            .zip(introns)
+            .filter(|(_, intron)| !intron)
+            .map(|(instruction, _)| instruction.clone())
+            .collect();
+        effective_program
+    }
+
+    /// Function to execute a program
+    /// The source and the destination are taken modulo the size of the input and the register,
+    /// so any instruction can be executed without panic:
+    ///     - If the register is empty then nothing is executed.
+    ///     - If the input is empty then the instructions reading the input are skipped.
+    ///     - If the operation is not in the operation set then the instruction is skipped.
+    /// The program is executed without memory (the memory instructions are skipped).
+    pub fn execute<T: Register>(
+        &self,
+        operations: &OperationSet<T>,
+        input: &[T],
+        regs: &mut [T],
+    ) {
+        self.execute_with_memory(operations, input, regs, &mut Memory::default());
+    }
+
+    /// Function to execute a program with the memory.
+    /// If the memory is empty then the memory instructions are skipped.
+    pub fn execute_with_memory<T: Register>(
+        &self,
+        operations: &OperationSet<T>,
+        input: &[T],
+        regs: &mut [T],
+        memory: &mut Memory<T>,
+    ) {
+        if regs.is_empty() {
+            return;
+        }
+
+        for instruction in self.instructions.iter() {
+            execute_instruction(instruction, operations, self.arithmetic, input, regs, memory);
+        }
+    }
+
+    /// Function to combine the program with an other program.
+    /// Return the two children (with a new id) created with the crossover of the params.
+    pub fn crossover(
+        &self,
+        other: &Program,
+        init_params: &mut ProgramParams,
+    ) -> (Program, Program) {
+        match init_params.crossover {
+            CrossoverType::OnePoint => self.crossover_one_point(other, init_params),
+            CrossoverType::TwoPoint => self.crossover_two_point(other, init_params),
+        }
+    }
+
+    /// Function to combine the program with an other program when only one child is needed.
+    /// Return the first child (with a new id) created with the crossover of the params.
+    pub fn crossover_child(&self, other: &Program, init_params: &mut ProgramParams) -> Program {
+        let (instructions, _) = match init_params.crossover {
+            CrossoverType::OnePoint => self.one_point_instructions(other),
+            CrossoverType::TwoPoint => self.two_point_instructions(other),
+        };
+        Program::new_child(instructions, init_params)
+    }
+
+    /// Function to exchange the tail of the two programs.
+    ///     child1 = self[..i] + other[j..]
+    ///     child2 = other[..j] + self[i..]
+    /// The cut points are chosen so that the children are never empty.
+    /// The children are cut to the maximum length of the params.
+    pub fn crossover_one_point(
+        &self,
+        other: &Program,
+        init_params: &mut ProgramParams,
+    ) -> (Program, Program) {
+        let (instructions1, instructions2) = self.one_point_instructions(other);
+        (
+            Program::new_child(instructions1, init_params),
+            Program::new_child(instructions2, init_params),
+        )
+    }
+
+    /// Function to exchange a segment of the two programs.
+    ///     child1 = self[..a1] + other[a2..b2] + self[b1..]
+    ///     child2 = other[..a2] + self[a1..b1] + other[b2..]
+    /// The segments are never empty so the children are never empty.
+    /// The children are cut to the maximum length of the params.
+    pub fn crossover_two_point(
+        &self,
+        other: &Program,
+        init_params: &mut ProgramParams,
+    ) -> (Program, Program) {
+        let (instructions1, instructions2) = self.two_point_instructions(other);
+        (
+            Program::new_child(instructions1, init_params),
+            Program::new_child(instructions2, init_params),
+        )
+    }
+
+    /// Function to create a child of the crossover with the instructions
+    /// (only the first instructions are kept, up to the maximum length of the params).
+    fn new_child(mut instructions: Vec<Instruction>, init_params: &mut ProgramParams) -> Program {
+        instructions.truncate(init_params.max_program_length.max(1) as usize);
+        let id = init_params.get_new_id_program();
+        Program::from_instructions(id, instructions, init_params.arithmetic)
+    }
+
+    /// Function to get the instructions of the two children of the one point crossover.
+    fn one_point_instructions(&self, other: &Program) -> (Vec<Instruction>, Vec<Instruction>) {
+        if self.instructions.is_empty() || other.instructions.is_empty() {
+            return (self.instructions.clone(), other.instructions.clone());
+        }
+
+        let mut rng = rng();
+        let i = rng.gen_range(1..=self.instructions.len());
+        let j = rng.gen_range(1..=other.instructions.len());
+
+        let mut instructions1: Vec<Instruction> = self.instructions[..i].to_vec();
+        instructions1.extend_from_slice(&other.instructions[j..]);
+
+        let mut instructions2: Vec<Instruction> = other.instructions[..j].to_vec();
+        instructions2.extend_from_slice(&self.instructions[i..]);
+
+        (instructions1, instructions2)
+    }
+
+    /// Function to get the instructions of the two children of the two point crossover.
+    fn two_point_instructions(&self, other: &Program) -> (Vec<Instruction>, Vec<Instruction>) {
+        if self.instructions.is_empty() || other.instructions.is_empty() {
+            return (self.instructions.clone(), other.instructions.clone());
+        }
+
+        let mut rng = rng();
+        let a1 = rng.gen_range(0..self.instructions.len());
+        let b1 = rng.gen_range(a1 + 1..=self.instructions.len());
+        let a2 = rng.gen_range(0..other.instructions.len());
+        let b2 = rng.gen_range(a2 + 1..=other.instructions.len());
+
+        let mut instructions1: Vec<Instruction> = self.instructions[..a1].to_vec();
+        instructions1.extend_from_slice(&other.instructions[a2..b2]);
+        instructions1.extend_from_slice(&self.instructions[b1..]);
+
+        let mut instructions2: Vec<Instruction> = other.instructions[..a2].to_vec();
+        instructions2.extend_from_slice(&self.instructions[a1..b1]);
+        instructions2.extend_from_slice(&other.instructions[b2..]);
+
+        (instructions1, instructions2)
     }
 
     /// Function to mutate the program
-    pub fn mutate(&mut self, mutate_params: &ProgramParams) {
-        let mut rng = rand::thread_rng();
+    /// The mutated instructions only use the operations of the operation set.
+    pub fn mutate<T: Register>(
+        &mut self,
+        mutate_params: &ProgramParams,
+        operations: &OperationSet<T>,
+    ) {
+        let mut rng = rng();
 
         let original_instruction = self.instructions.clone();
         let mut current_instruction = self.instructions.clone();
@@ -158,10 +464,10 @@ impl Program {
                 let index2 = rng.gen_range(0..4);
 
                 if index2 == 0 {
-                    let max_value: i32 = rng.gen_range(0..2);
+                    let max_value: i32 = rng.gen_range(0..mutate_params.get_nb_modes());
                     self.instructions[index1].mode = max_value;
                 } else if index2 == 1 {
-                    let max_value: i32 = rng.gen_range(0..mutate_params.nb_operations);
+                    let max_value: i32 = rng.gen_range(0..operations.len() as i32);
                     self.instructions[index1].op = max_value;
                 } else if index2 == 2 {
                     let max_val: i32 = rng.gen_range(0..mutate_params.nb_destinations);
@@ -186,8 +492,8 @@ impl Program {
 
             if flip(mutate_params.p_inst_add) {
                 println!("[Mutation] Instruction add...");
-                let mode: i32 = rng.gen_range(0..2);
-                let op: i32 = rng.gen_range(0..mutate_params.nb_operations);
+                let mode: i32 = rng.gen_range(0..mutate_params.get_nb_modes());
+                let op: i32 = rng.gen_range(0..operations.len() as i32);
                 let src: i32 = rng.gen_range(0..mutate_params.input_size);
                 let dst: i32 = rng.gen_range(0..mutate_params.nb_destinations);
 
@@ -203,22 +509,26 @@ impl Program {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::ProgramParams;
+    use crate::{OperationSet, ProgramParams};
 
     #[test]
     fn test_init_program() {
+        let operations: OperationSet<f64> = OperationSet::default();
         let mut init_params: ProgramParams = ProgramParams {
             id_counter_program: 47,
             max_program_length: 5,
-            nb_operations: 5,
             input_size: 3,
             nb_destinations: 3,
             p_inst_del: 0.0,
             p_inst_mut: 0.0,
             p_inst_swap: 0.0,
             p_inst_add: 0.0,
+            arithmetic: ArithmeticPolicy::Wrapping,
+            crossover: CrossoverType::TwoPoint,
+            memory_size: 0,
+            p_memory_write: 0.0,
         };
-        let mut p1: Program = Program::new(None, &mut init_params);
+        let mut p1: Program = Program::new(None, &mut init_params, &operations);
 
         // Check the id of program
         assert_eq!(
@@ -254,18 +564,22 @@ mod tests {
 
     #[test]
     fn test_execute_program() {
+        let operations: OperationSet<f64> = OperationSet::default();
         let mut init_params: ProgramParams = ProgramParams {
             id_counter_program: 47,
             max_program_length: 5,
-            nb_operations: 5,
             input_size: 3,
             nb_destinations: 3,
             p_inst_del: 0.0,
             p_inst_mut: 0.0,
             p_inst_swap: 0.0,
             p_inst_add: 0.0,
+            arithmetic: ArithmeticPolicy::Wrapping,
+            crossover: CrossoverType::TwoPoint,
+            memory_size: 0,
+            p_memory_write: 0.0,
         };
-        let mut p1: Program = Program::new(None, &mut init_params);
+        let mut p1: Program = Program::new(None, &mut init_params, &operations);
 
         // regs[0] = input[0] + regs[0]
         let i1: Instruction = Instruction::new(1, 0, 0, 0);
@@ -273,12 +587,12 @@ mod tests {
         p1.reset();
         p1.set_instructions(i1);
 
-        let input: Vec<i32> = vec![1, 2, 3, 4, 5];
-        let mut regs: Vec<i32> = vec![6, 7, 8, 9, 10];
+        let input: Vec<f64> = vec![1.0, 2.0, 3.0, 4.0, 5.0];
+        let mut regs: Vec<f64> = vec![6.0, 7.0, 8.0, 9.0, 10.0];
 
-        p1.execute(&input, &mut regs);
+        p1.execute(&operations, &input, &mut regs);
 
-        let regs_res: Vec<i32> = vec![7, 7, 8, 9, 10];
+        let regs_res: Vec<f64> = vec![7.0, 7.0, 8.0, 9.0, 10.0];
 
         // Check the len  of the register
         assert_eq!(
@@ -294,28 +608,372 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_execute_program_integer() {
+        let operations: OperationSet<i32> = OperationSet::default();
+        let mut init_params: ProgramParams = ProgramParams {
+            id_counter_program: 47,
+            max_program_length: 5,
+            input_size: 3,
+            nb_destinations: 3,
+            p_inst_del: 0.0,
+            p_inst_mut: 0.0,
+            p_inst_swap: 0.0,
+            p_inst_add: 0.0,
+            arithmetic: ArithmeticPolicy::Wrapping,
+            crossover: CrossoverType::TwoPoint,
+            memory_size: 0,
+            p_memory_write: 0.0,
+        };
+        let mut p1: Program = Program::new(None, &mut init_params, &operations);
+
+        // regs[0] = input[0] + regs[0]
+        // regs[1] = regs[1] / 2
+        p1.reset();
+        p1.set_instructions(Instruction::new(1, 0, 0, 0));
+        p1.set_instructions(Instruction::new(0, 3, 0, 1));
+
+        let input: Vec<i32> = vec![1, 2, 3];
+        let mut regs: Vec<i32> = vec![6, 7, 8];
+
+        p1.execute(&operations, &input, &mut regs);
+
+        // Check if the register give the correct result after execution
+        assert_eq!(
+            regs,
+            vec![7, 3, 8],
+            "The register is not correct after execution of the program"
+        );
+    }
+
+    #[test]
+    fn test_execute_program_float_semantics() {
+        let operations: OperationSet<f64> = OperationSet::default();
+        let mut init_params: ProgramParams = ProgramParams {
+            id_counter_program: 47,
+            max_program_length: 5,
+            input_size: 3,
+            nb_destinations: 3,
+            p_inst_del: 0.0,
+            p_inst_mut: 0.0,
+            p_inst_swap: 0.0,
+            p_inst_add: 0.0,
+            arithmetic: ArithmeticPolicy::Wrapping,
+            crossover: CrossoverType::TwoPoint,
+            memory_size: 0,
+            p_memory_write: 0.0,
+        };
+        let mut p1: Program = Program::new(None, &mut init_params, &operations);
+
+        // regs[0] = regs[0] / input[0] (protected)
+        // regs[1] = regs[1] * input[1]
+        // regs[2] = regs[2] / input[2]
+        p1.reset();
+        p1.set_instructions(Instruction::new(1, 6, 0, 0));
+        p1.set_instructions(Instruction::new(1, 5, 1, 1));
+        p1.set_instructions(Instruction::new(1, 6, 2, 2));
+
+        let input: Vec<f64> = vec![0.0, f64::MAX, f64::NAN];
+        let mut regs: Vec<f64> = vec![1.5, 2.0, 3.0];
+
+        p1.execute(&operations, &input, &mut regs);
+
+        // Check the division by zero keep the register unchanged
+        assert_eq!(regs[0], 1.5, "The protected division is not correct");
+
+        // Check the overflow is clamped to the max value
+        assert_eq!(regs[1], f64::MAX, "The overflow is not clamped");
+
+        // Check the NaN is replaced by zero
+        assert_eq!(regs[2], 0.0, "The NaN is not replaced by zero");
+    }
+
+    #[test]
+    fn test_execute_program_policy() {
+        let operations: OperationSet<i32> = OperationSet::default();
+        let mut init_params: ProgramParams = ProgramParams {
+            id_counter_program: 47,
+            max_program_length: 5,
+            input_size: 3,
+            nb_destinations: 3,
+            p_inst_del: 0.0,
+            p_inst_mut: 0.0,
+            p_inst_swap: 0.0,
+            p_inst_add: 0.0,
+            arithmetic: ArithmeticPolicy::Saturating,
+            crossover: CrossoverType::TwoPoint,
+            memory_size: 0,
+            p_memory_write: 0.0,
+        };
+        let mut p1: Program = Program::new(None, &mut init_params, &operations);
+        init_params.arithmetic = ArithmeticPolicy::Checked { fallback: 0.0 };
+        let mut p2: Program = Program::new(None, &mut init_params, &operations);
+
+        // regs[0] = regs[0] * input[0]
+        p1.reset();
+        p1.set_instructions(Instruction::new(1, 5, 0, 0));
+        p2.reset();
+        p2.set_instructions(Instruction::new(1, 5, 0, 0));
+
+        let input: Vec<i32> = vec![i32::MAX];
+        let mut regs1: Vec<i32> = vec![2];
+        let mut regs2: Vec<i32> = vec![2];
+
+        p1.execute(&operations, &input, &mut regs1);
+        p2.execute(&operations, &input, &mut regs2);
+
+        // Check the overflow is saturated
+        assert_eq!(regs1[0], i32::MAX, "The overflow is not saturated");
+
+        // Check the overflow is replaced by the fallback
+        assert_eq!(regs2[0], 0, "The overflow is not replaced by the fallback");
+    }
+
+    #[test]
+    fn test_execute_program_bounds() {
+        let operations: OperationSet<i32> = OperationSet::default();
+        let mut init_params: ProgramParams = ProgramParams {
+            id_counter_program: 47,
+            max_program_length: 50,
+            input_size: 10,
+            nb_destinations: 10,
+            p_inst_del: 0.0,
+            p_inst_mut: 0.0,
+            p_inst_swap: 0.0,
+            p_inst_add: 0.0,
+            arithmetic: ArithmeticPolicy::Wrapping,
+            crossover: CrossoverType::TwoPoint,
+            memory_size: 0,
+            p_memory_write: 0.0,
+        };
+
+        // Check the random programs run with register and input smaller than the params
+        for _ in 0..20 {
+            let program: Program = Program::new(None, &mut init_params, &operations);
+            let mut regs: Vec<i32> = vec![i32::MAX; 2];
+            program.execute(&operations, &[i32::MIN], &mut regs);
+            program.execute(&operations, &[], &mut regs);
+            program.execute(&operations, &[1, 2, 3], &mut []);
+        }
+
+        // regs[7 % 3] = regs[7 % 3] + input[-1 mod 2]
+        let mut p1: Program = Program::new(None, &mut init_params, &operations);
+        p1.reset();
+        p1.set_instructions(Instruction::new(1, 0, -1, 7));
+
+        let mut regs: Vec<i32> = vec![0, 0, 0];
+        p1.execute(&operations, &[5, 6], &mut regs);
+
+        // Check the index are taken modulo the size of the input and the register
+        assert_eq!(regs, vec![0, 6, 0], "The index of the instruction is not wrapped");
+    }
+
+    #[test]
+    fn test_intron_program() {
+        let operations: OperationSet<i32> = OperationSet::default();
+        let mut init_params: ProgramParams = ProgramParams {
+            id_counter_program: 47,
+            max_program_length: 5,
+            input_size: 3,
+            nb_destinations: 3,
+            p_inst_del: 0.0,
+            p_inst_mut: 0.0,
+            p_inst_swap: 0.0,
+            p_inst_add: 0.0,
+            arithmetic: ArithmeticPolicy::Wrapping,
+            crossover: CrossoverType::TwoPoint,
+            memory_size: 0,
+            p_memory_write: 0.0,
+        };
+        let mut p1: Program = Program::new(None, &mut init_params, &operations);
+
+        p1.reset();
+        // regs[1] = regs[1] + input[0] (used by the next instruction)
+        p1.set_instructions(Instruction::new(1, 0, 0, 1));
+        // regs[2] = regs[2] - input[1] (intron, regs[2] is never read)
+        p1.set_instructions(Instruction::new(1, 1, 1, 2));
+        // regs[0] = regs[0] + regs[1]
+        p1.set_instructions(Instruction::new(0, 0, 1, 0));
+        // regs[1] = regs[1] * 2 (intron, regs[1] is written after the last read)
+        p1.set_instructions(Instruction::new(0, 2, 0, 1));
+        // regs[0] = regs[0] * 2 (the source is not read by the operation)
+        p1.set_instructions(Instruction::new(0, 2, 2, 0));
+
+        // Check the intron mask
+        assert_eq!(
+            p1.get_intron_mask(&operations, 3),
+            vec![false, true, false, true, false],
+            "The intron mask is incorrect"
+        );
+
+        // Check the effective len
+        assert_eq!(
+            p1.get_effective_len_program(&operations, 3),
+            3,
+            "The effective len of the program is incorrect"
+        );
+
+        // Check the effective program give the same bid
+        let effective_program = p1.get_effective_program(&operations, 3);
+        let input: Vec<i32> = vec![3, 4, 5];
+        let mut regs1: Vec<i32> = vec![1, 2, 3];
+        let mut regs2: Vec<i32> = vec![1, 2, 3];
+        p1.execute(&operations, &input, &mut regs1);
+        effective_program.execute(&operations, &input, &mut regs2);
+        assert_eq!(regs1[0], regs2[0], "The effective program give a different bid");
+    }
+
+    #[test]
+    fn test_execute_program_memory() {
+        let operations: OperationSet<i32> = OperationSet::default();
+        let mut init_params: ProgramParams = ProgramParams {
+            id_counter_program: 47,
+            max_program_length: 5,
+            input_size: 3,
+            nb_destinations: 3,
+            p_inst_del: 0.0,
+            p_inst_mut: 0.0,
+            p_inst_swap: 0.0,
+            p_inst_add: 0.0,
+            arithmetic: ArithmeticPolicy::Wrapping,
+            crossover: CrossoverType::TwoPoint,
+            memory_size: 4,
+            p_memory_write: 1.0,
+        };
+        let mut p1: Program = Program::new(Some(Vec::new()), &mut init_params, &operations);
+
+        // mem[1] = mem[1] + regs[2] (write the memory)
+        p1.set_instructions(Instruction::new(3, 0, 2, 1));
+        // regs[0] = regs[0] + mem[5] (read the memory, 5 % 4 = 1)
+        p1.set_instructions(Instruction::new(2, 0, 5, 0));
+        // regs[2] = regs[2] * 2 (intron, regs[2] is written after the write of the memory)
+        p1.set_instructions(Instruction::new(0, 2, 0, 2));
+
+        // Check the memory is written then read
+        let mut memory: Memory<i32> = Memory::new(4, 1.0);
+        let mut regs: Vec<i32> = vec![1, 2, 3];
+        p1.execute_with_memory(&operations, &[], &mut regs, &mut memory);
+        assert_eq!(regs, vec![4, 2, 6], "The register is not correct after reading the memory");
+        assert_eq!(memory.get_values(), &[0, 3, 0, 0], "The memory is not written");
+
+        // Check the memory instructions are skipped without memory
+        let mut regs: Vec<i32> = vec![1, 2, 3];
+        p1.execute(&operations, &[], &mut regs);
+        assert_eq!(regs, vec![1, 2, 6], "The memory instructions are not skipped");
+
+        // Check the write of the memory is not an intron
+        assert_eq!(
+            p1.get_intron_mask(&operations, 3),
+            vec![false, false, true],
+            "The intron mask with the memory is incorrect"
+        );
+    }
+
+    #[test]
+    fn test_crossover_program() {
+        let operations: OperationSet<f64> = OperationSet::default();
+        let mut init_params: ProgramParams = ProgramParams {
+            id_counter_program: 47,
+            max_program_length: 5,
+            input_size: 3,
+            nb_destinations: 3,
+            p_inst_del: 0.0,
+            p_inst_mut: 0.0,
+            p_inst_swap: 0.0,
+            p_inst_add: 0.0,
+            arithmetic: ArithmeticPolicy::Wrapping,
+            crossover: CrossoverType::OnePoint,
+            memory_size: 0,
+            p_memory_write: 0.0,
+        };
+
+        // The instructions of the parent 1 write in regs[0], the parent 2 in regs[1]
+        let mut p1: Program = Program::new(None, &mut init_params, &operations);
+        let mut p2: Program = Program::new(None, &mut init_params, &operations);
+        p1.reset();
+        p2.reset();
+        for i in 0..4 {
+            p1.set_instructions(Instruction::new(1, 0, i, 0));
+        }
+        for i in 0..6 {
+            p2.set_instructions(Instruction::new(1, 0, i, 1));
+        }
+
+        init_params.max_program_length = 10;
+        for crossover in [CrossoverType::OnePoint, CrossoverType::TwoPoint] {
+            init_params.crossover = crossover;
+            for _ in 0..20 {
+                let (c1, c2) = p1.crossover(&p2, &mut init_params);
+
+                // Check the children keep all the instructions of the parents
+                assert_eq!(
+                    c1.get_len_program() + c2.get_len_program(),
+                    10,
+                    "The number of instruction is incorrect after the crossover"
+                );
+
+                // Check the children are never empty
+                assert!(c1.get_len_program() > 0, "The child 1 is empty");
+                assert!(c2.get_len_program() > 0, "The child 2 is empty");
+
+                // Check the children have a new id
+                assert_ne!(c1.get_id(), p1.get_id(), "The child 1 has the id of the parent");
+
+                let c1_from_p2 = c1.get_instructions().iter().filter(|i| i.get_dst() == 1);
+                let c2_from_p1 = c2.get_instructions().iter().filter(|i| i.get_dst() == 0);
+                if crossover == CrossoverType::OnePoint {
+                    // Check the children start with their first parent
+                    assert_eq!(c1.get_instructions()[0].get_dst(), 0, "The child 1 is incorrect");
+                    assert_eq!(c2.get_instructions()[0].get_dst(), 1, "The child 2 is incorrect");
+                } else {
+                    // Check the children receive a segment of their second parent
+                    assert!(c1_from_p2.count() > 0, "The child 1 has no segment of the parent 2");
+                    assert!(c2_from_p1.count() > 0, "The child 2 has no segment of the parent 1");
+                }
+            }
+        }
+
+        // Check the children are cut to the maximum length
+        init_params.max_program_length = 5;
+        for crossover in [CrossoverType::OnePoint, CrossoverType::TwoPoint] {
+            init_params.crossover = crossover;
+            for _ in 0..20 {
+                let (c1, c2) = p2.crossover(&p2, &mut init_params);
+                assert!(c1.get_len_program() <= 5, "The child 1 is longer than the maximum");
+                assert!(c2.get_len_program() <= 5, "The child 2 is longer than the maximum");
+                let child = p2.crossover_child(&p1, &mut init_params);
+                let len = child.get_len_program();
+                assert!(len > 0 && len <= 5, "The length of the child is incorrect");
+            }
+        }
+    }
+
     #[test]
     fn test_mutate_program() {
+        let operations: OperationSet<f64> = OperationSet::default();
         let mut init_params: ProgramParams = ProgramParams {
             id_counter_program: 47,
             max_program_length: 5,
-            nb_operations: 5,
             input_size: 3,
             nb_destinations: 3,
             p_inst_del: 0.0,
             p_inst_mut: 0.0,
             p_inst_swap: 0.0,
             p_inst_add: 0.0,
+            arithmetic: ArithmeticPolicy::Wrapping,
+            crossover: CrossoverType::TwoPoint,
+            memory_size: 0,
+            p_memory_write: 0.0,
         };
 
         // Define program to test the mutation 'delete'
-        let mut p1: Program = Program::new(None, &mut init_params);
+        let mut p1: Program = Program::new(None, &mut init_params, &operations);
         // Define program to test the mutation 'mutate'
-        let mut p2: Program = Program::new(None, &mut init_params);
+        let mut p2: Program = Program::new(None, &mut init_params, &operations);
         // Define program to test the mutation 'swap'
-        let mut p3: Program = Program::new(None, &mut init_params);
+        let mut p3: Program = Program::new(None, &mut init_params, &operations);
         // Define program to test the mutation 'add'
-        let mut p4: Program = Program::new(None, &mut init_params);
+        let mut p4: Program = Program::new(None, &mut init_params, &operations);
 
         // regs[0] = input[0] + regs[0]
         let i1: Instruction = Instruction::new(1, 0, 0, 0);
@@ -342,46 +1000,58 @@ mod tests {
         let mutate_params1: ProgramParams = ProgramParams {
             id_counter_program: 47,
             max_program_length: 5,
-            nb_operations: 5,
             input_size: 3,
             nb_destinations: 3,
             p_inst_del: 0.25,
             p_inst_mut: 0.0,
             p_inst_swap: 0.0,
             p_inst_add: 0.0,
+            arithmetic: ArithmeticPolicy::Wrapping,
+            crossover: CrossoverType::TwoPoint,
+            memory_size: 0,
+            p_memory_write: 0.0,
         };
         let mutate_params2: ProgramParams = ProgramParams {
             id_counter_program: 47,
             max_program_length: 5,
-            nb_operations: 5,
             input_size: 3,
             nb_destinations: 3,
             p_inst_del: 0.0,
             p_inst_mut: 0.25,
             p_inst_swap: 0.0,
             p_inst_add: 0.0,
+            arithmetic: ArithmeticPolicy::Wrapping,
+            crossover: CrossoverType::TwoPoint,
+            memory_size: 0,
+            p_memory_write: 0.0,
         };
         let mutate_params3: ProgramParams = ProgramParams {
             id_counter_program: 47,
             max_program_length: 5,
-            nb_operations: 5,
             input_size: 3,
             nb_destinations: 3,
             p_inst_del: 0.0,
             p_inst_mut: 0.0,
             p_inst_swap: 0.25,
             p_inst_add: 0.0,
+            arithmetic: ArithmeticPolicy::Wrapping,
+            crossover: CrossoverType::TwoPoint,
+            memory_size: 0,
+            p_memory_write: 0.0,
         };
         let mutate_params4: ProgramParams = ProgramParams {
             id_counter_program: 47,
             max_program_length: 5,
-            nb_operations: 5,
             input_size: 3,
             nb_destinations: 3,
             p_inst_del: 0.0,
             p_inst_mut: 0.0,
             p_inst_swap: 0.0,
             p_inst_add: 0.25,
+            arithmetic: ArithmeticPolicy::Wrapping,
+            crossover: CrossoverType::TwoPoint,
+            memory_size: 0,
+            p_memory_write: 0.0,
         };
 
         let len_before_del = p1.get_len_program();
@@ -390,7 +1060,7 @@ mod tests {
         let len_before_add = p4.get_len_program();
 
         // Unit test for the mutation 'delete'
-        p1.mutate(&mutate_params1);
+        p1.mutate(&mutate_params1, &operations);
         assert_eq!(
             p1.get_len_program(),
             len_before_del - 1,
@@ -398,7 +1068,7 @@ mod tests {
         );
 
         // Unit test for the mutation 'mutate'
-        p2.mutate(&mutate_params2);
+        p2.mutate(&mutate_params2, &operations);
         assert_eq!(
             p2.get_len_program(),
             len_before_mut,
@@ -406,7 +1076,7 @@ mod tests {
         );
 
         // Unit test for the mutation 'swap'
-        p3.mutate(&mutate_params3);
+        p3.mutate(&mutate_params3, &operations);
         assert_eq!(
             p3.get_len_program(),
             len_before_swap,
@@ -414,11 +1084,27 @@ mod tests {
         );
 
         // Unit test for the mutation 'add'
-        p4.mutate(&mutate_params4);
+        p4.mutate(&mutate_params4, &operations);
         assert_eq!(
             p4.get_len_program(),
             len_before_add + 1,
             "The len of the program is not correct after the mutation 'add'"
         );
+
+        // Check the new and the mutated instructions only use the operations of the set
+        let mut operations: OperationSet<f64> = OperationSet::new();
+        operations.add_operation(Box::new(crate::Addition));
+        operations.add_operation(Box::new(crate::Subtraction));
+        init_params.max_program_length = 50;
+        let mut p5: Program = Program::new(None, &mut init_params, &operations);
+        let ops_in_set = |program: &Program| {
+            program.get_instructions().iter().all(|instruction| instruction.get_op() < 2)
+        };
+        assert!(ops_in_set(&p5), "The new program use an unknown operation");
+        for _ in 0..20 {
+            p5.mutate(&mutate_params2, &operations);
+            p5.mutate(&mutate_params4, &operations);
+        }
+        assert!(ops_in_set(&p5), "The mutated program use an unknown operation");
     }
 }
@@ -0,0 +1,137 @@
+//! # Random
+//! Crate that permit to generate the random numbers of the Tangled Program Graph (TPG).
+//! All the random choices of the crate use the same generator (one per thread), the state of
+//! the generator can be saved and restored, so a training can continue exactly after a checkpoint.
+//! The generator is a xoshiro256** seeded with splitmix64 (seeded from the entropy by default).
+
+#![deny(missing_docs)]
+#![deny(clippy::all)]
+#![deny(clippy::missing_docs_in_private_items)]
+
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+
+/// Structure to represent the state of the generator.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RngState {
+    /// The 256 bits of the state.
+    state: [u64; 4],
+}
+
+impl RngState {
+    /// Constructor of the state from a seed (the seed is expanded with splitmix64).
+    pub fn from_seed(seed: u64) -> Self {
+        let mut x = seed;
+        let mut state = [0u64; 4];
+        for value in state.iter_mut() {
+            x = x.wrapping_add(0x9E37_79B9_7F4A_7C15);
+            let mut z = x;
+            z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+            *value = z ^ (z >> 31);
+        }
+        RngState { state }
+    }
+
+    /// Function to get the next random number and advance the state.
+    fn next(&mut self) -> u64 {
+        let s = &mut self.state;
+        let result = s[1].wrapping_mul(5).rotate_left(7).wrapping_mul(9);
+        let t = s[1] << 17;
+        s[2] ^= s[0];
+        s[3] ^= s[1];
+        s[1] ^= s[2];
+        s[0] ^= s[3];
+        s[2] ^= t;
+        s[3] = s[3].rotate_left(45);
+        result
+    }
+}
+
+thread_local! {
+    /// The state of the generator of the thread (None until the first use or the seed).
+    static STATE: RefCell<Option<RngState>> = const { RefCell::new(None) };
+}
+
+/// Function to apply a function on the state of the generator of the thread.
+fn with_state<R>(f: impl FnOnce(&mut RngState) -> R) -> R {
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        f(state.get_or_insert_with(|| RngState::from_seed(rand::random())))
+    })
+}
+
+/// Structure to represent the generator of the thread (used with the traits of rand).
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TpgRng;
+
+impl RngCore for TpgRng {
+    /// Function to get a random u32.
+    fn next_u32(&mut self) -> u32 {
+        (self.next_u64() >> 32) as u32
+    }
+
+    /// Function to get a random u64.
+    fn next_u64(&mut self) -> u64 {
+        with_state(|state| state.next())
+    }
+
+    /// Function to fill the bytes with random values.
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        for chunk in dest.chunks_mut(8) {
+            let bytes = self.next_u64().to_le_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+    }
+
+    /// Function to fill the bytes with random values (never fail).
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+/// Function to get the generator of the thread.
+pub fn rng() -> TpgRng {
+    TpgRng
+}
+
+/// Function to seed the generator of the thread (the same seed give the same training).
+pub fn seed_rng(seed: u64) {
+    set_rng_state(RngState::from_seed(seed));
+}
+
+/// Function to get the state of the generator of the thread.
+pub fn get_rng_state() -> RngState {
+    with_state(|state| *state)
+}
+
+/// Function to restore the state of the generator of the thread.
+pub fn set_rng_state(rng_state: RngState) {
+    STATE.with(|state| *state.borrow_mut() = Some(rng_state));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+
+    #[test]
+    fn test_rng_state() {
+        // Check the same seed give the same numbers
+        seed_rng(42);
+        let values: Vec<u32> = (0..5).map(|_| rng().gen_range(0..1000)).collect();
+        seed_rng(42);
+        let other: Vec<u32> = (0..5).map(|_| rng().gen_range(0..1000)).collect();
+        assert_eq!(values, other, "The seed don't give the same numbers");
+
+        // Check the restored state continue the sequence
+        let state = get_rng_state();
+        let values: Vec<u64> = (0..5).map(|_| rng().next_u64()).collect();
+        set_rng_state(state);
+        let other: Vec<u64> = (0..5).map(|_| rng().next_u64()).collect();
+        assert_eq!(values, other, "The restored state don't continue the sequence");
+        assert_ne!(values[0], values[1], "The numbers are not random");
+    }
+}
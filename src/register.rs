@@ -0,0 +1,252 @@
+//! # Register
+//! Crate that permit to define the type of value stored in the registers of the Tangled Program Graph (TPG).
+//! The same type is used for the observation given to the agent and for the bid of the learners.
+
+#![deny(missing_docs)]
+#![deny(clippy::all)]
+#![deny(clippy::missing_docs_in_private_items)]
+
+use crate::ArithmeticPolicy;
+use serde::{de::DeserializeOwned, Serialize};
+use std::fmt::{Debug, Display};
+
+/// Trait to represent a value that can be stored in the registers of a program.
+/// The operations never panic, the policy decide how to handle the invalid result
+/// (overflow for the integer, NaN and infinity for the float).
+/// The value can be saved with the brain (serde).
+pub trait Register:
+    Copy + Debug + Display + Default + PartialOrd + Serialize + DeserializeOwned
+{
+    /// The stable name of the type (e.g. saved with the brain and written by the generated code).
+    const NAME: &'static str;
+    /// Function to convert a float value into the register (used for the constants).
+    fn from_f64(value: f64) -> Self;
+    /// Function to add two values.
+    fn plus(self, other: Self, policy: ArithmeticPolicy) -> Self;
+    /// Function to subtract two values.
+    fn minus(self, other: Self, policy: ArithmeticPolicy) -> Self;
+    /// Function to multiply two values.
+    fn times(self, other: Self, policy: ArithmeticPolicy) -> Self;
+    /// Function to divide two values.
+    /// If the divisor is close to 0 then the value is returned unchanged.
+    fn divide(self, other: Self, policy: ArithmeticPolicy) -> Self;
+    /// Function to multiply the value by 2.
+    fn double(self, policy: ArithmeticPolicy) -> Self;
+    /// Function to divide the value by 2.
+    fn halve(self, policy: ArithmeticPolicy) -> Self;
+    /// Function to negate the value.
+    fn negate(self, policy: ArithmeticPolicy) -> Self;
+    /// Function to keep the value in the valid range of the register.
+    fn finite(self, policy: ArithmeticPolicy) -> Self;
+}
+
+/// Function to apply an integer operation with the arithmetic policy.
+fn apply_integer(
+    policy: ArithmeticPolicy,
+    x: i32,
+    y: i32,
+    wrapping: fn(i32, i32) -> i32,
+    saturating: fn(i32, i32) -> i32,
+    checked: fn(i32, i32) -> Option<i32>,
+) -> i32 {
+    match policy {
+        ArithmeticPolicy::Wrapping => wrapping(x, y),
+        ArithmeticPolicy::Saturating => saturating(x, y),
+        ArithmeticPolicy::Checked { fallback } => {
+            checked(x, y).unwrap_or_else(|| i32::from_f64(fallback))
+        }
+    }
+}
+
+impl Register for i32 {
+    const NAME: &'static str = "i32";
+
+    fn from_f64(value: f64) -> Self {
+        value as i32
+    }
+
+    fn plus(self, other: Self, policy: ArithmeticPolicy) -> Self {
+        apply_integer(
+            policy,
+            self,
+            other,
+            i32::wrapping_add,
+            i32::saturating_add,
+            i32::checked_add,
+        )
+    }
+
+    fn minus(self, other: Self, policy: ArithmeticPolicy) -> Self {
+        apply_integer(
+            policy,
+            self,
+            other,
+            i32::wrapping_sub,
+            i32::saturating_sub,
+            i32::checked_sub,
+        )
+    }
+
+    fn times(self, other: Self, policy: ArithmeticPolicy) -> Self {
+        apply_integer(
+            policy,
+            self,
+            other,
+            i32::wrapping_mul,
+            i32::saturating_mul,
+            i32::checked_mul,
+        )
+    }
+
+    fn divide(self, other: Self, policy: ArithmeticPolicy) -> Self {
+        if other == 0 {
+            self
+        } else {
+            apply_integer(
+                policy,
+                self,
+                other,
+                i32::wrapping_div,
+                i32::saturating_div,
+                i32::checked_div,
+            )
+        }
+    }
+
+    fn double(self, policy: ArithmeticPolicy) -> Self {
+        self.times(2, policy)
+    }
+
+    fn halve(self, _policy: ArithmeticPolicy) -> Self {
+        self / 2
+    }
+
+    fn negate(self, policy: ArithmeticPolicy) -> Self {
+        0.minus(self, policy)
+    }
+
+    fn finite(self, _policy: ArithmeticPolicy) -> Self {
+        self
+    }
+}
+
+/// Macro to implement the register for the float types.
+/// The result of each operation is kept finite:
+///     Wrapping, Saturating
+///         NaN  |-> 0
+///         +inf |-> MAX
+///         -inf |-> MIN
+///     Checked
+///         NaN, +inf, -inf |-> fallback
+macro_rules! impl_register_float {
+    ($t:ty, $name:expr, $epsilon:expr) => {
+        impl Register for $t {
+            const NAME: &'static str = $name;
+
+            fn from_f64(value: f64) -> Self {
+                value as $t
+            }
+
+            fn plus(self, other: Self, policy: ArithmeticPolicy) -> Self {
+                (self + other).finite(policy)
+            }
+
+            fn minus(self, other: Self, policy: ArithmeticPolicy) -> Self {
+                (self - other).finite(policy)
+            }
+
+            fn times(self, other: Self, policy: ArithmeticPolicy) -> Self {
+                (self * other).finite(policy)
+            }
+
+            fn divide(self, other: Self, policy: ArithmeticPolicy) -> Self {
+                if other.abs() < $epsilon {
+                    self
+                } else {
+                    (self / other).finite(policy)
+                }
+            }
+
+            fn double(self, policy: ArithmeticPolicy) -> Self {
+                (self * 2.0).finite(policy)
+            }
+
+            fn halve(self, policy: ArithmeticPolicy) -> Self {
+                (self / 2.0).finite(policy)
+            }
+
+            fn negate(self, policy: ArithmeticPolicy) -> Self {
+                (-self).finite(policy)
+            }
+
+            fn finite(self, policy: ArithmeticPolicy) -> Self {
+                match policy {
+                    ArithmeticPolicy::Checked { fallback } if !self.is_finite() => {
+                        <$t>::from_f64(fallback)
+                    }
+                    _ if self.is_nan() => 0.0,
+                    _ => self.clamp(<$t>::MIN, <$t>::MAX),
+                }
+            }
+        }
+    };
+}
+
+/// The smallest divisor accepted by the division of the f32 registers.
+pub(crate) const DIV_EPSILON_F32: f32 = 1e-6;
+/// The smallest divisor accepted by the division of the f64 registers.
+pub(crate) const DIV_EPSILON_F64: f64 = 1e-9;
+
+impl_register_float!(f32, "f32", DIV_EPSILON_F32);
+impl_register_float!(f64, "f64", DIV_EPSILON_F64);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_register_integer() {
+        let wrapping = ArithmeticPolicy::Wrapping;
+
+        // Check the overflow don't panic
+        assert_eq!(i32::MAX.plus(1, wrapping), i32::MIN, "The add overflow is not wrapped");
+        assert_eq!(i32::MAX.double(wrapping), -2, "The double overflow is not wrapped");
+        assert_eq!(i32::MIN.divide(-1, wrapping), i32::MIN, "The div overflow is not wrapped");
+
+        // Check the division by zero keep the value
+        assert_eq!(7.divide(0, wrapping), 7, "The protected division is not correct");
+        assert_eq!(7.divide(2, wrapping), 3, "The division is not correct");
+    }
+
+    #[test]
+    fn test_register_integer_policy() {
+        let saturating = ArithmeticPolicy::Saturating;
+        let checked = ArithmeticPolicy::Checked { fallback: -1.0 };
+
+        // Check the saturating policy
+        assert_eq!(i32::MAX.plus(1, saturating), i32::MAX, "The add is not saturated");
+        assert_eq!(i32::MIN.negate(saturating), i32::MAX, "The negate is not saturated");
+
+        // Check the checked policy
+        assert_eq!(i32::MAX.times(2, checked), -1, "The fallback is not used");
+        assert_eq!(3.times(2, checked), 6, "The fallback is used without overflow");
+    }
+
+    #[test]
+    fn test_register_float() {
+        let wrapping = ArithmeticPolicy::Wrapping;
+        let checked = ArithmeticPolicy::Checked { fallback: 0.5 };
+
+        // Check the division by zero keep the value
+        assert_eq!(1.5_f32.divide(0.0, wrapping), 1.5, "The protected division is not correct");
+
+        // Check the overflow is clamped to the max value
+        assert_eq!(f64::MAX.times(2.0, wrapping), f64::MAX, "The overflow is not clamped");
+
+        // Check the NaN is replaced by zero
+        assert_eq!(1.0_f64.times(f64::NAN, wrapping), 0.0, "The NaN is not replaced by zero");
+
+        // Check the checked policy
+        assert_eq!(f64::MAX.times(2.0, checked), 0.5, "The fallback is not used");
+    }
+}
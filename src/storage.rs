@@ -0,0 +1,335 @@
+//! # Storage
+//! Crate that permit to save and load the brain of the Tangled Program Graph (TPG) as JSON.
+//! The file contains the version of the format, the type of the register, the name of the
+//! operations (the operations are code and can't be saved) and the brain:
+//!     { "version": 1, "register": "f64", "operations": ["add", ...], "brain": { ... } }
+//! The execution context of an agent (memory and persistent registers) is not in the brain,
+//! it can be saved with serde and given back with `Agent::with_context`, so the agent give the
+//! same decisions after the load.
+
+#![deny(missing_docs)]
+#![deny(clippy::all)]
+#![deny(clippy::missing_docs_in_private_items)]
+
+use crate::{Brain, OperationSet, Register};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+/// The version of the format of the saved brain.
+pub const BRAIN_FORMAT_VERSION: u32 = 1;
+
+/// Enumeration to represent an error while saving or loading a brain.
+#[derive(Debug)]
+pub enum StorageError {
+    /// The file can't be read or written.
+    Io(std::io::Error),
+    /// The file is not a valid JSON or don't follow the format.
+    Json(serde_json::Error),
+    /// The version of the format (of the brain or of the checkpoint) is not supported.
+    UnsupportedVersion(u32),
+    /// The type of the register in the file is not the type of the brain.
+    RegisterMismatch {
+        /// The type of the register in the file.
+        found: String,
+        /// The type of the register of the brain.
+        expected: String,
+    },
+    /// The operations in the file are not the operations given to the load.
+    OperationMismatch {
+        /// The name of the operations in the file.
+        found: Vec<String>,
+        /// The name of the operations given to the load.
+        expected: Vec<String>,
+    },
+    /// An index inside the brain is out of range.
+    InvalidIndex(String),
+}
+
+impl fmt::Display for StorageError {
+    /// Function to display the error.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StorageError::Io(error) => write!(f, "io error: {}", error),
+            StorageError::Json(error) => write!(f, "json error: {}", error),
+            StorageError::UnsupportedVersion(version) => {
+                write!(f, "the version {} is not supported", version)
+            }
+            StorageError::RegisterMismatch { found, expected } => {
+                write!(f, "the register '{}' is not the register '{}'", found, expected)
+            }
+            StorageError::OperationMismatch { found, expected } => write!(
+                f,
+                "the operations {:?} are not the operations {:?}",
+                found, expected
+            ),
+            StorageError::InvalidIndex(message) => write!(f, "invalid index: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for StorageError {}
+
+impl From<std::io::Error> for StorageError {
+    /// Function to convert the io error.
+    fn from(error: std::io::Error) -> Self {
+        StorageError::Io(error)
+    }
+}
+
+impl From<serde_json::Error> for StorageError {
+    /// Function to convert the json error.
+    fn from(error: serde_json::Error) -> Self {
+        StorageError::Json(error)
+    }
+}
+
+/// Structure to represent the content of the file (B is the brain or a reference on the brain).
+#[derive(Serialize, Deserialize)]
+struct BrainFile<B> {
+    /// The version of the format.
+    version: u32,
+    /// The type of the register.
+    register: String,
+    /// The name of the operations (the code of the operation is the index).
+    operations: Vec<String>,
+    /// The brain.
+    brain: B,
+}
+
+/// Structure to read the version before the rest of the file.
+#[derive(Deserialize)]
+pub(crate) struct BrainFileVersion {
+    /// The version of the format.
+    pub(crate) version: u32,
+}
+
+/// Function to check the index inside the brain are in range
+/// (the references and the back references).
+pub(crate) fn check_indices<T: Register>(brain: &Brain<T>) -> Result<(), StorageError> {
+    let nb_learners = brain.learners.len();
+    let nb_teams = brain.teams.len();
+    for team in brain.teams.iter() {
+        let learners = team.get_learners();
+        if let Some(idx) = learners.into_iter().find(|idx| *idx >= nb_learners) {
+            let message = format!("the team {} use the learner {}", team.get_id(), idx);
+            return Err(StorageError::InvalidIndex(message));
+        }
+        let in_learners = team.get_in_learners();
+        if let Some(idx) = in_learners.into_iter().find(|idx| *idx >= nb_learners) {
+            let message = format!("the team {} is used by the learner {}", team.get_id(), idx);
+            return Err(StorageError::InvalidIndex(message));
+        }
+    }
+    for learner in brain.learners.iter() {
+        let idx = learner.get_idx_action();
+        if idx >= brain.actions.len() {
+            let message = format!("the learner {} use the action {}", learner.get_id(), idx);
+            return Err(StorageError::InvalidIndex(message));
+        }
+        let in_teams = learner.get_in_teams();
+        if let Some(idx) = in_teams.into_iter().find(|idx| *idx >= nb_teams) {
+            let message = format!("the learner {} is in the team {}", learner.get_id(), idx);
+            return Err(StorageError::InvalidIndex(message));
+        }
+    }
+    for action in brain.actions.iter() {
+        if let Some(idx) = action.get_action_team().filter(|idx| *idx >= nb_teams) {
+            let message = format!("the action {} use the team {}", action.get_id(), idx);
+            return Err(StorageError::InvalidIndex(message));
+        }
+    }
+    Ok(())
+}
+
+impl<T: Register> Brain<T> {
+    /// Function to write the brain as JSON.
+    pub fn to_json(&self) -> Result<String, StorageError> {
+        Ok(serde_json::to_string(&self.to_value()?)?)
+    }
+
+    /// Function to read the brain from JSON with the operations used by the programs.
+    /// The name of the operations must be the same as the saved operations.
+    pub fn from_json(text: &str, operations: OperationSet<T>) -> Result<Self, StorageError> {
+        let value: serde_json::Value = serde_json::from_str(text)?;
+        Brain::from_value(&value, operations)
+    }
+
+    /// Function to write the brain as a JSON value (with the version, the register and the
+    /// operations), e.g. to put the brain inside a checkpoint.
+    pub(crate) fn to_value(&self) -> Result<serde_json::Value, StorageError> {
+        let file = BrainFile {
+            version: BRAIN_FORMAT_VERSION,
+            register: T::NAME.to_string(),
+            operations: self.operations.names().into_iter().map(String::from).collect(),
+            brain: self,
+        };
+        Ok(serde_json::to_value(&file)?)
+    }
+
+    /// Function to read the brain from a JSON value written by `to_value`.
+    pub(crate) fn from_value(
+        value: &serde_json::Value,
+        operations: OperationSet<T>,
+    ) -> Result<Self, StorageError> {
+        let version = BrainFileVersion::deserialize(value)?.version;
+        if version != BRAIN_FORMAT_VERSION {
+            return Err(StorageError::UnsupportedVersion(version));
+        }
+
+        // The brain is read after the check of the register (the values depend on the register)
+        let header = BrainFile::<serde::de::IgnoredAny>::deserialize(value)?;
+        let expected = T::NAME;
+        if header.register != expected {
+            return Err(StorageError::RegisterMismatch {
+                found: header.register,
+                expected: expected.to_string(),
+            });
+        }
+        let names: Vec<String> = operations.names().into_iter().map(String::from).collect();
+        if header.operations != names {
+            return Err(StorageError::OperationMismatch {
+                found: header.operations,
+                expected: names,
+            });
+        }
+
+        let mut brain = BrainFile::<Brain<T>>::deserialize(value)?.brain;
+        check_indices(&brain)?;
+        brain.operations = operations;
+        for (idx, team) in brain.teams.iter().enumerate() {
+            brain.team_id_to_index.insert(team.get_id(), idx);
+        }
+        for (idx, learner) in brain.learners.iter().enumerate() {
+            brain.learner_id_to_index.insert(learner.get_id(), idx);
+        }
+        for (idx, action) in brain.actions.iter().enumerate() {
+            brain.action_id_to_index.insert(action.get_id(), idx);
+        }
+        brain.team_slots.fill(brain.teams.len());
+        brain.learner_slots.fill(brain.learners.len());
+        brain.action_slots.fill(brain.actions.len());
+        Ok(brain)
+    }
+
+    /// Function to save the brain in the file (JSON).
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), StorageError> {
+        fs::write(path, self.to_json()?)?;
+        Ok(())
+    }
+
+    /// Function to load the brain from the file (JSON) with the default operations.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, StorageError> {
+        Brain::load_with_operations(path, OperationSet::default())
+    }
+
+    /// Function to load the brain from the file (JSON) with the operations used by the programs.
+    pub fn load_with_operations<P: AsRef<Path>>(
+        path: P,
+        operations: OperationSet<T>,
+    ) -> Result<Self, StorageError> {
+        let text = fs::read_to_string(path)?;
+        Brain::from_json(&text, operations)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::brain::brain_from_assembly;
+    use crate::{Agent, ExecutionContext, Memory};
+
+    /// Function to get the brain used by the tests (with a memory and persistent registers).
+    fn get_brain() -> Brain<f64> {
+        // The root team call the team 1 with the learner 2
+        let programs = [
+            ("r1 = r1 + in[0]\nr0 = r0 + r1", 0),
+            ("r0 = r0 + mem[1]\nmem[1] = mem[1] + r0", 1),
+            ("r0 = r0 - in[1]", 2),
+            ("r0 = r0 * in[0]", 3),
+            ("r0 = r0 + in[1]", 4),
+        ];
+        let mut brain = brain_from_assembly(&programs, &[&[0, 1, 2], &[3, 4]], &[(2, 1)]);
+        brain.memory = Memory::new(2, 1.0);
+        brain.learners[0].set_persistent_registers(true);
+        brain
+    }
+
+    #[test]
+    fn test_save_load_brain() {
+        let brain = get_brain();
+        let mut agent = Agent::new(0, &brain);
+        let states: Vec<Vec<f64>> = vec![vec![0.5, -2.0], vec![-1.5, 3.0], vec![2.0, 0.25]];
+        for state in states.iter() {
+            agent.act(&brain, state).unwrap();
+        }
+
+        // Check the loaded brain give the same decisions
+        // (the execution context of the agent is saved with serde)
+        let path = std::env::temp_dir().join(format!("tpg_brain_{}.json", std::process::id()));
+        brain.save(&path).expect("The brain is not saved");
+        let loaded: Brain<f64> = Brain::load(&path).expect("The brain is not loaded");
+        std::fs::remove_file(&path).unwrap();
+        let context = serde_json::to_string(agent.get_context()).unwrap();
+        let context: ExecutionContext<f64> = serde_json::from_str(&context).unwrap();
+        let mut loaded_agent = Agent::with_context(0, context);
+
+        assert_eq!(loaded.get_team_index_from_team_id(1), Some(1), "The id are not restored");
+        assert_eq!(loaded.memory, brain.memory, "The memory of the brain is not saved");
+        assert_eq!(loaded_agent.get_memory(), agent.get_memory(), "The memory is not saved");
+        for _ in 0..3 {
+            for state in states.iter() {
+                let action = agent.act(&brain, state).unwrap();
+                let loaded_action = loaded_agent.act(&loaded, state).unwrap();
+                assert_eq!(loaded_action, action, "The action is not the same");
+            }
+        }
+    }
+
+    #[test]
+    fn test_load_errors() {
+        let brain = get_brain();
+        let text = brain.to_json().unwrap();
+
+        // Check the version, the register and the operations are checked
+        let other_version = text.replacen("\"version\":1", "\"version\":7", 1);
+        let error = Brain::<f64>::from_json(&other_version, OperationSet::default()).err();
+        assert!(
+            matches!(error, Some(StorageError::UnsupportedVersion(7))),
+            "The version is not checked"
+        );
+        let error = Brain::<i32>::from_json(&text, OperationSet::default()).err();
+        assert!(
+            matches!(error, Some(StorageError::RegisterMismatch { .. })),
+            "The register is not checked"
+        );
+        let error = Brain::<f64>::from_json(&text, OperationSet::new()).err();
+        assert!(
+            matches!(error, Some(StorageError::OperationMismatch { .. })),
+            "The operations are not checked"
+        );
+
+        // Check the invalid JSON and the invalid index
+        let error = Brain::<f64>::from_json("{\"version\":1}", OperationSet::default()).err();
+        assert!(matches!(error, Some(StorageError::Json(_))), "The format is not checked");
+        let invalid = text.replacen("\"action_team\":1", "\"action_team\":9", 1);
+        let error = Brain::<f64>::from_json(&invalid, OperationSet::default()).err();
+        assert!(matches!(error, Some(StorageError::InvalidIndex(_))), "The index is not checked");
+
+        // Check the back references and the registers of the execution context are checked
+        let invalid = text.replacen("\"in_learners\":[]", "\"in_learners\":[8]", 1);
+        let error = Brain::<f64>::from_json(&invalid, OperationSet::default()).err();
+        assert!(
+            matches!(error, Some(StorageError::InvalidIndex(_))),
+            "The in learners are not checked"
+        );
+        let mut brain = get_brain();
+        brain.learners[3].set_in_teams(4);
+        let error = Brain::<f64>::from_json(&brain.to_json().unwrap(), OperationSet::default());
+        assert!(
+            matches!(error.err(), Some(StorageError::InvalidIndex(_))),
+            "The in teams are not checked"
+        );
+    }
+}
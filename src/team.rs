@@ -7,11 +7,77 @@
 
 use rand::seq::SliceRandom;
 
-use crate::{flip, Brain, Learner, TeamParams};
+use crate::{flip, rng, Brain, ExecutionContext, Learner, Register, TeamParams, TeamTrace};
 use core::panic;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::fmt;
+
+/// Enumeration to represent an error during the decision of a team.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ActError {
+    /// The team is visited twice during the same decision (the id of the team).
+    AlreadyVisited(i32),
+    /// The team has no learner to bid (the id of the team).
+    EmptyTeam(i32),
+    /// The team is not in the brain (the index of the team).
+    UnknownTeam(usize),
+    /// The size of the block is not a multiple of the number of rows (batch only).
+    MalformedBlock {
+        /// The size of the block.
+        len: usize,
+        /// The number of rows.
+        nb_rows: usize,
+    },
+    /// The program of the learner use the memory of the brain (batch only).
+    UnsupportedMemory {
+        /// The id of the learner.
+        learner: i32,
+    },
+    /// The learner has persistent registers (batch only).
+    UnsupportedPersistentRegisters {
+        /// The id of the learner.
+        learner: i32,
+    },
+}
+
+impl fmt::Display for ActError {
+    /// Function to display the error.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ActError::AlreadyVisited(team_id) => write!(f, "already visited team {}", team_id),
+            ActError::EmptyTeam(team_id) => write!(f, "the team {} has no learner", team_id),
+            ActError::UnknownTeam(team_idx) => write!(f, "unknown team {}", team_idx),
+            ActError::MalformedBlock { len, nb_rows } => {
+                write!(f, "the size of the block ({}) is not a multiple of {} rows", len, nb_rows)
+            }
+            ActError::UnsupportedMemory { learner } => {
+                write!(f, "the learner {} use the memory (not supported in batch)", learner)
+            }
+            ActError::UnsupportedPersistentRegisters { learner } => {
+                let text = "has persistent registers (not supported in batch)";
+                write!(f, "the learner {} {}", learner, text)
+            }
+        }
+    }
+}
 
-#[derive(Debug, Clone)]
+impl std::error::Error for ActError {}
+
+/// Function to check if the learner can win in a team during a decision:
+/// the action of the learner is atomic or the team of the action is not already visited.
+pub(crate) fn is_valid_learner<T: Register>(
+    brain: &Brain<T>,
+    learner_idx: usize,
+    visited: &[i32],
+) -> bool {
+    match brain.learners[learner_idx].get_action_team(brain) {
+        Some(team_idx) => !visited.contains(&brain.teams[team_idx].get_id()),
+        None => true,
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 /// Structure to represent the team.
 pub struct Team {
     /// The id of the team.
@@ -55,6 +121,11 @@ impl Team {
         self.learners.to_vec()
     }
 
+    /// Function to get the learners with an action referencing the team
+    pub fn get_in_learners(&self) -> Vec<usize> {
+        self.in_learners.to_vec()
+    }
+
     /// Function to get the number of learner in the team
     pub fn get_len_in_learners(&self) -> usize {
         self.in_learners.len()
@@ -89,6 +160,11 @@ impl Team {
         self.id
     }
 
+    /// Function to set the id of the team (e.g. when the team is imported in a brain).
+    pub(crate) fn set_id(&mut self, id: i32) {
+        self.id = id;
+    }
+
     /// Function to remove all learner from the team.
     pub fn remove_learners(&mut self) {
         self.learners.clear();
@@ -104,6 +180,19 @@ impl Team {
         self.learners.push(learner_idx);
     }
 
+    /// Function to add a learner with an action referencing the team (if not already added).
+    pub(crate) fn add_in_learner(&mut self, learner_idx: usize) {
+        if !self.in_learners.contains(&learner_idx) {
+            self.in_learners.push(learner_idx);
+        }
+    }
+
+    /// Function to change the index of the learners (the learners remapped to None are removed).
+    pub(crate) fn remap_learners(&mut self, remap: &impl Fn(usize) -> Option<usize>) {
+        self.learners = self.learners.iter().filter_map(|idx| remap(*idx)).collect();
+        self.in_learners = self.in_learners.iter().filter_map(|idx| remap(*idx)).collect();
+    }
+
     /// Function to set the outcomes.
     pub fn set_outcomes(&mut self, task: String, outcome: i32) {
         self.outcomes.remove(&task);
@@ -121,12 +210,9 @@ impl Team {
     }
 
     /// Function to reset the register of the learner
-    pub fn zero_registers(&mut self, brain: &Brain) {
-        // Get the list of learner in the brain
-        let mut list_learner = brain.learners.to_vec();
+    pub fn zero_registers<T: Register>(&self, brain: &mut Brain<T>) {
         for idx_learner in &self.learners {
-            let learner_mut = list_learner.get_mut(*idx_learner).unwrap();
-            learner_mut.zero_registers();
+            brain.learners[*idx_learner].zero_registers();
         }
     }
 
@@ -136,7 +222,7 @@ impl Team {
     }
 
     /// Function to get the number of atomic action in the team
-    pub fn num_atomic_actions(&self, brain: &Brain) -> i32 {
+    pub fn num_atomic_actions<T: Register>(&self, brain: &Brain<T>) -> i32 {
         let mut num_atomic_actions: i32 = 0;
         for lrnr_idx in self.learners.iter() {
             let learner = brain.learners.get(*lrnr_idx).unwrap();
@@ -148,58 +234,62 @@ impl Team {
     }
 
     /// Function to act
-    pub fn act(&self, brain: &Brain, state: &Vec<i32>, visited: &mut Vec<i32>) -> i32 {
-        if visited.contains(&self.id) {
-            panic!("Already visited team {}!", self.id);
+    /// The brain is only read, the registers and the memory are in the execution context.
+    /// The trace record the index of the team set in the context by the caller
+    /// (see `Action::get_action`).
+    /// A learner is valid if its action is atomic or its team is not already visited, the
+    /// action of the valid learner with the highest bid is followed (so a team is never visited
+    /// twice, the first learner wins on equal bids).
+    /// A learner with an atomic action is always valid, so no learner is valid only if every
+    /// learner call a visited team: the decision end in the team with the default action of the
+    /// brain (fallback, see `Brain::default_action`).
+    /// Return an error if the team is already visited or has no learner.
+    pub fn act<T: Register>(
+        &self,
+        brain: &Brain<T>,
+        state: &[T],
+        context: &mut ExecutionContext<T>,
+    ) -> Result<i32, ActError> {
+        if context.visited.contains(&self.id) {
+            return Err(ActError::AlreadyVisited(self.id));
+        }
+        if self.learners.is_empty() {
+            return Err(ActError::EmptyTeam(self.id));
         }
 
         // Put the current team to the list of already visited team.
-        visited.push(self.id);
-
-        // A Learner is valid if he has atomic action or the team reference with the action is not in the list of team already visited !
-        let mut valid_learners: Vec<usize> = Vec::new();
-        for lrnr in &self.learners {
-            let mut team_already_visited: bool = false;
-            if let Some(team_tmp) = brain.learners[*lrnr].get_action_team(brain) {
-                let team_id = brain.teams[team_tmp].get_id();
-                if visited.contains(&team_id) {
-                    team_already_visited = true;
-                }
-            }
-
-            let learner_with_atomic_action = brain.learners[*lrnr].is_action_atomic(brain);
-            if learner_with_atomic_action || !team_already_visited {
-                valid_learners.push(*lrnr);
+        context.visited.push(self.id);
+
+        // Compute the top valid learner (with highest bid)
+        // The bids are only kept if the decision is traced
+        let tracing = context.trace.is_some();
+        let mut bids: Vec<(usize, T)> = Vec::new();
+        let mut top_valid: Option<(usize, T)> = None;
+        for lrnr in self.learners.iter().copied() {
+            let bid = context.bid(brain, lrnr, state);
+            if tracing {
+                bids.push((lrnr, bid));
             }
-        }
-
-        // Get the list of learner from the brain
-        let mut list_learner = brain.learners.to_vec();
-
-        println!("Number of learners in the team: {}", self.learners.len());
-
-        // Compute top learner (with highest bid)
-        let learner_mut = list_learner.get_mut(self.learners[0]).unwrap();
-        let mut max_bid = learner_mut.bid(state);
-        let mut idx_top_learner = self.learners[0];
-        println!("Learner n°{} => Bid: {}", idx_top_learner, max_bid);
 
-        for i in 1..self.learners.len() {
-            let lrnr = self.learners[i];
-            let learner_mut = list_learner.get_mut(lrnr).unwrap();
-            let bid = learner_mut.bid(state);
-            println!("Learner n°{} => Bid: {}", lrnr, bid);
-
-            if bid > max_bid {
-                max_bid = bid;
-                idx_top_learner = lrnr;
+            let is_top_valid = top_valid.is_none_or(|(_, max_bid)| bid > max_bid);
+            if is_top_valid && is_valid_learner(brain, lrnr, &context.visited) {
+                top_valid = Some((lrnr, bid));
             }
         }
-
-        println!("The best learner is {}", idx_top_learner);
+        let winner = top_valid.map(|(lrnr, _)| lrnr);
 
         // Make path_trace
-        brain.learners[idx_top_learner].get_action(brain, state, visited)
+        if let Some(trace) = context.trace.as_mut() {
+            trace.push(TeamTrace {
+                team: context.team,
+                bids,
+                winner,
+            });
+        }
+        match winner {
+            Some(lrnr) => brain.learners[lrnr].get_action(brain, state, context),
+            None => Ok(brain.default_action),
+        }
     }
 
     /// Executes a delete mutation with a certain probability
@@ -209,7 +299,7 @@ impl Team {
     ///     - Verifies that there is always at least one learner pointing to an atomic action on a team, raise an panic otherwise.
     ///     - If there is only one learner pointing to an atomic filter it out and pick from the remaining learners.
     ///     - Return a list of learners removed from the team
-    pub fn mutation_delete(&mut self, brain: &Brain, probability: f64) -> Vec<usize> {
+    pub fn mutation_delete<T: Register>(&mut self, brain: &Brain<T>, probability: f64) -> Vec<usize> {
         let mut deleted_learner: Vec<usize> = Vec::new();
         if probability == 0.0 {
             return deleted_learner;
@@ -226,7 +316,7 @@ impl Team {
         while flip(probability) && self.learners.len() > 2 {
             let list_learner = self.learners.to_vec();
 
-            let mut idx_learner: usize = *list_learner.choose(&mut rand::thread_rng()).unwrap();
+            let mut idx_learner: usize = *list_learner.choose(&mut rng()).unwrap();
             if self.num_atomic_actions(brain) < 1 {
                 let mut valid_learner: Vec<usize> = Vec::new();
                 for idx_lrnr in list_learner {
@@ -235,7 +325,7 @@ impl Team {
                         valid_learner.push(idx_lrnr);
                     }
                 }
-                idx_learner = *valid_learner.choose(&mut rand::thread_rng()).unwrap();
+                idx_learner = *valid_learner.choose(&mut rng()).unwrap();
             }
             deleted_learner.push(idx_learner);
             self.remove_learner(idx_learner);
@@ -269,7 +359,7 @@ impl Team {
                 break;
             }
 
-            let idx_learner = selection_pool.choose(&mut rand::thread_rng()).unwrap();
+            let idx_learner = selection_pool.choose(&mut rng()).unwrap();
             added_learner.push(*idx_learner);
 
             self.add_learner(*idx_learner);
@@ -286,9 +376,9 @@ impl Team {
     }
 
     /// Iterate throught this team's learners and mutates them with a given probability.
-    pub fn mutation_mutate(
+    pub fn mutation_mutate<T: Register>(
         &mut self,
-        brain: &mut Brain,
+        brain: &mut Brain<T>,
         probability: f64,
         mutate_params: &mut TeamParams,
         teams: &Vec<usize>,
@@ -351,9 +441,9 @@ impl Team {
     }
 
     /// Mutates the learner set of this team.
-    pub fn mutate(
+    pub fn mutate<T: Register>(
         &mut self,
-        brain: &mut Brain,
+        brain: &mut Brain<T>,
         mutate_params: &mut TeamParams,
         all_learners: Vec<usize>,
         teams: Vec<usize>,
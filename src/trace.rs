@@ -0,0 +1,162 @@
+//! # Trace
+//! Crate that permit to explain a decision of an agent of the Tangled Program Graph (TPG).
+//! The trace contains the teams visited (in the order of the visit), the bid of each learner
+//! of each team, the learner followed and the atomic action at the end.
+//! The trace is recorded by the execution context during `Team::act`
+//! (see `Agent::act_with_trace`).
+
+#![deny(missing_docs)]
+#![deny(clippy::all)]
+#![deny(clippy::missing_docs_in_private_items)]
+
+use crate::Register;
+
+/// Structure to represent the visit of a team during a decision.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TeamTrace<T: Register> {
+    /// The index of the team in the brain.
+    pub(crate) team: usize,
+    /// The bid of each learner of the team (index of the learner, bid) in the order of the team.
+    pub(crate) bids: Vec<(usize, T)>,
+    /// The index of the learner followed (the valid learner with the highest bid),
+    /// None if no learner is valid (the decision end with the default action of the brain).
+    pub(crate) winner: Option<usize>,
+}
+
+impl<T: Register> TeamTrace<T> {
+    /// Function to get the index of the team.
+    pub fn get_team(&self) -> usize {
+        self.team
+    }
+
+    /// Function to get the bid of each learner of the team (index of the learner, bid).
+    pub fn get_bids(&self) -> &[(usize, T)] {
+        &self.bids
+    }
+
+    /// Function to get the index of the learner followed (the valid learner with the highest bid).
+    pub fn get_winner(&self) -> Option<usize> {
+        self.winner
+    }
+
+    /// Function to check if no learner of the team is valid (the decision end in the team with
+    /// the default action of the brain).
+    pub fn is_fallback(&self) -> bool {
+        self.winner.is_none()
+    }
+}
+
+/// Structure to represent the path of a decision.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DecisionTrace<T: Register> {
+    /// The teams visited in the order of the visit.
+    pub(crate) teams: Vec<TeamTrace<T>>,
+    /// The atomic action at the end of the path.
+    pub(crate) action: i32,
+}
+
+impl<T: Register> DecisionTrace<T> {
+    /// Function to get the visit of each team in the order of the visit.
+    pub fn get_teams(&self) -> &[TeamTrace<T>] {
+        &self.teams
+    }
+
+    /// Function to get the index of the teams visited in the order of the visit.
+    pub fn get_visited_teams(&self) -> Vec<usize> {
+        self.teams.iter().map(|team| team.team).collect()
+    }
+
+    /// Function to get the index of the learners followed in the visited teams
+    /// (no learner is followed in the team of a fallback).
+    pub fn get_winners(&self) -> Vec<usize> {
+        self.teams.iter().filter_map(|team| team.winner).collect()
+    }
+
+    /// Function to check if the decision end with a fallback (no valid learner in a team).
+    pub fn is_fallback(&self) -> bool {
+        self.teams.iter().any(|team| team.is_fallback())
+    }
+
+    /// Function to get the atomic action at the end of the path.
+    pub fn get_action(&self) -> i32 {
+        self.action
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::brain::brain_from_assembly;
+    use crate::{ActError, Agent, Brain, ExecutionContext};
+
+    /// The programs of the tests (the learner 3 has the highest bid of the team 1).
+    const PROGRAMS: [(&str, i32); 4] = [
+        ("r0 = r0 + in[0]", 10),
+        ("r0 = r0 + in[1]", 11),
+        ("r0 = r0 - in[0]", 12),
+        ("r0 = r0 + in[0]\nr0 = r0 + in[1]", 13),
+    ];
+
+    #[test]
+    fn test_decision_trace() {
+        // The root team 0 call the team 1 with the learner 1
+        let brain: Brain<i32> = brain_from_assembly(&PROGRAMS, &[&[0, 1], &[2, 3]], &[(1, 1)]);
+
+        // Check the teams visited, the bids and the action
+        let mut agent = Agent::new(0, &brain);
+        let trace = agent.act_with_trace(&brain, &[1, 5]).unwrap();
+        assert_eq!(trace.get_visited_teams(), vec![0, 1], "The teams visited are incorrect");
+        assert_eq!(trace.get_teams()[0].get_bids(), &[(0, 1), (1, 5)], "The bids are incorrect");
+        assert_eq!(trace.get_teams()[1].get_bids(), &[(2, -1), (3, 6)], "The bids are incorrect");
+        assert_eq!(trace.get_winners(), vec![1, 3], "The winners are incorrect");
+        assert_eq!(trace.get_action(), 13, "The action is incorrect");
+        let action = agent.act(&brain, &[1, 5]).unwrap();
+        assert_eq!(trace.get_action(), action, "The action is not the same as the act");
+
+        // Check the trace with an other context and the trace is not kept after the decision
+        let mut context: ExecutionContext<i32> = ExecutionContext::default();
+        let trace = agent.act_with_context_and_trace(&brain, &[4, 1], &mut context).unwrap();
+        assert_eq!(trace.get_visited_teams(), vec![0], "The teams visited are incorrect");
+        assert_eq!(trace.get_action(), 10, "The action is incorrect");
+        assert!(context.trace.is_none(), "The trace is kept in the context");
+    }
+
+    #[test]
+    fn test_cycle() {
+        // The root team 0 call the team 1 with the learner 1, the learner 3 call back the team 0
+        let teams: [&[usize]; 3] = [&[0, 1], &[2, 3], &[]];
+        let mut brain: Brain<i32> = brain_from_assembly(&PROGRAMS, &teams, &[(1, 1), (3, 0)]);
+
+        // Check the learner 3 (highest bid) is not followed because the team 0 is visited
+        let mut agent = Agent::new(0, &brain);
+        let trace = agent.act_with_trace(&brain, &[1, 5]).unwrap();
+        assert_eq!(trace.get_visited_teams(), vec![0, 1], "The teams visited are incorrect");
+        assert_eq!(trace.get_winners(), vec![1, 2], "The winners are incorrect");
+        assert!(!trace.is_fallback(), "The decision is a fallback");
+        assert_eq!(trace.get_action(), 12, "The action is incorrect");
+
+        // Check the fallback to the default action when no learner of the team 1 is valid
+        brain.actions[2].set_action_team(Some(0));
+        brain.default_action = 7;
+        let trace = agent.act_with_trace(&brain, &[1, 5]).unwrap();
+        assert_eq!(trace.get_winners(), vec![1], "The winners are incorrect");
+        assert_eq!(trace.get_teams()[1].get_winner(), None, "A learner is followed");
+        assert!(trace.get_teams()[1].is_fallback(), "The decision is not a fallback");
+        assert_eq!(trace.get_action(), 7, "The action of the fallback is incorrect");
+        let actions = agent.act_batch(&brain, &[1, 4, 5, 1], 2).unwrap();
+        assert_eq!(actions, vec![7, 10], "The actions of the batch are incorrect");
+
+        // Check the team without learner return an error
+        let mut agent = Agent::new(2, &brain);
+        let error = Err(ActError::EmptyTeam(2));
+        assert_eq!(agent.act(&brain, &[1, 5]), error, "The error is incorrect");
+        let batch_error = error.map(|code| vec![code]);
+        assert_eq!(agent.act_batch(&brain, &[1, 5], 1), batch_error, "The error is incorrect");
+
+        // Check the unknown root team return an error
+        let mut agent = Agent::new(3, &brain);
+        let error = Err(ActError::UnknownTeam(3));
+        assert_eq!(agent.act(&brain, &[1, 5]), error, "The error is incorrect");
+        let batch_error = error.map(|code| vec![code]);
+        assert_eq!(agent.act_batch(&brain, &[1, 5], 1), batch_error, "The error is incorrect");
+    }
+}
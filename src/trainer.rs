@@ -5,11 +5,37 @@
 #![deny(clippy::all)]
 #![deny(clippy::missing_docs_in_private_items)]
 
-use crate::{TrainerParams, Action, Agent, Brain, Learner, Program, Team};
+use crate::{flip, rng, TrainerParams, Action, Agent, Brain, GarbageReport, Learner, Memory, Program, Register, StorageError, Team, Violation};
 use rand::{seq::SliceRandom, Rng};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Enumeration to represent an error during the evolution.
+#[derive(Debug)]
+pub enum EvolveError {
+    /// The brain is corrupted by the evolution (the violations found by the validation).
+    Corrupted(Vec<Violation>),
+    /// The checkpoint of the generation can't be saved.
+    Checkpoint(StorageError),
+}
+
+impl fmt::Display for EvolveError {
+    /// Function to display the error.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EvolveError::Corrupted(violations) => {
+                let messages: Vec<String> = violations.iter().map(|v| v.to_string()).collect();
+                write!(f, "the brain is corrupted: {}", messages.join(", "))
+            }
+            EvolveError::Checkpoint(error) => write!(f, "the checkpoint is not saved: {}", error),
+        }
+    }
+}
+
+impl std::error::Error for EvolveError {}
 
 /// Structure to represent the trainer
-#[derive(Default)]
+#[derive(Default, Serialize, Deserialize)]
 pub struct Trainer {
     /// Bool to say if we concerve elites
     do_elites: bool,
@@ -32,7 +58,10 @@ pub struct Trainer {
     /// The number of action
     action_lengths: usize,
     /// The trainer information
-    mutate_params: TrainerParams,
+    pub(crate) mutate_params: TrainerParams,
+    /// The path of the checkpoints saved by the evolution (the oldest first).
+    #[serde(default)]
+    pub(crate) checkpoints: Vec<String>,
 }
 
 impl Trainer {
@@ -62,14 +91,35 @@ impl Trainer {
             action_codes,
             action_lengths,
             mutate_params,
+            checkpoints: Vec::new(),
         }
     }
 
+    /// Function to check the index of the trainer are in range of the brain
+    /// (e.g. after the load of a checkpoint).
+    pub(crate) fn check_indices<T: Register>(&self, brain: &Brain<T>) -> Result<(), StorageError> {
+        let teams = self.teams.iter().chain(&self.root_teams).chain(&self.elites);
+        if let Some(idx) = teams.copied().find(|idx| *idx >= brain.teams.len()) {
+            let message = format!("the trainer use the team {}", idx);
+            return Err(StorageError::InvalidIndex(message));
+        }
+        if let Some(idx) = self.learners.iter().copied().find(|idx| *idx >= brain.learners.len()) {
+            let message = format!("the trainer use the learner {}", idx);
+            return Err(StorageError::InvalidIndex(message));
+        }
+        Ok(())
+    }
+
     /// Function to get the number of teams in the trainer
     pub fn get_nb_teams(&self) -> usize {
         self.teams.len()
     }
 
+    /// Function to get the generation of the trainer
+    pub fn get_generation(&self) -> i32 {
+        self.generation
+    }
+
     /// Function to setup actions
     pub fn set_up_actions(&mut self, actions: Vec<i32>) {
         if actions.is_empty() {
@@ -83,20 +133,74 @@ impl Trainer {
         }
     }
 
+    /// Function to check the operation set of the brain is not empty
+    /// (the programs only select the operations of the brain).
+    fn check_operations<T: Register>(&self, brain: &Brain<T>) {
+        if brain.operations.is_empty() {
+            panic!("The operation set of the brain is empty !");
+        }
+    }
+
+    /// Function to create a new learner with the crossover of the program of two learners.
+    /// The new learner get a copy of the action of the first learner (with a new id).
+    /// The back references of the new learner are set when its team is linked
+    /// (see `Brain::link_team`).
+    /// Return the index of the new learner in the brain.
+    fn crossover_learners<T: Register>(
+        &mut self,
+        brain: &mut Brain<T>,
+        learner1_idx: usize,
+        learner2_idx: usize,
+    ) -> usize {
+        let learner1 = brain.learners.get(learner1_idx).unwrap();
+        let learner2 = brain.learners.get(learner2_idx).unwrap();
+        let learner_params = &mut self.mutate_params.team.learner;
+
+        let program = learner1
+            .get_program()
+            .crossover_child(&learner2.get_program(), &mut learner_params.program);
+        let len_register = learner1.get_len_register();
+
+        // Copy the action so the new learner don't share the action of the first learner
+        let mut action = brain.actions[learner1.get_idx_action()].clone();
+        action.set_id(learner_params.action.get_new_id_action());
+        let action_idx = brain.actions.len();
+        brain.add_action(action);
+
+        let learner: Learner<T> = Learner::new(learner_params, program, action_idx, len_register);
+        let learner_idx = brain.learners.len();
+
+        brain.add_learner(learner);
+        learner_idx
+    }
+
     /// The initialization of populations
-    pub fn initialize_populations(&mut self, brain: &mut Brain) {
+    pub fn initialize_populations<T: Register>(&mut self, brain: &mut Brain<T>) {
         // Check that the list of action is not empty.
         if self.action_codes.len() < 2 {
             panic!("The list of action codes is empty !");
         }
 
+        // Only create instructions with the operations of the brain.
+        self.check_operations(brain);
+
+        // Create the memory of the brain (read and written by the modes 2 and 3).
+        let program_params = &self.mutate_params.team.learner.program;
+        brain.memory = Memory::new(
+            program_params.memory_size.max(0) as usize,
+            program_params.p_memory_write,
+        );
+
+        // The fallback of the decisions is the first action code (see `Team::act`).
+        brain.default_action = self.action_codes[0];
+
         // For each team in the population.
         for _i in 0..self.team_pop_size {
             // Choose 2 unique actions in the list of action.
-            let a1 = self.action_codes.choose(&mut rand::thread_rng()).unwrap();
-            let mut a2 = self.action_codes.choose(&mut rand::thread_rng()).unwrap();
+            let a1 = self.action_codes.choose(&mut rng()).unwrap();
+            let mut a2 = self.action_codes.choose(&mut rng()).unwrap();
             while *a2 == *a1 {
-                a2 = self.action_codes.choose(&mut rand::thread_rng()).unwrap();
+                a2 = self.action_codes.choose(&mut rng()).unwrap();
             }
 
             // Create the first action.
@@ -136,6 +240,7 @@ impl Trainer {
                     .team
                     .learner
                     .program,
+                &brain.operations,
             );
 
             let program2: Program = Program::new(
@@ -145,10 +250,11 @@ impl Trainer {
                     .team
                     .learner
                     .program,
+                &brain.operations,
             );
 
             // Create two learner based on the two action and append it in the brain.
-            let learner1: Learner = Learner::new(
+            let learner1: Learner<T> = Learner::new(
                 &mut self.mutate_params.team.learner,
                 program1.clone(),
                 action1_idx,
@@ -159,7 +265,7 @@ impl Trainer {
             // Add the learner 1 in the brain.
             brain.add_learner(learner1.clone());
 
-            let learner2: Learner = Learner::new(
+            let learner2: Learner<T> = Learner::new(
                 &mut self.mutate_params.team.learner,
                 program2.clone(),
                 action2_idx,
@@ -180,13 +286,13 @@ impl Trainer {
             }
 
             // Add more learners
-            let mut rng = rand::thread_rng();
+            let mut rng = rng();
             let more_learners = rng.gen_range(0..self.mutate_params.max_learner_in_team - 2);
 
             // If me add more learners
             for _i in 0..more_learners {
                 // Select action
-                let act = self.action_codes.choose(&mut rand::thread_rng()).unwrap();
+                let act = self.action_codes.choose(&mut rng).unwrap();
 
                 // Create a new action
                 let action_tmp = Action::new(
@@ -210,6 +316,7 @@ impl Trainer {
                         .team
                         .learner
                         .program,
+                    &brain.operations,
                 );
 
                 // Create a new learner
@@ -230,8 +337,9 @@ impl Trainer {
 
             let team_idx = brain.teams.len();
 
-            // Save the team in the brain
+            // Save the team in the brain (with the back references)
             brain.add_team(team.clone());
+            brain.link_team(team_idx);
 
             // Add reference of the team to the trainer
             self.teams.push(team_idx);
@@ -243,12 +351,12 @@ impl Trainer {
 
     /// Function to get the root teams / agents.
     /// Sort decending by sortTasks, and skips individuals who don't have scores for all skip tasks.
-    pub fn get_agents(
+    pub fn get_agents<T: Register>(
         &self,
-        brain: &Brain,
+        brain: &Brain<T>,
         sort_tasks: Vec<String>,
         skip_tasks: Vec<String>,
-    ) -> Vec<Agent> {
+    ) -> Vec<Agent<T>> {
         let mut r_teams: Vec<usize> = Vec::new();
         for team_idx in &self.root_teams {
             let team = brain.teams.get(*team_idx).unwrap();
@@ -264,10 +372,10 @@ impl Trainer {
             }
         }
 
-        let mut list_agents: Vec<Agent> = Vec::new();
+        let mut list_agents: Vec<Agent<T>> = Vec::new();
         if sort_tasks.is_empty() {
             for team_idx in r_teams.iter() {
-                let agent: Agent = Agent::new(*team_idx);
+                let agent: Agent<T> = Agent::new(*team_idx, brain);
                 list_agents.push(agent);
             }
             list_agents
@@ -295,7 +403,7 @@ impl Trainer {
                 }
             }
             
-            let agent: Agent = Agent::new(best_team_idx);
+            let agent: Agent<T> = Agent::new(best_team_idx, brain);
             list_agents.push(agent);
             list_agents
         } else {
@@ -304,7 +412,7 @@ impl Trainer {
     }
 
     /// Function to get the elite agents of trainer
-    pub fn get_elite_agent(&self, brain: &Brain, task: String) -> Agent {
+    pub fn get_elite_agent<T: Register>(&self, brain: &Brain<T>, task: String) -> Agent<T> {
         let mut teams: Vec<usize> = Vec::new();
         for t_idx in &self.teams {
             let team = brain.teams.get(*t_idx).unwrap();
@@ -325,11 +433,22 @@ impl Trainer {
             }
         }
 
-        Agent::new(best_team_idx)
+        Agent::new(best_team_idx, brain)
     }
 
     /// Function to evolve the trainer
-    pub fn evolve(&mut self, brain: &mut Brain, tasks: Vec<String>, extra_teams: &Vec<usize>) {
+    /// The teams culled by the evolution stay in the brain until an explicit call of
+    /// `collect_garbage`, so the index held by the caller (e.g. the agents or the extra teams)
+    /// are not changed.
+    /// Return an error with the violations if the validation after the evolution
+    /// (`TrainerParams::validate_after_evolve`) find a corruption of the brain,
+    /// or an error if the checkpoint can't be saved.
+    pub fn evolve<T: Register>(
+        &mut self,
+        brain: &mut Brain<T>,
+        tasks: Vec<String>,
+        extra_teams: &Vec<usize>,
+    ) -> Result<(), EvolveError> {
         // Assign score to individuals
         println!("Before the score individuals !");
         self.score_individuals(brain, tasks, self.do_elites);
@@ -342,11 +461,29 @@ impl Trainer {
         // Set up for the next generation
         println!("Before the next epoch !");
         self.next_epoch(brain);
+        // Check the evolution don't corrupt the brain
+        if self.mutate_params.validate_after_evolve {
+            let violations = self.validate_population(brain, extra_teams);
+            if !violations.is_empty() {
+                return Err(EvolveError::Corrupted(violations));
+            }
+        }
+        // Save a checkpoint every N generations
+        let interval = self.mutate_params.checkpoint_interval;
+        if interval > 0 && self.generation % interval == 0 {
+            self.auto_save_checkpoint(brain).map_err(EvolveError::Checkpoint)?;
+        }
+        Ok(())
     }
 
     /// Assign a fitness to each agent based on performance at the tasks.
     /// Assigns fitness value, or just returns sorted root teams.
-    pub fn score_individuals(&mut self, brain: &mut Brain, tasks: Vec<String>, do_elites: bool) {
+    pub fn score_individuals<T: Register>(
+        &mut self,
+        brain: &mut Brain<T>,
+        tasks: Vec<String>,
+        do_elites: bool,
+    ) {
         // handle generation of new elites, typically just done in evolution
 
         if do_elites {
@@ -380,7 +517,7 @@ impl Trainer {
     }
 
     /// Select a portion of the root team population to keep according to gap size.
-    pub fn select(&mut self, brain: &mut Brain, extra_teams: &[usize]) {
+    pub fn select<T: Register>(&mut self, brain: &mut Brain<T>, extra_teams: &[usize]) {
         // Get the new list of team idx sorted by fitness.
         let ranked_team_idx: Vec<usize> = brain.sort_teams_idx_with_fitness(&self.root_teams);
         println!(
@@ -430,8 +567,7 @@ impl Trainer {
         for team_idx in team_select {
             // remove learners from teams and delete team from population
             if !extra_teams.contains(&team_idx) {
-                let team = brain.teams.get_mut(team_idx).unwrap();
-                team.remove_learners();
+                brain.clear_team(team_idx);
             }
 
             // Get the index of the teams_idx in the teams list
@@ -443,9 +579,7 @@ impl Trainer {
             self.teams.remove(idx_element_teams);
             self.root_teams.remove(idx_element_root_teams);
 
-            // TODO: Maybe remove team from the brain
-
-            // TODO: Clean the link between trainer and brain
+            // The team is removed from the brain by `collect_garbage`
         }
 
         // Manage orphans learner
@@ -480,7 +614,7 @@ impl Trainer {
     }
 
     /// Generate new root teams based on existing teams.
-    pub fn generate(&mut self, brain: &mut Brain, extra_teams: &Vec<usize>) {
+    pub fn generate<T: Register>(&mut self, brain: &mut Brain<T>, extra_teams: &Vec<usize>) {
         let mut protected_extras: Vec<usize> = Vec::new();
         let mut extras_added: i32 = 0;
 
@@ -498,16 +632,31 @@ impl Trainer {
 
         self.mutate_params.generation = self.generation;
 
+        // Only mutate instructions with the operations of the brain.
+        self.check_operations(brain);
+
         let mut number_teams = self.teams.len() as i32;
         while number_teams < (self.team_pop_size + extras_added) {
             // get parent root team, and child to be based on that
-            let parent_idx = self.root_teams.choose(&mut rand::thread_rng()).unwrap();
+            let parent_idx = self.root_teams.choose(&mut rng()).unwrap();
             let parent = brain.teams.get(*parent_idx).unwrap();
+            let mut parent_learners = parent.get_learners();
 
             let mut child: Team = Team::new(&mut self.mutate_params.team);
 
+            // maybe replace a learner of the parent by the crossover with an other learner
+            if !parent_learners.is_empty()
+                && !o_learners.is_empty()
+                && flip(self.mutate_params.team.learner.p_crossover)
+            {
+                let mut rng = rng();
+                let i = rng.gen_range(0..parent_learners.len());
+                let other_idx = *o_learners.choose(&mut rng).unwrap();
+                parent_learners[i] = self.crossover_learners(brain, parent_learners[i], other_idx);
+            }
+
             // child start just like parent
-            for learner_idx in parent.get_learners() {
+            for learner_idx in parent_learners {
                 child.add_learner(learner_idx);
             }
 
@@ -523,6 +672,7 @@ impl Trainer {
             let child_idx = brain.teams.len();
 
             brain.add_team(child);
+            brain.link_team(child_idx);
             self.teams.push(child_idx);
 
             number_teams = self.teams.len() as i32;
@@ -536,8 +686,53 @@ impl Trainer {
         }
     }
 
+    /// Function to remove from the brain the teams, the learners and the actions not reachable
+    /// from the teams of the trainer and the protected extra teams.
+    /// The index of the trainer (teams, root teams, learners and elites) are remapped,
+    /// the other index (e.g. the extra teams or the agents) are remapped with the report.
+    pub fn collect_garbage<T: Register>(
+        &mut self,
+        brain: &mut Brain<T>,
+        extra_teams: &[usize],
+    ) -> GarbageReport {
+        let mut roots: Vec<usize> = self.teams.to_vec();
+        roots.extend_from_slice(extra_teams);
+        let report = brain.collect_garbage(&roots);
+
+        self.teams = report.remap_teams(&self.teams);
+        self.root_teams = report.remap_teams(&self.root_teams);
+        self.learners = report.remap_learners(&self.learners);
+        self.elites = report.remap_teams(&self.elites);
+        report
+    }
+
+    /// Function to check the invariants of the brain with the root teams of the trainer.
+    /// All the teams of the brain are checked (also the teams that are not in the trainer).
+    pub fn validate<T: Register>(&self, brain: &Brain<T>) -> Vec<Violation> {
+        brain.validate(&self.root_teams)
+    }
+
+    /// Function to check the invariants of the brain after the evolution.
+    /// The teams removed by the selection (without learners until `collect_garbage`) can't
+    /// have an atomic learner, so this violation is only kept for the teams of the trainer
+    /// and the extra teams.
+    pub fn validate_population<T: Register>(
+        &self,
+        brain: &Brain<T>,
+        extra_teams: &[usize],
+    ) -> Vec<Violation> {
+        let mut violations = self.validate(brain);
+        violations.retain(|violation| match violation {
+            Violation::NoAtomicLearner { team } => {
+                self.teams.contains(team) || extra_teams.contains(team)
+            }
+            _ => true,
+        });
+        violations
+    }
+
     /// Finalize populations and prepare for the next generation / epoch.
-    pub fn next_epoch(&mut self, brain: &Brain) {
+    pub fn next_epoch<T: Register>(&mut self, brain: &Brain<T>) {
         self.root_teams.clear();
         for team_idx in &self.teams {
             let team = brain.teams.get(*team_idx).unwrap();
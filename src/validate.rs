@@ -0,0 +1,344 @@
+//! # Validate
+//! Crate that permit to check the invariants of the brain of the Tangled Program Graph (TPG).
+//! A bug in a mutation can silently corrupt the graph, the validation return the list of the
+//! violations (instead of panicking) so the corruption is found where it happen:
+//!     - the index of the learners of the teams, of the actions of the learners and of the teams
+//!       of the actions are in range,
+//!     - each team has at least one learner with an atomic action,
+//!     - the back references (`in_teams` of the learners and `in_learners` of the teams)
+//!       match the references in both directions (see `Brain::link_team`),
+//!     - no root team is referenced by the action of a learner.
+//! The trainer can validate the brain after each evolution
+//! (`TrainerParams::validate_after_evolve`).
+
+#![deny(missing_docs)]
+#![deny(clippy::all)]
+#![deny(clippy::missing_docs_in_private_items)]
+
+use crate::{Brain, Register};
+use std::fmt;
+
+/// Enumeration to represent a broken invariant of the brain (with the index of the entities).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Violation {
+    /// The team use a learner that is not in the brain.
+    InvalidLearnerIndex {
+        /// The index of the team.
+        team: usize,
+        /// The index of the learner.
+        learner: usize,
+    },
+    /// The learner use an action that is not in the brain.
+    InvalidActionIndex {
+        /// The index of the learner.
+        learner: usize,
+        /// The index of the action.
+        action: usize,
+    },
+    /// The action use a team that is not in the brain.
+    InvalidTeamIndex {
+        /// The index of the action.
+        action: usize,
+        /// The index of the team.
+        team: usize,
+    },
+    /// The team has no learner with an atomic action (the decision can't end in the team).
+    NoAtomicLearner {
+        /// The index of the team.
+        team: usize,
+    },
+    /// The learner is referenced by a team (`in_teams`) that don't contain the learner.
+    InconsistentInTeam {
+        /// The index of the learner.
+        learner: usize,
+        /// The index of the team.
+        team: usize,
+    },
+    /// The team is referenced by a learner (`in_learners`) whose action don't use the team.
+    InconsistentInLearner {
+        /// The index of the team.
+        team: usize,
+        /// The index of the learner.
+        learner: usize,
+    },
+    /// The team contain the learner but the team is not in `in_teams` of the learner.
+    MissingInTeam {
+        /// The index of the learner.
+        learner: usize,
+        /// The index of the team.
+        team: usize,
+    },
+    /// The action of the learner use the team but the learner is not in `in_learners` of the
+    /// team.
+    MissingInLearner {
+        /// The index of the team.
+        team: usize,
+        /// The index of the learner.
+        learner: usize,
+    },
+    /// The root team is used by the action of a learner.
+    ReferencedRootTeam {
+        /// The index of the root team.
+        team: usize,
+        /// The index of the learner.
+        learner: usize,
+    },
+}
+
+impl fmt::Display for Violation {
+    /// Function to display the violation.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Violation::InvalidLearnerIndex { team, learner } => {
+                write!(f, "the team {} use the learner {} (out of range)", team, learner)
+            }
+            Violation::InvalidActionIndex { learner, action } => {
+                write!(f, "the learner {} use the action {} (out of range)", learner, action)
+            }
+            Violation::InvalidTeamIndex { action, team } => {
+                write!(f, "the action {} use the team {} (out of range)", action, team)
+            }
+            Violation::NoAtomicLearner { team } => {
+                write!(f, "the team {} has no learner with an atomic action", team)
+            }
+            Violation::InconsistentInTeam { learner, team } => {
+                write!(f, "the learner {} is referenced by the team {} without it", learner, team)
+            }
+            Violation::InconsistentInLearner { team, learner } => write!(
+                f,
+                "the team {} is referenced by the learner {} with an other action",
+                team, learner
+            ),
+            Violation::MissingInTeam { learner, team } => {
+                write!(f, "the learner {} don't reference the team {} with it", learner, team)
+            }
+            Violation::MissingInLearner { team, learner } => {
+                write!(f, "the team {} don't reference the learner {} using it", team, learner)
+            }
+            Violation::ReferencedRootTeam { team, learner } => {
+                write!(f, "the root team {} is used by the learner {}", team, learner)
+            }
+        }
+    }
+}
+
+impl<T: Register> Brain<T> {
+    /// Function to check the invariants of the brain with the root teams (index of the teams).
+    /// Return the list of the violations (empty if the brain is valid).
+    pub fn validate(&self, root_teams: &[usize]) -> Vec<Violation> {
+        let mut violations: Vec<Violation> = Vec::new();
+
+        // The index in range (the other checks skip the index out of range)
+        for (team_idx, team) in self.teams.iter().enumerate() {
+            for learner_idx in team.get_learners() {
+                if learner_idx >= self.learners.len() {
+                    violations.push(Violation::InvalidLearnerIndex {
+                        team: team_idx,
+                        learner: learner_idx,
+                    });
+                }
+            }
+        }
+        for (learner_idx, learner) in self.learners.iter().enumerate() {
+            if learner.get_idx_action() >= self.actions.len() {
+                violations.push(Violation::InvalidActionIndex {
+                    learner: learner_idx,
+                    action: learner.get_idx_action(),
+                });
+            }
+        }
+        for (action_idx, action) in self.actions.iter().enumerate() {
+            let team = action.get_action_team();
+            if let Some(team_idx) = team.filter(|idx| *idx >= self.teams.len()) {
+                violations.push(Violation::InvalidTeamIndex {
+                    action: action_idx,
+                    team: team_idx,
+                });
+            }
+        }
+
+        // The team used by the action of the learner (None if atomic or out of range)
+        let get_action_team = |learner_idx: usize| -> Option<usize> {
+            let action = self.actions.get(self.learners.get(learner_idx)?.get_idx_action())?;
+            action.get_action_team()
+        };
+        let is_atomic = |learner_idx: usize| -> bool {
+            self.learners
+                .get(learner_idx)
+                .and_then(|learner| self.actions.get(learner.get_idx_action()))
+                .is_some_and(|action| action.is_atomic())
+        };
+
+        // Each team can end the decision
+        for (team_idx, team) in self.teams.iter().enumerate() {
+            if !team.get_learners().into_iter().any(is_atomic) {
+                violations.push(Violation::NoAtomicLearner { team: team_idx });
+            }
+        }
+
+        // The back references match the references
+        for (learner_idx, learner) in self.learners.iter().enumerate() {
+            for team_idx in learner.get_in_teams() {
+                let in_team = self.teams.get(team_idx).is_some_and(|team| {
+                    team.get_learners().contains(&learner_idx)
+                });
+                if !in_team {
+                    violations.push(Violation::InconsistentInTeam {
+                        learner: learner_idx,
+                        team: team_idx,
+                    });
+                }
+            }
+        }
+        for (team_idx, team) in self.teams.iter().enumerate() {
+            for learner_idx in team.get_in_learners() {
+                if get_action_team(learner_idx) != Some(team_idx) {
+                    violations.push(Violation::InconsistentInLearner {
+                        team: team_idx,
+                        learner: learner_idx,
+                    });
+                }
+            }
+        }
+        for (team_idx, team) in self.teams.iter().enumerate() {
+            for learner_idx in team.get_learners() {
+                let missing = self
+                    .learners
+                    .get(learner_idx)
+                    .is_some_and(|learner| !learner.get_in_teams().contains(&team_idx));
+                if missing {
+                    violations.push(Violation::MissingInTeam {
+                        learner: learner_idx,
+                        team: team_idx,
+                    });
+                }
+            }
+        }
+        for learner_idx in 0..self.learners.len() {
+            if let Some(team_idx) = get_action_team(learner_idx) {
+                let missing = self
+                    .teams
+                    .get(team_idx)
+                    .is_some_and(|team| !team.get_in_learners().contains(&learner_idx));
+                if missing {
+                    violations.push(Violation::MissingInLearner {
+                        team: team_idx,
+                        learner: learner_idx,
+                    });
+                }
+            }
+        }
+
+        // The root teams are not used by a learner
+        for learner_idx in 0..self.learners.len() {
+            if let Some(team_idx) = get_action_team(learner_idx) {
+                if root_teams.contains(&team_idx) {
+                    violations.push(Violation::ReferencedRootTeam {
+                        team: team_idx,
+                        learner: learner_idx,
+                    });
+                }
+            }
+        }
+        violations
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::brain::brain_from_assembly;
+    use crate::{seed_rng, EvolveError, Learner, LearnerParams, Params, Trainer};
+
+    #[test]
+    fn test_validate() {
+        // The root team 0 call the team 1 with the learner 1
+        let programs: Vec<(&str, i32)> = (0..4).map(|code| ("r0 = r0 + in[0]", code)).collect();
+        let mut brain: Brain<i32> = brain_from_assembly(&programs, &[&[0, 1], &[2, 3]], &[(1, 1)]);
+        assert_eq!(brain.validate(&[0]), Vec::new(), "The valid brain has violations");
+
+        // Check the references to a root team and the back references
+        assert_eq!(
+            brain.validate(&[0, 1]),
+            vec![Violation::ReferencedRootTeam { team: 1, learner: 1 }],
+            "The root team referenced is not found"
+        );
+        brain.learners[3].set_in_teams(0);
+        assert_eq!(
+            brain.validate(&[0]),
+            vec![Violation::InconsistentInTeam { learner: 3, team: 0 }],
+            "The inconsistent back reference is not found"
+        );
+        brain.learners[3].remap_in_teams(&|idx| (idx != 0).then_some(idx));
+
+        // Check the missing back references (e.g. a learner added without `link_team`)
+        let mut action = brain.actions[1].clone();
+        action.set_id(4);
+        brain.add_action(action);
+        let program = brain.learners[0].get_program();
+        brain.add_learner(Learner::new(&mut LearnerParams::default(), program, 4, 2));
+        brain.teams[0].add_learner(4);
+        assert_eq!(
+            brain.validate(&[0]),
+            vec![
+                Violation::MissingInTeam { learner: 4, team: 0 },
+                Violation::MissingInLearner { team: 1, learner: 4 },
+            ],
+            "The missing back references are not found"
+        );
+        brain.link_team(0);
+        assert_eq!(brain.validate(&[0]), Vec::new(), "The linked brain has violations");
+
+        // Check the index out of range and the team without atomic learner
+        brain.teams[0].add_learner(7);
+        brain.actions[0].set_action_team(Some(5));
+        let violations = brain.validate(&[0]);
+        assert_eq!(
+            violations,
+            vec![
+                Violation::InvalidLearnerIndex { team: 0, learner: 7 },
+                Violation::InvalidTeamIndex { action: 0, team: 5 },
+                Violation::NoAtomicLearner { team: 0 },
+            ],
+            "The violations are incorrect"
+        );
+        assert_eq!(
+            violations[2].to_string(),
+            "the team 0 has no learner with an atomic action",
+            "The message of the violation is incorrect"
+        );
+    }
+
+    #[test]
+    fn test_validate_after_evolve() {
+        let mut params = Params::new("params_trainer.json".to_string());
+        params.trainer.validate_after_evolve = true;
+        params.trainer.team.learner.p_crossover = 1.0;
+        seed_rng(5);
+        let mut brain: Brain<f64> = Brain::default();
+        let mut trainer = Trainer::new(&mut params.trainer, 5);
+        trainer.set_up_actions(vec![0, 1, 2, 3, 4]);
+        trainer.initialize_populations(&mut brain);
+        let tasks = vec!["t1".to_string()];
+
+        // Check the back references are kept by the evolution (also the crossover learners)
+        for _ in 0..3 {
+            trainer.evolve(&mut brain, tasks.clone(), &Vec::new()).unwrap();
+        }
+
+        // Check the corruption is returned by the evolution (without panic)
+        let unknown_team = brain.teams.len() + 100;
+        brain.learners[0].set_in_teams(unknown_team);
+        let result = trainer.evolve(&mut brain, tasks, &Vec::new());
+        let expected = Violation::InconsistentInTeam {
+            learner: 0,
+            team: unknown_team,
+        };
+        match result {
+            Err(EvolveError::Corrupted(violations)) => {
+                assert!(violations.contains(&expected), "The violation is not returned")
+            }
+            _ => panic!("The corruption is not found by the evolution"),
+        }
+    }
+}